@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use {
+    anyhow::Error,
+    async_stream::try_stream,
+    axum::{
+        body::{Bytes, StreamBody},
+        extract::{Extension, Path},
+        http::{header, HeaderMap, HeaderValue, StatusCode},
+        response::{IntoResponse, Json},
+    },
+    axum_auth::AuthBearer,
+    futures_util::stream::BoxStream,
+    sea_orm::{entity::prelude::*, DatabaseConnection, QueryOrder, QuerySelect, QueryTrait},
+    tracing::{error, warn},
+};
+
+use super::{
+    error::{resp_err, ApiError},
+    groups::GroupId,
+    links::{visible_link, LinkId},
+    UserId,
+};
+
+use crate::{
+    database::entity::{grouped_links, links, snapshots},
+    identity,
+    snapshots::Snapshotter,
+};
+
+// what an archive is made of
+pub enum Scope {
+    Link(LinkId),
+    Group(GroupId),
+}
+
+type Archive = (
+    StatusCode,
+    HeaderMap,
+    StreamBody<BoxStream<'static, Result<Bytes, Error>>>,
+);
+
+// the newest snapshot of every link in the scope as one WARC file, the
+// archives of single snapshots can simply be put one after another
+pub async fn warc(
+    dbconn: Arc<DatabaseConnection>,
+    snapshotter: Option<Arc<Snapshotter>>,
+    user_id: UserId,
+    scope: Scope,
+) -> Result<Archive, (StatusCode, Json<ApiError<'static>>)> {
+    let snapshotter = snapshotter.ok_or_else(|| {
+        resp_err(
+            StatusCode::NOT_IMPLEMENTED,
+            "snapshots are not enabled on this server",
+        )
+    })?;
+    let db_err = |e: DbErr| {
+        error!("looking up snapshots to archive failed: {e}");
+        resp_err(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "database was unreachable",
+        )
+    };
+
+    let query = snapshots::Entity::find().filter(snapshots::Column::WarcKey.is_not_null());
+    let (query, filename) = match scope {
+        Scope::Link(link_id) => {
+            if visible_link(dbconn.clone(), &user_id, &link_id)
+                .await
+                .map_err(db_err)?
+                .is_none()
+            {
+                return Err(resp_err(StatusCode::NOT_FOUND, "link does not exist"));
+            }
+            (
+                query.filter(snapshots::Column::LinkId.eq(link_id.to_string())),
+                format!("link-{link_id}.warc.gz"),
+            )
+        }
+        // any member of a group is allowed to read it's links
+        Scope::Group(group_id) => {
+            if identity::group_role(dbconn.clone(), &user_id, &group_id)
+                .await
+                .map_err(db_err)?
+                .is_none()
+            {
+                return Err(resp_err(StatusCode::NOT_FOUND, "group does not exist"));
+            }
+            (
+                query.filter(
+                    snapshots::Column::LinkId.in_subquery(
+                        grouped_links::Entity::find()
+                            .select_only()
+                            .column(grouped_links::Column::LinkId)
+                            .filter(grouped_links::Column::GroupId.eq(group_id.to_string()))
+                            .filter(grouped_links::Column::DeletedAt.is_null())
+                            .filter(
+                                grouped_links::Column::LinkId.in_subquery(
+                                    links::Entity::find()
+                                        .select_only()
+                                        .column(links::Column::Id)
+                                        .filter(links::Column::DeletedAt.is_null())
+                                        .into_query(),
+                                ),
+                            )
+                            .into_query(),
+                    ),
+                ),
+                format!("group-{group_id}.warc.gz"),
+            )
+        }
+    };
+
+    let mut found = query
+        .order_by_asc(snapshots::Column::LinkId)
+        .order_by_desc(snapshots::Column::DateCreated)
+        .all(dbconn.as_ref())
+        .await
+        .map_err(db_err)?;
+    found.dedup_by(|a, b| a.link_id == b.link_id);
+    if found.is_empty() {
+        return Err(resp_err(
+            StatusCode::NOT_FOUND,
+            "there are no archived snapshots yet",
+        ));
+    }
+
+    // the status is already out once the body streams, a snapshot that went
+    // missing from storage in the meantime is left out
+    let archives: BoxStream<'static, Result<Bytes, Error>> = Box::pin(try_stream! {
+        for snapshot in found {
+            match snapshotter.load_warc(&snapshot).await {
+                Ok(Some(warc)) => yield Bytes::from(warc),
+                Ok(None) => warn!("archive of snapshot {} is missing from storage", snapshot.id),
+                Err(e) => {
+                    warn!("archive stream stopped partway through: {e}");
+                    Err(e)?;
+                }
+            }
+        }
+    });
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/gzip"),
+    );
+    if let Ok(disposition) = HeaderValue::from_str(&format!("attachment; filename=\"{filename}\""))
+    {
+        headers.insert(header::CONTENT_DISPOSITION, disposition);
+    }
+    Ok((StatusCode::OK, headers, StreamBody::new(archives)))
+}
+
+pub async fn link(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(snapshotter): Extension<Option<Arc<Snapshotter>>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(link_id): Path<LinkId>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    warc(dbconn, snapshotter, user_id, Scope::Link(link_id)).await
+}
+
+pub async fn group(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(snapshotter): Extension<Option<Arc<Snapshotter>>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(group_id): Path<GroupId>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    warc(dbconn, snapshotter, user_id, Scope::Group(group_id)).await
+}
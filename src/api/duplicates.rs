@@ -0,0 +1,498 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use {
+    anyhow::{Error, Result},
+    axum::{
+        extract::{Extension, Json},
+        http::StatusCode,
+        response::IntoResponse,
+    },
+    axum_auth::AuthBearer,
+    chrono::Utc,
+    sea_orm::{
+        entity::{prelude::*, Set},
+        sea_query::Expr,
+        DatabaseConnection, QueryOrder, QuerySelect, QueryTrait, TransactionTrait,
+    },
+    serde::{Deserialize, Serialize},
+    tracing::{error, warn},
+    ulid::Ulid,
+};
+
+use super::{
+    error::{resp_err, ApiError},
+    links::{Link, LinkId},
+    UserId,
+};
+
+use crate::{
+    database::entity::{grouped_links, link_health, links, notes, tagged_links},
+    identity,
+    jobs::Queue,
+    normalize::Normalizer,
+    webhooks::{self, Event},
+};
+
+// how much of their words two titles need to share to count as the same
+const TITLE_SIMILARITY: f64 = 0.8;
+// shorter titles, like "home" or "blog", say too little about the page
+const MIN_TITLE_WORDS: usize = 3;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    // the addresses are the same once normalized
+    SameAddress,
+    // one redirects to the other, or both to the same page
+    SameTarget,
+    SimilarTitle,
+}
+
+#[derive(Serialize)]
+pub struct Cluster {
+    pub reasons: Vec<Reason>,
+    // oldest first, which is also the one suggested to keep
+    pub links: Vec<Link>,
+}
+
+// keeps track of which links were found to belong together
+struct Clusters {
+    parents: Vec<usize>,
+    reasons: Vec<(usize, Reason)>,
+}
+
+impl Clusters {
+    fn new(size: usize) -> Self {
+        Self {
+            parents: (0..size).collect(),
+            reasons: Vec::new(),
+        }
+    }
+
+    fn root(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    fn join(&mut self, a: usize, b: usize, reason: Reason) {
+        let (a, b) = (self.root(a), self.root(b));
+        if a != b {
+            self.parents[b] = a;
+        }
+        self.reasons.push((a, reason));
+    }
+}
+
+// the words of a title, without the punctuation and case sites differ in
+fn title_words(title: &str) -> BTreeSet<String> {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+fn similarity(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    let shared = a.intersection(b).count();
+    shared as f64 / (a.len() + b.len() - shared) as f64
+}
+
+// links with similar titles, only links sharing one of their rarest words
+// are compared, which is enough to find every pair above the threshold
+fn similar_titles(titles: &[Option<BTreeSet<String>>], clusters: &mut Clusters) {
+    let mut frequency: HashMap<&str, usize> = HashMap::new();
+    for words in titles.iter().flatten() {
+        for word in words {
+            *frequency.entry(word).or_default() += 1;
+        }
+    }
+
+    let mut postings: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, words) in titles.iter().enumerate() {
+        let words = match words {
+            Some(words) => words,
+            None => continue,
+        };
+        let mut rarest: Vec<&str> = words.iter().map(String::as_str).collect();
+        rarest.sort_by_key(|w| (frequency[w], *w));
+        let prefix = words.len() - (TITLE_SIMILARITY * words.len() as f64).ceil() as usize + 1;
+
+        let mut candidates = BTreeSet::new();
+        for word in &rarest[..prefix] {
+            let posting = postings.entry(word).or_default();
+            candidates.extend(posting.iter().copied());
+            posting.push(i);
+        }
+        for j in candidates {
+            if let Some(other) = &titles[j] {
+                if similarity(words, other) >= TITLE_SIMILARITY {
+                    clusters.join(j, i, Reason::SimilarTitle);
+                }
+            }
+        }
+    }
+}
+
+// groups the user's links that are likely the same page, by their address,
+// where they redirect to and their title
+pub async fn find(
+    dbconn: &DatabaseConnection,
+    normalizer: &Normalizer,
+    user_id: &UserId,
+) -> Result<Vec<Cluster>, DbErr> {
+    let owned = links::Entity::find()
+        .filter(links::Column::CreatedBy.eq(user_id.clone()))
+        .filter(links::Column::DeletedAt.is_null());
+    let found = owned
+        .clone()
+        .order_by_asc(links::Column::DateCreated)
+        .all(dbconn)
+        .await?;
+    let targets: HashMap<String, String> = link_health::Entity::find()
+        .filter(link_health::Column::FinalUrl.is_not_null())
+        .filter(
+            link_health::Column::LinkId
+                .in_subquery(owned.select_only().column(links::Column::Id).into_query()),
+        )
+        .all(dbconn)
+        .await?
+        .into_iter()
+        .filter_map(|h| Some((h.link_id, h.final_url?)))
+        .collect();
+
+    let mut clusters = Clusters::new(found.len());
+
+    // a link is known by it's own address, and the one it redirects to
+    let mut addresses: HashMap<String, Vec<(usize, bool)>> = HashMap::new();
+    for (i, link) in found.iter().enumerate() {
+        if let Ok(url) = link.url.parse() {
+            addresses
+                .entry(normalizer.normalize(&url))
+                .or_default()
+                .push((i, false));
+        }
+        if let Some(Ok(target)) = targets.get(&link.id).map(|t| t.parse()) {
+            addresses
+                .entry(normalizer.normalize(&target))
+                .or_default()
+                .push((i, true));
+        }
+    }
+    for same in addresses.values().filter(|same| same.len() > 1) {
+        let reason = if same.iter().any(|(_, redirected)| *redirected) {
+            Reason::SameTarget
+        } else {
+            Reason::SameAddress
+        };
+        for (i, _) in &same[1..] {
+            if clusters.root(same[0].0) != clusters.root(*i) {
+                clusters.join(same[0].0, *i, reason);
+            }
+        }
+    }
+
+    let titles: Vec<Option<BTreeSet<String>>> = found
+        .iter()
+        .map(|link| {
+            link.title
+                .as_deref()
+                .map(title_words)
+                .filter(|words| words.len() >= MIN_TITLE_WORDS)
+        })
+        .collect();
+    similar_titles(&titles, &mut clusters);
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..found.len() {
+        let root = clusters.root(i);
+        members.entry(root).or_default().push(i);
+    }
+    let mut reasons: HashMap<usize, BTreeSet<Reason>> = HashMap::new();
+    for (i, reason) in clusters.reasons.clone() {
+        let root = clusters.root(i);
+        reasons.entry(root).or_default().insert(reason);
+    }
+
+    let mut found: Vec<Option<links::Model>> = found.into_iter().map(Some).collect();
+    let mut result = Vec::new();
+    for (root, members) in members.into_iter().filter(|(_, m)| m.len() > 1) {
+        let mut cluster = Cluster {
+            reasons: reasons
+                .remove(&root)
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            links: Vec::new(),
+        };
+        // the links were loaded oldest first, so they still are
+        for i in members {
+            if let Some(Ok(link)) = found[i].take().map(Link::from_inactive_der) {
+                cluster.links.push(link);
+            }
+        }
+        // links that couldn't be read leave nothing to compare with
+        if cluster.links.len() > 1 {
+            result.push(cluster);
+        }
+    }
+    result.sort_by(|a, b| {
+        b.links
+            .len()
+            .cmp(&a.links.len())
+            .then_with(|| a.links[0].date_created.cmp(&b.links[0].date_created))
+    });
+
+    Ok(result)
+}
+
+// folds the links into the one that's kept, which takes on their tags, groups
+// and notes along with the earliest date any of them was saved, the others are
+// deleted, nothing happens unless all of them belong to the user
+pub async fn merge(
+    dbconn: &DatabaseConnection,
+    queue: &Queue,
+    normalizer: &Normalizer,
+    user_id: &UserId,
+    into: &LinkId,
+    others: &[LinkId],
+) -> Result<Option<Link>, Error> {
+    let mut others: Vec<String> = others
+        .iter()
+        .filter(|id| *id != into)
+        .map(|id| id.to_string())
+        .collect();
+    others.sort();
+    others.dedup();
+
+    let txn = dbconn.begin().await?;
+    let owned = |ids: Vec<String>| {
+        links::Entity::find()
+            .filter(links::Column::Id.is_in(ids))
+            .filter(links::Column::CreatedBy.eq(user_id.clone()))
+            .filter(links::Column::DeletedAt.is_null())
+    };
+    let kept = match owned(vec![into.to_string()]).one(&txn).await? {
+        Some(kept) => kept,
+        None => return Ok(None),
+    };
+    let merged = owned(others.clone()).all(&txn).await?;
+    if merged.len() != others.len() {
+        return Ok(None);
+    }
+    let now: DateTimeWithTimeZone = Utc::now().into();
+
+    // tags the kept link doesn't have yet
+    let tagged: Vec<String> = tagged_links::Entity::find()
+        .filter(tagged_links::Column::LinkId.eq(kept.id.clone()))
+        .filter(tagged_links::Column::DeletedAt.is_null())
+        .all(&txn)
+        .await?
+        .into_iter()
+        .map(|t| t.tag_id)
+        .collect();
+    let mut added = Vec::new();
+    for tagging in tagged_links::Entity::find()
+        .filter(tagged_links::Column::LinkId.is_in(others.clone()))
+        .filter(tagged_links::Column::DeletedAt.is_null())
+        .all(&txn)
+        .await?
+    {
+        if tagged.contains(&tagging.tag_id) || added.contains(&tagging.tag_id) {
+            continue;
+        }
+        tagged_links::Entity::insert(tagged_links::ActiveModel {
+            id: Set(Ulid::new().to_string()),
+            link_id: Set(kept.id.clone()),
+            tag_id: Set(tagging.tag_id.clone()),
+            created_by: Set(tagging.created_by),
+            date_created: Set(tagging.date_created),
+            deleted_at: Set(None),
+        })
+        .exec(&txn)
+        .await?;
+        added.push(tagging.tag_id);
+    }
+
+    // groups, a group the kept link was taken out of before is brought back
+    // since every link is only ever in a group once
+    let grouped: HashMap<String, grouped_links::Model> = grouped_links::Entity::find()
+        .filter(grouped_links::Column::LinkId.eq(kept.id.clone()))
+        .all(&txn)
+        .await?
+        .into_iter()
+        .map(|g| (g.group_id.clone(), g))
+        .collect();
+    let mut added = Vec::new();
+    for grouping in grouped_links::Entity::find()
+        .filter(grouped_links::Column::LinkId.is_in(others.clone()))
+        .filter(grouped_links::Column::DeletedAt.is_null())
+        .all(&txn)
+        .await?
+    {
+        if added.contains(&grouping.group_id) {
+            continue;
+        }
+        match grouped.get(&grouping.group_id) {
+            Some(existing) if existing.deleted_at.is_none() => (),
+            Some(existing) => {
+                let mut der: grouped_links::ActiveModel = existing.clone().into();
+                der.deleted_at = Set(None);
+                der.update(&txn).await?;
+            }
+            None => {
+                grouped_links::Entity::insert(grouped_links::ActiveModel {
+                    id: Set(Ulid::new().to_string()),
+                    link_id: Set(kept.id.clone()),
+                    group_id: Set(grouping.group_id.clone()),
+                    name: Set(grouping.name),
+                    description: Set(grouping.description),
+                    created_by: Set(grouping.created_by),
+                    date_created: Set(grouping.date_created),
+                    deleted_at: Set(None),
+                })
+                .exec(&txn)
+                .await?;
+            }
+        }
+        added.push(grouping.group_id);
+    }
+
+    // notes are about the page, so they all move over
+    notes::Entity::update_many()
+        .col_expr(notes::Column::LinkId, Expr::value(kept.id.clone()))
+        .filter(notes::Column::LinkId.is_in(others.clone()))
+        .exec(&txn)
+        .await?;
+
+    links::Entity::update_many()
+        .col_expr(links::Column::DeletedAt, Expr::value(now))
+        .filter(links::Column::Id.is_in(others))
+        .exec(&txn)
+        .await?;
+
+    let mut der: links::ActiveModel = kept.clone().into();
+    if let Some(earliest) = merged.iter().map(|l| l.date_created).min() {
+        if earliest < kept.date_created {
+            der.date_created = Set(earliest);
+        }
+    }
+    if kept.title.is_none() {
+        der.title = Set(merged.iter().find_map(|l| l.title.clone()));
+    }
+    // the address may have been left out when it collided with one of the
+    // links that are gone now
+    if kept.normalized_url.is_none() {
+        if let Ok(url) = kept.url.parse() {
+            let normalized = normalizer.normalize(&url);
+            let taken = links::Entity::find()
+                .filter(links::Column::CreatedBy.eq(user_id.clone()))
+                .filter(links::Column::NormalizedUrl.eq(normalized.clone()))
+                .filter(links::Column::DeletedAt.is_null())
+                .one(&txn)
+                .await?
+                .is_some();
+            if !taken {
+                der.normalized_url = Set(Some(normalized));
+            }
+        }
+    }
+    der.modified_at = Set(Some(now));
+    let kept = der.update(&txn).await?;
+    txn.commit().await?;
+
+    // the merge went through, so receivers not hearing about it doesn't
+    // undo it
+    for mut link in merged
+        .into_iter()
+        .filter_map(|l| Link::from_inactive_der(l).ok())
+    {
+        link.deleted_at = Some(now);
+        if let Err(e) = webhooks::emit(dbconn, queue, Event::Deleted, &link, &[]).await {
+            warn!("webhooks for a merged link couldn't be queued: {e}");
+        }
+    }
+
+    Ok(Some(Link::from_der(kept.into())?))
+}
+
+pub async fn list(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(normalizer): Extension<Arc<Normalizer>>,
+    AuthBearer(auth_token): AuthBearer,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    match find(dbconn.as_ref(), &normalizer, &user_id).await {
+        Ok(clusters) => Ok((StatusCode::OK, Json(clusters))),
+        Err(e) => {
+            error!("looking for duplicate links failed: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MergeRequest {
+    // the link that's kept
+    into: LinkId,
+    links: Vec<LinkId>,
+}
+
+pub async fn merge_links(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(normalizer): Extension<Arc<Normalizer>>,
+    AuthBearer(auth_token): AuthBearer,
+    Json(req): Json<MergeRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    if req.links.iter().all(|id| *id == req.into) {
+        return Err(resp_err(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "no other links were given to merge",
+        ));
+    }
+    match merge(
+        dbconn.as_ref(),
+        &queue,
+        &normalizer,
+        &user_id,
+        &req.into,
+        &req.links,
+    )
+    .await
+    {
+        Ok(Some(link)) => Ok((StatusCode::OK, Json(link))),
+        Ok(None) => Err(resp_err(
+            StatusCode::NOT_FOUND,
+            "one of the links does not exist",
+        )),
+        Err(e) => {
+            error!("merging links failed: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ))
+        }
+    }
+}
@@ -0,0 +1,587 @@
+use std::{collections::HashMap, sync::Arc};
+
+use {
+    axum::{
+        body::StreamBody,
+        extract::{BodyStream, Extension, Query},
+        http::{header, HeaderMap, HeaderValue, StatusCode},
+        response::{IntoResponse, Json},
+    },
+    axum_auth::AuthBearer,
+    futures_util::StreamExt,
+    sea_orm::{
+        entity::prelude::*, Condition, DatabaseConnection, QueryOrder, QuerySelect, QueryTrait,
+    },
+    serde::Deserialize,
+    tracing::{error, warn},
+};
+
+use super::{
+    error::{resp_err, ApiError},
+    groups::GroupId,
+    links::{Link, LinkFilter, ReadingStatus},
+    UserId,
+};
+
+use crate::{
+    database::entity::{grouped_links, groups, links, notes, tagged_links, tags},
+    identity,
+    interchange::{
+        backup::{self, Restore},
+        citation, delimited,
+        import::Malformed,
+        markdown,
+        netscape::{self, Bookmark, Folder},
+        AnnotatedLink,
+    },
+    jobs::{Job, Queue},
+    snapshots::Snapshotter,
+    webhooks,
+};
+
+// the names of the tags and groups a set of links was filed under, and the
+// bodies of the notes written on them, keyed by link id
+#[derive(Default)]
+pub struct LinkExtras {
+    pub tags: HashMap<String, Vec<String>>,
+    pub groups: HashMap<String, Vec<groups::Model>>,
+    pub notes: HashMap<String, Vec<notes::Model>>,
+}
+
+impl LinkExtras {
+    // looks up the extras for every link matched by the query at once, notes
+    // other members of a group left public are only taken along when asked for,
+    // tags and groups only when they're the user's own or ones they're in
+    pub async fn fetch(
+        dbconn: Arc<DatabaseConnection>,
+        user_id: &UserId,
+        link_ids: Select<links::Entity>,
+        shared_notes: bool,
+    ) -> Result<Self, DbErr> {
+        let link_ids = link_ids
+            .select_only()
+            .column(links::Column::Id)
+            .into_query();
+        let mut extras = Self::default();
+        let roles = identity::group_roles(dbconn.clone(), user_id).await?;
+        let dbconn = dbconn.as_ref();
+
+        let taggings = tagged_links::Entity::find()
+            .filter(tagged_links::Column::LinkId.in_subquery(link_ids.clone()))
+            .filter(tagged_links::Column::DeletedAt.is_null())
+            .order_by_asc(tagged_links::Column::DateCreated)
+            .all(dbconn)
+            .await?;
+        // tags are personal, other members tagging a group link is their business
+        let tag_names: HashMap<String, String> = tags::Entity::find()
+            .filter(tags::Column::Id.is_in(taggings.iter().map(|t| t.tag_id.clone())))
+            .filter(tags::Column::CreatedBy.eq(user_id.clone()))
+            .filter(tags::Column::DeletedAt.is_null())
+            .all(dbconn)
+            .await?
+            .into_iter()
+            .map(|tag| (tag.id, tag.name))
+            .collect();
+        for tagging in taggings {
+            if let Some(name) = tag_names.get(&tagging.tag_id) {
+                extras
+                    .tags
+                    .entry(tagging.link_id)
+                    .or_default()
+                    .push(name.clone());
+            }
+        }
+
+        // a link can be in groups of other members the user isn't part of
+        let groupings = grouped_links::Entity::find()
+            .filter(grouped_links::Column::LinkId.in_subquery(link_ids.clone()))
+            .filter(grouped_links::Column::GroupId.is_in(roles.into_keys()))
+            .filter(grouped_links::Column::DeletedAt.is_null())
+            .order_by_asc(grouped_links::Column::DateCreated)
+            .all(dbconn)
+            .await?;
+        let found_groups: HashMap<String, groups::Model> = groups::Entity::find()
+            .filter(groups::Column::Id.is_in(groupings.iter().map(|g| g.group_id.clone())))
+            .filter(groups::Column::DeletedAt.is_null())
+            .all(dbconn)
+            .await?
+            .into_iter()
+            .map(|group| (group.id.clone(), group))
+            .collect();
+        for grouping in groupings {
+            if let Some(group) = found_groups.get(&grouping.group_id) {
+                extras
+                    .groups
+                    .entry(grouping.link_id)
+                    .or_default()
+                    .push(group.clone());
+            }
+        }
+
+        let mut authors = Condition::any().add(notes::Column::CreatedBy.eq(user_id.clone()));
+        if shared_notes {
+            authors = authors.add(notes::Column::Private.eq(false));
+        }
+        for note in notes::Entity::find()
+            .filter(notes::Column::LinkId.in_subquery(link_ids))
+            .filter(authors)
+            .filter(notes::Column::DeletedAt.is_null())
+            .order_by_asc(notes::Column::DateCreated)
+            .all(dbconn)
+            .await?
+        {
+            extras
+                .notes
+                .entry(note.link_id.clone())
+                .or_default()
+                .push(note);
+        }
+
+        Ok(extras)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BookmarksRequest {
+    include_archived: Option<bool>,
+    include_sensitive: Option<bool>,
+}
+
+pub async fn bookmarks(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Query(req): Query<BookmarksRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    // archived links are part of the backlog's history so they're kept by
+    // default, sensitive ones have to be asked for
+    let mut query = links::Entity::find()
+        .filter(links::Column::CreatedBy.eq(user_id.clone()))
+        .filter(links::Column::DeletedAt.is_null());
+    if !req.include_archived.unwrap_or(true) {
+        query = query.filter(links::Column::ArchivedAt.is_null());
+    }
+    if !req.include_sensitive.unwrap_or(false) {
+        query = query.filter(links::Column::Sensitive.eq(false));
+    }
+
+    let (found, mut extras) = match futures_util::try_join!(
+        query
+            .clone()
+            .order_by_asc(links::Column::DateCreated)
+            .all(dbconn.as_ref()),
+        // the file is the user's own, so only their own notes go in it
+        LinkExtras::fetch(dbconn.clone(), &user_id, query, false),
+    ) {
+        Ok(ok) => ok,
+        Err(e) => {
+            error!("fetching links to export from the database failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "couldn't retrieve links from database",
+            ));
+        }
+    };
+
+    // links filed under several groups show up in each of their folders
+    let mut folders: Vec<Folder> = Vec::new();
+    let mut folder_index: HashMap<String, usize> = HashMap::new();
+    let mut unfiled = Vec::new();
+    for der in found {
+        let link_groups = extras.groups.remove(&der.id).unwrap_or_default();
+        let tags = extras.tags.remove(&der.id).unwrap_or_default();
+        let note = extras.notes.remove(&der.id).map(|notes| {
+            notes
+                .into_iter()
+                .filter_map(|n| n.body)
+                .collect::<Vec<String>>()
+                .join("\n\n")
+        });
+        let link = match Link::from_inactive_der(der) {
+            Ok(ok) => ok,
+            Err(e) => {
+                error!("der link couldn't be casted into rust repr link: {e}");
+                return Err(resp_err(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "link in database couldn't be processed",
+                ));
+            }
+        };
+        let bookmark = |link: Link| Bookmark {
+            link,
+            tags: tags.clone(),
+            note: note.clone().filter(|n| !n.is_empty()),
+        };
+
+        if link_groups.is_empty() {
+            unfiled.push(bookmark(link));
+            continue;
+        }
+        for group in link_groups {
+            let index = *folder_index.entry(group.id.clone()).or_insert_with(|| {
+                folders.push(Folder {
+                    name: group.name.clone(),
+                    date_created: group.date_created,
+                    bookmarks: Vec::new(),
+                });
+                folders.len() - 1
+            });
+            folders[index].bookmarks.push(bookmark(link.clone()));
+        }
+    }
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static("attachment; filename=\"bookmarks.html\""),
+    );
+    Ok((StatusCode::OK, headers, netscape::write(&folders, &unfiled)))
+}
+
+pub async fn jsonl(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    let records = match backup::export(dbconn.clone(), user_id).await {
+        Ok(ok) => ok,
+        Err(e) => {
+            error!("looking up groups to export failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ));
+        }
+    };
+
+    // the status is already out once the body streams, so a failure partway
+    // through can only cut the export short
+    let records = records.map(|record| {
+        record.map_err(|e| {
+            warn!("export stream stopped partway through: {e}");
+            e
+        })
+    });
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static("attachment; filename=\"export.jsonl\""),
+    );
+    Ok((StatusCode::OK, headers, StreamBody::new(records)))
+}
+
+pub async fn restore(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(snapshotter): Extension<Option<Arc<Snapshotter>>>,
+    AuthBearer(auth_token): AuthBearer,
+    mut body: BodyStream,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    let mut restore = match Restore::new(dbconn.clone(), user_id).await {
+        Ok(ok) => ok,
+        Err(e) => {
+            error!("looking up groups to restore into failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ));
+        }
+    };
+
+    let mut result = Ok(());
+    while let Some(chunk) = body.next().await {
+        result = match chunk {
+            Ok(chunk) => restore.feed(&chunk).await,
+            Err(e) => Err(e.into()),
+        };
+        if result.is_err() {
+            break;
+        }
+    }
+    let result = match result {
+        Ok(()) => restore.finish().await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = queue.push_unique(Job::NormalizeUrls).await {
+        warn!("normalizing the addresses of restored links couldn't be queued: {e}");
+    }
+    queue
+        .push_saved(&restore.saved, snapshotter.is_some())
+        .await;
+    webhooks::emit_saved(dbconn.as_ref(), &queue, &restore.saved).await;
+
+    match result {
+        Ok(report) => Ok((StatusCode::OK, Json(report))),
+        Err(e) if e.is::<Malformed>() => {
+            warn!("restore stopped at a malformed record: {e}");
+            Err(resp_err(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "backup contains a malformed record, records before it were restored",
+            ))
+        }
+        Err(e) => {
+            error!("restore stopped partway through: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "restore stopped partway through, records before the failure were restored",
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sections {
+    Group,
+    Tag,
+}
+
+#[derive(Deserialize)]
+pub struct LinksRequest {
+    group: Option<GroupId>,
+    tag: Option<String>,
+    broken: Option<bool>,
+    q: Option<String>,
+    status: Option<ReadingStatus>,
+    snoozed: Option<bool>,
+    // what the markdown document is sectioned by
+    by: Option<Sections>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LinksFormat {
+    Csv,
+    Markdown,
+    Bibtex,
+    CslJson,
+}
+
+// writes every link matching the same filters as the link list, along with
+// their notes, as a document for reports and wiki pages or as a bibliography
+pub async fn links_document(
+    dbconn: Arc<DatabaseConnection>,
+    user_id: UserId,
+    req: LinksRequest,
+    format: LinksFormat,
+) -> Result<(StatusCode, HeaderMap, String), (StatusCode, Json<ApiError<'static>>)> {
+    let filter = LinkFilter {
+        group: req.group,
+        tag: req.tag,
+        broken: req.broken,
+        q: req.q,
+        status: req.status,
+        snoozed: req.snoozed,
+    };
+    let query = filter
+        .query(dbconn.clone(), &user_id)
+        .await?
+        .filter(links::Column::DeletedAt.is_null());
+
+    let (found, mut extras) = match futures_util::try_join!(
+        query
+            .clone()
+            .order_by_desc(links::Column::DateCreated)
+            .all(dbconn.as_ref()),
+        // documents cover group links too, along with what the group can read
+        LinkExtras::fetch(dbconn.clone(), &user_id, query, true),
+    ) {
+        Ok(ok) => ok,
+        Err(e) => {
+            error!("fetching links to export from the database failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "couldn't retrieve links from database",
+            ));
+        }
+    };
+
+    // convert der's to a rust struct
+    let mut annotated = Vec::new();
+    for der in found {
+        let groups = extras.groups.remove(&der.id).unwrap_or_default();
+        let tags = extras.tags.remove(&der.id).unwrap_or_default();
+        let notes = extras.notes.remove(&der.id).unwrap_or_default();
+        annotated.push(match Link::from_inactive_der(der) {
+            Ok(mut link) => {
+                link.created_by.clear();
+                AnnotatedLink {
+                    link,
+                    groups: groups.into_iter().map(|g| g.name).collect(),
+                    tags,
+                    notes: notes.into_iter().filter_map(|n| n.body).collect(),
+                }
+            }
+            Err(e) => {
+                error!("der link couldn't be casted into rust repr link: {e}");
+                return Err(resp_err(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "link in database couldn't be processed",
+                ));
+            }
+        });
+    }
+
+    let (document, content_type, disposition) = match format {
+        LinksFormat::Csv => match delimited::write(&annotated) {
+            Ok(ok) => (
+                ok,
+                "text/csv; charset=utf-8",
+                "attachment; filename=\"links.csv\"",
+            ),
+            Err(e) => {
+                error!("writing links as csv failed: {e}");
+                return Err(resp_err(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "links couldn't be written as csv",
+                ));
+            }
+        },
+        LinksFormat::Markdown => {
+            let mut title = String::from("Links");
+            if let Some(group_id) = filter.group {
+                match groups::Entity::find_by_id(group_id.to_string())
+                    .one(dbconn.as_ref())
+                    .await
+                {
+                    Ok(Some(group)) => title = format!("{title} in {}", group.name),
+                    Ok(None) => (),
+                    Err(e) => warn!("group name for an export couldn't be fetched: {e}"),
+                }
+            }
+            if let Some(tag) = &filter.tag {
+                title = format!("{title} tagged {tag}");
+            }
+
+            let document = match req.by.unwrap_or(Sections::Group) {
+                Sections::Group => markdown::write(&title, &annotated, |l| &l.groups, "Ungrouped"),
+                Sections::Tag => markdown::write(&title, &annotated, |l| &l.tags, "Untagged"),
+            };
+            (
+                document,
+                "text/markdown; charset=utf-8",
+                "attachment; filename=\"links.md\"",
+            )
+        }
+        LinksFormat::Bibtex => {
+            let links: Vec<Link> = annotated.into_iter().map(|a| a.link).collect();
+            (
+                citation::write_bibtex(&links),
+                "application/x-bibtex; charset=utf-8",
+                "attachment; filename=\"citations.bib\"",
+            )
+        }
+        LinksFormat::CslJson => {
+            let links: Vec<Link> = annotated.into_iter().map(|a| a.link).collect();
+            match serde_json::to_string_pretty(&citation::write_csl_json(&links)) {
+                Ok(ok) => (
+                    ok,
+                    "application/vnd.citationstyles.csl+json",
+                    "attachment; filename=\"citations.json\"",
+                ),
+                Err(e) => {
+                    error!("writing links as csl-json failed: {e}");
+                    return Err(resp_err(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "links couldn't be written as csl-json",
+                    ));
+                }
+            }
+        }
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static(disposition),
+    );
+    Ok((StatusCode::OK, headers, document))
+}
+
+pub async fn links_csv(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Query(req): Query<LinksRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    links_document(dbconn, user_id, req, LinksFormat::Csv).await
+}
+
+pub async fn links_markdown(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Query(req): Query<LinksRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    links_document(dbconn, user_id, req, LinksFormat::Markdown).await
+}
+
+pub async fn citations_bibtex(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Query(req): Query<LinksRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    links_document(dbconn, user_id, req, LinksFormat::Bibtex).await
+}
+
+pub async fn citations_csl_json(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Query(req): Query<LinksRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    links_document(dbconn, user_id, req, LinksFormat::CslJson).await
+}
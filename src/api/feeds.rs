@@ -0,0 +1,490 @@
+use std::{fmt, str::FromStr, sync::Arc};
+
+use {
+    anyhow::{anyhow, Error, Result},
+    axum::{
+        extract::{Extension, Path},
+        http::{header, HeaderMap, HeaderValue, StatusCode},
+        response::{IntoResponse, Json},
+    },
+    axum_auth::AuthBearer,
+    chrono::Utc,
+    sea_orm::{
+        entity::{prelude::*, Set},
+        DatabaseConnection, QueryOrder, QuerySelect, QueryTrait,
+    },
+    serde::{Deserialize, Serialize},
+    tracing::error,
+    ulid::Ulid,
+};
+
+use super::{
+    error::{resp_err, ApiError},
+    links::{self, Link},
+    UserId,
+};
+
+use crate::{
+    config::Configuration,
+    database::entity::{feed_tokens, groups, links as links_entity, tagged_links, tags, users},
+    identity,
+    interchange::feed::{self, FeedMeta},
+};
+
+// how many of the most recent links a feed carries
+const FEED_LENGTH: u64 = 50;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedScope {
+    Backlog,
+    Group,
+    Tag,
+}
+
+impl FeedScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeedScope::Backlog => "backlog",
+            FeedScope::Group => "group",
+            FeedScope::Tag => "tag",
+        }
+    }
+}
+
+impl fmt::Display for FeedScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for FeedScope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "backlog" => Ok(FeedScope::Backlog),
+            "group" => Ok(FeedScope::Group),
+            "tag" => Ok(FeedScope::Tag),
+            _ => Err(anyhow!("unknown feed scope: {s}")),
+        }
+    }
+}
+
+pub type FeedId = Ulid;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Feed {
+    pub id: FeedId,
+    pub created_by: UserId,
+    pub token: String,
+    pub scope: FeedScope,
+    pub target_id: Option<Ulid>,
+    pub include_sensitive: bool,
+    pub date_created: DateTimeWithTimeZone,
+    pub deleted_at: Option<DateTimeWithTimeZone>,
+}
+
+impl Feed {
+    pub fn new(
+        scope: FeedScope,
+        target_id: Option<Ulid>,
+        include_sensitive: bool,
+        created_at: DateTimeWithTimeZone,
+        owner: UserId,
+    ) -> Self {
+        Self {
+            id: FeedId::new(),
+            created_by: owner,
+            token: identity::secret_token(32),
+            scope,
+            target_id,
+            include_sensitive,
+            date_created: created_at,
+            deleted_at: None,
+        }
+    }
+
+    // converts the feed into it's database entity representation
+    pub fn into_der(self) -> feed_tokens::ActiveModel {
+        feed_tokens::ActiveModel {
+            id: Set(self.id.to_string()),
+            created_by: Set(self.created_by),
+            token: Set(self.token),
+            scope: Set(self.scope.to_string()),
+            target_id: Set(self.target_id.map(|t| t.to_string())),
+            include_sensitive: Set(self.include_sensitive),
+            date_created: Set(self.date_created),
+            deleted_at: Set(self.deleted_at),
+        }
+    }
+
+    pub fn from_inactive_der(der: feed_tokens::Model) -> Result<Self, Error> {
+        Ok(Self {
+            id: Ulid::from_string(&der.id)?,
+            created_by: der.created_by,
+            token: der.token,
+            scope: der.scope.parse()?,
+            target_id: match der.target_id {
+                Some(target_id) => Some(Ulid::from_string(&target_id)?),
+                None => None,
+            },
+            include_sensitive: der.include_sensitive,
+            date_created: der.date_created,
+            deleted_at: der.deleted_at,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SubmitRequest {
+    pub timestamptz: DateTimeWithTimeZone,
+    pub scope: FeedScope,
+    pub target: Option<Ulid>,
+    pub include_sensitive: Option<bool>,
+}
+
+pub async fn submit(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Json(req): Json<SubmitRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    // make sure the feed points at something the user is allowed to read
+    let readable = match (req.scope, req.target) {
+        (FeedScope::Backlog, None) => Ok(true),
+        (FeedScope::Group, Some(group_id)) => {
+            identity::group_role(dbconn.clone(), &user_id, &group_id)
+                .await
+                .map(|role| role.is_some())
+        }
+        (FeedScope::Tag, Some(tag_id)) => tags::Entity::find_by_id(tag_id.to_string())
+            .filter(tags::Column::CreatedBy.eq(user_id.clone()))
+            .filter(tags::Column::DeletedAt.is_null())
+            .one(dbconn.as_ref())
+            .await
+            .map(|tag| tag.is_some()),
+        (FeedScope::Backlog, Some(_)) => {
+            return Err(resp_err(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "backlog feeds don't take a target",
+            ))
+        }
+        (_, None) => {
+            return Err(resp_err(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "group and tag feeds need a target",
+            ))
+        }
+    };
+    match readable {
+        Ok(true) => (),
+        Ok(false) => {
+            return Err(resp_err(
+                StatusCode::NOT_FOUND,
+                "feed target does not exist",
+            ))
+        }
+        Err(e) => {
+            error!("looking up feed target failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ));
+        }
+    }
+
+    // commit to database
+    let feed = Feed::new(
+        req.scope,
+        req.target,
+        req.include_sensitive.unwrap_or_default(),
+        req.timestamptz,
+        user_id,
+    );
+    match feed_tokens::Entity::insert(feed.clone().into_der())
+        .exec(dbconn.as_ref())
+        .await
+    {
+        Ok(_) => (),
+        Err(e) => {
+            error!("tried committing feed to database: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ));
+        }
+    };
+
+    // return the response
+    Ok((StatusCode::CREATED, Json(feed)))
+}
+
+pub async fn list(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    let feeds = match feed_tokens::Entity::find()
+        .order_by_desc(feed_tokens::Column::DateCreated)
+        .filter(feed_tokens::Column::CreatedBy.eq(user_id))
+        .filter(feed_tokens::Column::DeletedAt.is_null())
+        .all(dbconn.as_ref())
+        .await
+    {
+        Ok(ok) => ok,
+        Err(e) => {
+            error!("fetching feeds from the database failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "couldn't retrieve feeds from database",
+            ));
+        }
+    };
+
+    // convert der's to a rust struct
+    let mut converted_feeds = Vec::new();
+    for feed in feeds {
+        converted_feeds.push(match Feed::from_inactive_der(feed) {
+            Ok(mut ok) => {
+                ok.created_by.clear();
+                ok
+            }
+            Err(e) => {
+                error!("der feed couldn't be casted into rust repr feed: {e}");
+                return Err(resp_err(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "feeds in database couldn't be processed",
+                ));
+            }
+        })
+    }
+
+    Ok((StatusCode::OK, Json(converted_feeds)))
+}
+
+pub async fn revoke(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(feed_id): Path<FeedId>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    let feed = match feed_tokens::Entity::find_by_id(feed_id.to_string())
+        .filter(feed_tokens::Column::CreatedBy.eq(user_id))
+        .filter(feed_tokens::Column::DeletedAt.is_null())
+        .one(dbconn.as_ref())
+        .await
+    {
+        Ok(Some(feed)) => feed,
+        Ok(None) => return Err(resp_err(StatusCode::NOT_FOUND, "feed does not exist")),
+        Err(e) => {
+            error!("fetching feed from the database failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ));
+        }
+    };
+
+    let mut feed: feed_tokens::ActiveModel = feed.into();
+    feed.deleted_at = Set(Some(Utc::now().into()));
+    match feed.update(dbconn.as_ref()).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => {
+            error!("tried revoking feed in database: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ))
+        }
+    }
+}
+
+// works out the feed's title and which links belong in it, or nothing when
+// the target is gone or the feed's owner lost access to it
+async fn feed_contents(
+    dbconn: Arc<DatabaseConnection>,
+    feed: &Feed,
+) -> Result<Option<(String, Select<links_entity::Entity>)>, DbErr> {
+    match (feed.scope, feed.target_id) {
+        (FeedScope::Backlog, _) => {
+            let owner = users::Entity::find_by_id(feed.created_by.clone())
+                .one(dbconn.as_ref())
+                .await?
+                .and_then(|u| u.login);
+            let title = match owner {
+                Some(login) => format!("{login}'s backlog"),
+                None => "Link Backlog".to_string(),
+            };
+            let query = links::visible_links(feed.created_by.clone(), None)
+                .filter(links_entity::Column::ArchivedAt.is_null());
+            Ok(Some((title, query)))
+        }
+        (FeedScope::Group, Some(group_id)) => {
+            if identity::group_role(dbconn.clone(), &feed.created_by, &group_id)
+                .await?
+                .is_none()
+            {
+                return Ok(None);
+            }
+            let group = groups::Entity::find_by_id(group_id.to_string())
+                .one(dbconn.as_ref())
+                .await?;
+            Ok(group.map(|g| {
+                (
+                    g.name,
+                    links::visible_links(feed.created_by.clone(), Some(group_id)),
+                )
+            }))
+        }
+        (FeedScope::Tag, Some(tag_id)) => {
+            let tag = tags::Entity::find_by_id(tag_id.to_string())
+                .filter(tags::Column::CreatedBy.eq(feed.created_by.clone()))
+                .filter(tags::Column::DeletedAt.is_null())
+                .one(dbconn.as_ref())
+                .await?;
+            Ok(tag.map(|t| {
+                let query = links_entity::Entity::find().filter(
+                    links_entity::Column::Id.in_subquery(
+                        tagged_links::Entity::find()
+                            .select_only()
+                            .column(tagged_links::Column::LinkId)
+                            .filter(tagged_links::Column::TagId.eq(t.id))
+                            .filter(tagged_links::Column::DeletedAt.is_null())
+                            .into_query(),
+                    ),
+                );
+                (format!("#{}", t.name), query)
+            }))
+        }
+        (_, None) => Ok(None),
+    }
+}
+
+pub async fn serve(
+    Extension(cfg): Extension<Arc<Configuration>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Path((token, file)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    type Writer = fn(&FeedMeta, &[Link]) -> String;
+    let (writer, content_type): (Writer, &'static str) = match file.as_str() {
+        "atom.xml" => (feed::atom, "application/atom+xml; charset=utf-8"),
+        "rss.xml" => (feed::rss, "application/rss+xml; charset=utf-8"),
+        "feed.json" => (feed::json, "application/feed+json; charset=utf-8"),
+        _ => return Err(resp_err(StatusCode::NOT_FOUND, "unknown feed format")),
+    };
+
+    // feed readers can't send bearer tokens, so the token in the url is all
+    // the authentication there is
+    let feed = match feed_tokens::Entity::find()
+        .filter(feed_tokens::Column::Token.eq(token.clone()))
+        .filter(feed_tokens::Column::DeletedAt.is_null())
+        .one(dbconn.as_ref())
+        .await
+    {
+        Ok(Some(der)) => match Feed::from_inactive_der(der) {
+            Ok(feed) => feed,
+            Err(e) => {
+                error!("der feed couldn't be casted into rust repr feed: {e}");
+                return Err(resp_err(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "feed in database couldn't be processed",
+                ));
+            }
+        },
+        Ok(None) => return Err(resp_err(StatusCode::NOT_FOUND, "feed does not exist")),
+        Err(e) => {
+            error!("fetching feed from the database failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ));
+        }
+    };
+
+    let (title, mut query) = match feed_contents(dbconn.clone(), &feed).await {
+        Ok(Some(contents)) => contents,
+        Ok(None) => return Err(resp_err(StatusCode::NOT_FOUND, "feed does not exist")),
+        Err(e) => {
+            error!("looking up feed target failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ));
+        }
+    };
+    query = query.filter(links_entity::Column::DeletedAt.is_null());
+    if !feed.include_sensitive {
+        query = query.filter(links_entity::Column::Sensitive.eq(false));
+    }
+
+    let links = match query
+        .order_by_desc(links_entity::Column::DateCreated)
+        .limit(FEED_LENGTH)
+        .all(dbconn.as_ref())
+        .await
+    {
+        Ok(ok) => ok,
+        Err(e) => {
+            error!("fetching feed links from the database failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "couldn't retrieve links from database",
+            ));
+        }
+    };
+
+    // convert der's to a rust struct
+    let mut converted_links = Vec::new();
+    for link in links {
+        converted_links.push(match Link::from_inactive_der(link) {
+            Ok(mut ok) => {
+                ok.created_by.clear();
+                ok
+            }
+            Err(e) => {
+                error!("der link couldn't be casted into rust repr link: {e}");
+                return Err(resp_err(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "link in database couldn't be processed",
+                ));
+            }
+        })
+    }
+
+    let meta = FeedMeta {
+        id: feed.id.to_string(),
+        title,
+        updated: converted_links
+            .iter()
+            .map(|l| l.modified_at.unwrap_or(l.date_created))
+            .max()
+            .unwrap_or_else(|| Utc::now().into()),
+        home_url: cfg.public_url("/").map(|u| u.to_string()),
+        feed_url: cfg
+            .public_url(&format!("feed/{token}/{file}"))
+            .map(|u| u.to_string()),
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    Ok((StatusCode::OK, headers, writer(&meta, &converted_links)))
+}
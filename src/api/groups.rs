@@ -19,6 +19,7 @@ use {
 
 use super::{
     error::{resp_err, ApiError},
+    members::Role,
     UserId,
 };
 
@@ -121,6 +122,14 @@ pub async fn submit(
     Ok((StatusCode::CREATED, Json(group)))
 }
 
+// a group as seen by one of it's members
+#[derive(Serialize, Clone)]
+pub struct MemberGroup {
+    #[serde(flatten)]
+    pub group: Group,
+    pub role: Role,
+}
+
 #[derive(Deserialize)]
 pub struct ListRequest {
     page: Option<usize>,
@@ -140,11 +149,23 @@ pub async fn list(
     };
     let user_id = apikey.created_by as UserId;
 
+    // shared groups are listed right alongside the ones the user owns
+    let roles = match identity::group_roles(dbconn.clone(), &user_id).await {
+        Ok(ok) => ok,
+        Err(e) => {
+            error!("fetching group memberships from the database failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "couldn't retrieve groups from database",
+            ));
+        }
+    };
+
     let page = req.page.unwrap_or(1);
     let groups_per_page = req.groups_per_page.unwrap_or(50);
     let paginator = groups::Entity::find()
         .order_by_desc(groups::Column::DateCreated)
-        .filter(groups::Column::CreatedBy.eq(user_id))
+        .filter(groups::Column::Id.is_in(roles.keys().cloned().collect::<Vec<_>>()))
         .paginate(dbconn.as_ref(), groups_per_page);
 
     let groups = match paginator.fetch_page(page - 1).await {
@@ -161,10 +182,14 @@ pub async fn list(
     // convert der's to a rust struct
     let mut converted_groups = Vec::new();
     for group in groups {
+        let role = roles[&group.id];
         converted_groups.push(match Group::from_inactive_der(group) {
             Ok(mut converted_group) => {
                 converted_group.created_by.clear();
-                converted_group
+                MemberGroup {
+                    group: converted_group,
+                    role,
+                }
             }
             Err(e) => {
                 error!("der group couldn't be casted into rust repr group: {e}");
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use {
+    axum::{
+        extract::{Extension, Multipart, Path, Query},
+        http::StatusCode,
+        response::{IntoResponse, Json, Response},
+    },
+    axum_auth::AuthBearer,
+    sea_orm::DatabaseConnection,
+    serde::{Deserialize, Serialize},
+    tracing::error,
+};
+
+use super::{
+    error::{resp_err, ApiError},
+    UserId,
+};
+
+use crate::{
+    identity,
+    interchange::import::{ImportFormat, ImportReport, ImportSession, ImportedLink, Malformed},
+    jobs::Queue,
+    normalize::Normalizer,
+    snapshots::Snapshotter,
+    webhooks,
+};
+
+#[derive(Deserialize)]
+pub struct SubmitRequest {
+    dry_run: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct PreviewResponse {
+    pub report: ImportReport,
+    pub links: Vec<ImportedLink>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn submit(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(normalizer): Extension<Arc<Normalizer>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(snapshotter): Extension<Option<Arc<Snapshotter>>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(format): Path<String>,
+    Query(req): Query<SubmitRequest>,
+    mut upload: Multipart,
+) -> Result<Response, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    let format: ImportFormat = match format.parse() {
+        Ok(ok) => ok,
+        Err(_) => return Err(resp_err(StatusCode::NOT_FOUND, "unknown import format")),
+    };
+
+    // the export is expected in a field called file, anything else is skipped
+    loop {
+        let field = match upload.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => {
+                return Err(resp_err(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "no file was uploaded",
+                ))
+            }
+            Err(e) => {
+                error!("reading multipart upload failed: {e}");
+                return Err(resp_err(StatusCode::BAD_REQUEST, "upload was malformed"));
+            }
+        };
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let dry_run = req.dry_run.unwrap_or(false);
+        let mut session = match dry_run {
+            true => ImportSession::dry_run(dbconn.clone(), normalizer.clone(), user_id),
+            false => ImportSession::new(dbconn.clone(), normalizer.clone(), user_id),
+        };
+        let result = session.import_stream(format.parser(), field).await;
+        // links saved before an import stopped are kept, and get the same
+        // work as the rest
+        queue
+            .push_saved(&session.saved, snapshotter.is_some())
+            .await;
+        webhooks::emit_saved(dbconn.as_ref(), &queue, &session.saved).await;
+        return match result {
+            Ok(report) if dry_run => Ok((
+                StatusCode::OK,
+                Json(PreviewResponse {
+                    report,
+                    links: session.previewed,
+                }),
+            )
+                .into_response()),
+            Ok(report) => Ok((StatusCode::OK, Json(report)).into_response()),
+            Err(e) if e.is::<Malformed>() => Err(resp_err(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                match dry_run {
+                    true => "file couldn't be read as the chosen format",
+                    false => "file couldn't be read as the chosen format, links before the problem were saved",
+                },
+            )),
+            Err(e) => {
+                error!("import stopped partway through: {e}");
+                Err(resp_err(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "import stopped partway through, links before the failure were saved",
+                ))
+            }
+        };
+    }
+}
@@ -3,15 +3,17 @@ use std::sync::Arc;
 use {
     anyhow::{Error, Result},
     axum::{
-        extract::{Extension, Query},
+        extract::{Extension, Path, Query},
         http::StatusCode,
         response::{IntoResponse, Json, Redirect},
     },
     axum_auth::AuthBearer,
+    chrono::Utc,
     reqwest::Url,
     sea_orm::{
         entity::{prelude::*, Set},
-        DatabaseConnection, QueryOrder,
+        sea_query::Expr,
+        Condition, DatabaseConnection, NotSet, QueryOrder, QuerySelect, QueryTrait,
     },
     serde::{Deserialize, Serialize},
     tower_cookies::{Cookie, Cookies},
@@ -21,17 +23,108 @@ use {
 
 use crate::{
     app::Sessions,
-    database::entity::{api_keys, grouped_links, links},
+    database::entity::{
+        api_keys, grouped_links, link_articles, link_checks, link_health, links, snapshots,
+        tagged_links, tags,
+    },
     identity,
+    interchange::citation::Citation,
+    jobs::{Job, Queue},
+    metadata::{Fetcher, Preview},
+    normalize::Normalizer,
+    reminders,
+    snapshots::Snapshotter,
+    webhooks::{self, Event},
 };
 
 use super::{
     error::{resp_err, ApiError},
+    groups::GroupId,
     UserId,
 };
 
 pub type LinkId = Ulid;
 
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadingStatus {
+    #[default]
+    Unread,
+    Reading,
+    Read,
+}
+
+impl ReadingStatus {
+    pub const ALL: [ReadingStatus; 3] = [
+        ReadingStatus::Unread,
+        ReadingStatus::Reading,
+        ReadingStatus::Read,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadingStatus::Unread => "unread",
+            ReadingStatus::Reading => "reading",
+            ReadingStatus::Read => "read",
+        }
+    }
+}
+
+// how far the owner of a link got with reading the page, the status follows
+// from the dates and is only there for whoever reads the link
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct Reading {
+    pub reading_status: ReadingStatus,
+    pub reading_started_at: Option<DateTimeWithTimeZone>,
+    pub read_at: Option<DateTimeWithTimeZone>,
+    // in percent of the page
+    pub reading_progress: Option<i16>,
+}
+
+impl Reading {
+    pub fn new(
+        started_at: Option<DateTimeWithTimeZone>,
+        read_at: Option<DateTimeWithTimeZone>,
+        progress: Option<i16>,
+    ) -> Self {
+        let status = match (started_at, read_at) {
+            (_, Some(_)) => ReadingStatus::Read,
+            (Some(_), None) => ReadingStatus::Reading,
+            (None, None) => ReadingStatus::Unread,
+        };
+        Self {
+            reading_status: status,
+            reading_started_at: started_at,
+            read_at,
+            reading_progress: progress,
+        }
+    }
+
+    // moves the link to another status, the dates of the earlier steps are
+    // kept, progress only counts while the page is being read
+    pub fn mark(
+        &self,
+        status: ReadingStatus,
+        progress: Option<i16>,
+        now: DateTimeWithTimeZone,
+    ) -> Self {
+        match status {
+            ReadingStatus::Unread => Self::new(None, None, None),
+            ReadingStatus::Reading => Self::new(
+                Some(self.reading_started_at.unwrap_or(now)),
+                None,
+                progress.or(self.reading_progress),
+            ),
+            ReadingStatus::Read => Self::new(
+                Some(self.reading_started_at.unwrap_or(now)),
+                Some(self.read_at.unwrap_or(now)),
+                Some(100),
+            ),
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 pub struct Link {
     pub id: LinkId,
@@ -43,6 +136,25 @@ pub struct Link {
     pub modified_at: Option<DateTimeWithTimeZone>,
     pub archived_at: Option<DateTimeWithTimeZone>,
     pub deleted_at: Option<DateTimeWithTimeZone>,
+    #[serde(flatten)]
+    pub citation: Citation,
+    #[serde(flatten)]
+    pub preview: Preview,
+    // the address in the form duplicates are recognized by
+    #[serde(default)]
+    pub normalized_url: Option<String>,
+    #[serde(flatten)]
+    pub reading: Reading,
+    // how often anyone went to the page through the application
+    #[serde(default)]
+    pub open_count: i32,
+    #[serde(default)]
+    pub last_opened_at: Option<DateTimeWithTimeZone>,
+    // the link is snoozed until then, and brought back up with a reminder
+    #[serde(default)]
+    pub remind_at: Option<DateTimeWithTimeZone>,
+    #[serde(default)]
+    pub reminded_at: Option<DateTimeWithTimeZone>,
 }
 
 impl Link {
@@ -63,6 +175,14 @@ impl Link {
             modified_at: None,
             archived_at: None,
             deleted_at: None,
+            citation: Citation::default(),
+            preview: Preview::default(),
+            normalized_url: None,
+            reading: Reading::default(),
+            open_count: 0,
+            last_opened_at: None,
+            remind_at: None,
+            reminded_at: None,
         }
     }
 
@@ -78,6 +198,29 @@ impl Link {
             modified_at: Set(self.modified_at),
             archived_at: Set(self.archived_at),
             deleted_at: Set(self.deleted_at),
+            authors: Set(if self.citation.authors.is_empty() {
+                None
+            } else {
+                Some(serde_json::Value::from(self.citation.authors))
+            }),
+            published_on: Set(self.citation.published_on),
+            site_name: Set(self.citation.site_name),
+            doi: Set(self.citation.doi),
+            description: Set(self.preview.description),
+            image_url: Set(self.preview.image_url),
+            canonical_url: Set(self.preview.canonical_url),
+            favicon_url: Set(self.preview.favicon_url),
+            fetched_at: Set(self.preview.fetched_at),
+            normalized_url: Set(self.normalized_url),
+            reading_started_at: Set(self.reading.reading_started_at),
+            read_at: Set(self.reading.read_at),
+            reading_progress: Set(self.reading.reading_progress),
+            // only ever counted up in place by opening the link, writing back
+            // what was read earlier would lose the opens in between
+            open_count: NotSet,
+            last_opened_at: NotSet,
+            remind_at: Set(self.remind_at),
+            reminded_at: Set(self.reminded_at),
         }
     }
 
@@ -92,6 +235,32 @@ impl Link {
             modified_at: der.modified_at.unwrap(),
             archived_at: der.archived_at.unwrap(),
             deleted_at: der.deleted_at.unwrap(),
+            citation: Citation {
+                authors: match der.authors.unwrap() {
+                    Some(authors) => serde_json::from_value(authors)?,
+                    None => Vec::new(),
+                },
+                published_on: der.published_on.unwrap(),
+                site_name: der.site_name.unwrap(),
+                doi: der.doi.unwrap(),
+            },
+            preview: Preview {
+                description: der.description.unwrap(),
+                image_url: der.image_url.unwrap(),
+                canonical_url: der.canonical_url.unwrap(),
+                favicon_url: der.favicon_url.unwrap(),
+                fetched_at: der.fetched_at.unwrap(),
+            },
+            normalized_url: der.normalized_url.unwrap(),
+            reading: Reading::new(
+                der.reading_started_at.unwrap(),
+                der.read_at.unwrap(),
+                der.reading_progress.unwrap(),
+            ),
+            open_count: der.open_count.unwrap(),
+            last_opened_at: der.last_opened_at.unwrap(),
+            remind_at: der.remind_at.unwrap(),
+            reminded_at: der.reminded_at.unwrap(),
         })
     }
 
@@ -106,10 +275,353 @@ impl Link {
             modified_at: der.modified_at,
             archived_at: der.archived_at,
             deleted_at: der.deleted_at,
+            citation: Citation {
+                authors: match der.authors {
+                    Some(authors) => serde_json::from_value(authors)?,
+                    None => Vec::new(),
+                },
+                published_on: der.published_on,
+                site_name: der.site_name,
+                doi: der.doi,
+            },
+            preview: Preview {
+                description: der.description,
+                image_url: der.image_url,
+                canonical_url: der.canonical_url,
+                favicon_url: der.favicon_url,
+                fetched_at: der.fetched_at,
+            },
+            normalized_url: der.normalized_url,
+            reading: Reading::new(der.reading_started_at, der.read_at, der.reading_progress),
+            open_count: der.open_count,
+            last_opened_at: der.last_opened_at,
+            remind_at: der.remind_at,
+            reminded_at: der.reminded_at,
         })
     }
 }
 
+// builds the query for the links a user can see, either their own or the ones
+// in a single group, callers have to check the user's role in the group first
+pub fn visible_links(user_id: UserId, group: Option<GroupId>) -> Select<links::Entity> {
+    match group {
+        Some(group_id) => links::Entity::find().filter(
+            links::Column::Id.in_subquery(
+                grouped_links::Entity::find()
+                    .select_only()
+                    .column(grouped_links::Column::LinkId)
+                    .filter(grouped_links::Column::GroupId.eq(group_id.to_string()))
+                    .filter(grouped_links::Column::DeletedAt.is_null())
+                    .into_query(),
+            ),
+        ),
+        None => links::Entity::find().filter(links::Column::CreatedBy.eq(user_id)),
+    }
+}
+
+// looks up a single link the user can see, either their own or one in any of
+// the groups they're a member of
+pub async fn visible_link(
+    dbconn: Arc<DatabaseConnection>,
+    user_id: &UserId,
+    link_id: &LinkId,
+) -> Result<Option<links::Model>, DbErr> {
+    let roles = identity::group_roles(dbconn.clone(), user_id).await?;
+    links::Entity::find_by_id(link_id.to_string())
+        .filter(links::Column::DeletedAt.is_null())
+        .filter(
+            Condition::any()
+                .add(links::Column::CreatedBy.eq(user_id.clone()))
+                .add(
+                    links::Column::Id.in_subquery(
+                        grouped_links::Entity::find()
+                            .select_only()
+                            .column(grouped_links::Column::LinkId)
+                            .filter(
+                                grouped_links::Column::GroupId
+                                    .is_in(roles.into_keys().collect::<Vec<_>>()),
+                            )
+                            .filter(grouped_links::Column::DeletedAt.is_null())
+                            .into_query(),
+                    ),
+                ),
+        )
+        .one(dbconn.as_ref())
+        .await
+}
+
+// the orders links can be listed in, newest first unless asked otherwise
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Sort {
+    Newest,
+    MostOpened,
+    // links that were never opened, then the ones opened longest ago
+    NeverOpened,
+}
+
+impl Sort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Sort::Newest => "newest",
+            Sort::MostOpened => "most_opened",
+            Sort::NeverOpened => "never_opened",
+        }
+    }
+
+    pub fn order(&self, query: Select<links::Entity>) -> Select<links::Entity> {
+        match self {
+            Sort::Newest => query,
+            Sort::MostOpened => query.order_by_desc(links::Column::OpenCount),
+            Sort::NeverOpened => query
+                .order_by_asc(links::Column::OpenCount)
+                .order_by_asc(links::Column::LastOpenedAt),
+        }
+        .order_by_desc(links::Column::DateCreated)
+    }
+}
+
+// the filters that listing links and exporting them have in common
+#[derive(Deserialize, Clone, Default)]
+pub struct LinkFilter {
+    pub group: Option<GroupId>,
+    pub tag: Option<String>,
+    // whether the last check found the link to be gone
+    pub broken: Option<bool>,
+    // words in the title, the address or the text of the article
+    pub q: Option<String>,
+    pub status: Option<ReadingStatus>,
+    // whether the link is waiting for it's reminder
+    pub snoozed: Option<bool>,
+}
+
+impl LinkFilter {
+    // narrows down the links the user can see, a group is only searched when
+    // the user is a member of it
+    pub async fn query(
+        &self,
+        dbconn: Arc<DatabaseConnection>,
+        user_id: &UserId,
+    ) -> Result<Select<links::Entity>, (StatusCode, Json<ApiError<'static>>)> {
+        // any member of a group is allowed to read it's links
+        if let Some(group_id) = self.group {
+            match identity::group_role(dbconn.clone(), user_id, &group_id).await {
+                Ok(Some(_)) => (),
+                Ok(None) => return Err(resp_err(StatusCode::NOT_FOUND, "group does not exist")),
+                Err(e) => {
+                    error!("looking up group role failed: {e}");
+                    return Err(resp_err(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "database was unreachable",
+                    ));
+                }
+            }
+        }
+
+        Ok(self.query_unchecked(user_id))
+    }
+
+    // the query without checking group membership, for callers that did so
+    // themselves
+    pub fn query_unchecked(&self, user_id: &UserId) -> Select<links::Entity> {
+        let mut query = visible_links(user_id.clone(), self.group);
+
+        // tags are personal, so it's the user's own tag of that name
+        if let Some(tag) = &self.tag {
+            query = query.filter(
+                links::Column::Id.in_subquery(
+                    tagged_links::Entity::find()
+                        .select_only()
+                        .column(tagged_links::Column::LinkId)
+                        .filter(
+                            tagged_links::Column::TagId.in_subquery(
+                                tags::Entity::find()
+                                    .select_only()
+                                    .column(tags::Column::Id)
+                                    .filter(tags::Column::CreatedBy.eq(user_id.clone()))
+                                    .filter(tags::Column::Name.eq(tag.clone()))
+                                    .filter(tags::Column::DeletedAt.is_null())
+                                    .into_query(),
+                            ),
+                        )
+                        .filter(tagged_links::Column::DeletedAt.is_null())
+                        .into_query(),
+                ),
+            );
+        }
+
+        // links that were never checked count as working
+        if let Some(broken) = self.broken {
+            let broken_links = link_health::Entity::find()
+                .select_only()
+                .column(link_health::Column::LinkId)
+                .filter(link_health::Column::Broken.eq(true))
+                .into_query();
+            query = query.filter(if broken {
+                links::Column::Id.in_subquery(broken_links)
+            } else {
+                links::Column::Id.not_in_subquery(broken_links)
+            });
+        }
+
+        query = match self.status {
+            Some(ReadingStatus::Unread) => query
+                .filter(links::Column::ReadingStartedAt.is_null())
+                .filter(links::Column::ReadAt.is_null()),
+            Some(ReadingStatus::Reading) => query
+                .filter(links::Column::ReadingStartedAt.is_not_null())
+                .filter(links::Column::ReadAt.is_null()),
+            Some(ReadingStatus::Read) => query.filter(links::Column::ReadAt.is_not_null()),
+            None => query,
+        };
+
+        if let Some(snoozed) = self.snoozed {
+            let waiting = Expr::cust("\"links\".\"remind_at\" > NOW()");
+            query = query.filter(if snoozed {
+                Condition::all().add(waiting)
+            } else {
+                Condition::any()
+                    .add(links::Column::RemindAt.is_null())
+                    .add(Expr::cust("\"links\".\"remind_at\" <= NOW()"))
+            });
+        }
+
+        // titles and addresses are matched as they're typed, article text by
+        // it's words, which understands quotes, "or" and leaving words out
+        if let Some(q) = self.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+            let pattern = format!(
+                "%{}%",
+                q.replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            );
+            query = query.filter(
+                Condition::any()
+                    .add(Expr::cust_with_values(
+                        "\"links\".\"title\" ILIKE ?",
+                        vec![pattern.clone()],
+                    ))
+                    .add(Expr::cust_with_values(
+                        "\"links\".\"url\" ILIKE ?",
+                        vec![pattern],
+                    ))
+                    .add(
+                        links::Column::Id.in_subquery(
+                            link_articles::Entity::find()
+                                .select_only()
+                                .column(link_articles::Column::LinkId)
+                                .filter(Expr::cust_with_values(
+                                    "\"link_articles\".\"search\" @@ websearch_to_tsquery('simple', ?)",
+                                    vec![q.to_string()],
+                                ))
+                                .into_query(),
+                        ),
+                    ),
+            );
+        }
+
+        query
+    }
+}
+
+// files the link under the groups and tags, the ones it's filed under already
+// are left as they are and ones it was taken out of are brought back, gives
+// back the names of the tags that were put on the link
+async fn file_link(
+    dbconn: &DatabaseConnection,
+    user_id: &UserId,
+    link_id: &str,
+    groups: &[GroupId],
+    tags: &[String],
+    date: DateTimeWithTimeZone,
+) -> Result<Vec<String>, DbErr> {
+    for group_id in groups {
+        match grouped_links::Entity::find()
+            .filter(grouped_links::Column::LinkId.eq(link_id))
+            .filter(grouped_links::Column::GroupId.eq(group_id.to_string()))
+            .one(dbconn)
+            .await?
+        {
+            Some(grouped) if grouped.deleted_at.is_none() => (),
+            Some(grouped) => {
+                let mut der: grouped_links::ActiveModel = grouped.into();
+                der.deleted_at = Set(None);
+                der.update(dbconn).await?;
+            }
+            None => {
+                grouped_links::Entity::insert(grouped_links::ActiveModel {
+                    id: Set(Ulid::new().to_string()),
+                    link_id: Set(link_id.to_string()),
+                    group_id: Set(group_id.to_string()),
+                    name: Set(String::new()),
+                    description: Set(None),
+                    created_by: Set(user_id.clone()),
+                    date_created: Set(date),
+                    deleted_at: Set(None),
+                })
+                .exec(dbconn)
+                .await?;
+            }
+        }
+    }
+
+    // tags are personal, one that doesn't exist yet is made
+    let mut seen = Vec::new();
+    let mut added = Vec::new();
+    for name in tags.iter().map(|t| t.trim()) {
+        if name.is_empty() || seen.contains(&name) {
+            continue;
+        }
+        seen.push(name);
+
+        let tag_id = match tags::Entity::find()
+            .filter(tags::Column::CreatedBy.eq(user_id.clone()))
+            .filter(tags::Column::Name.eq(name))
+            .filter(tags::Column::DeletedAt.is_null())
+            .one(dbconn)
+            .await?
+        {
+            Some(tag) => tag.id,
+            None => {
+                let id = Ulid::new().to_string();
+                tags::Entity::insert(tags::ActiveModel {
+                    id: Set(id.clone()),
+                    name: Set(name.to_string()),
+                    description: Set(None),
+                    created_by: Set(user_id.clone()),
+                    date_created: Set(date),
+                    modified_at: Set(None),
+                    deleted_at: Set(None),
+                })
+                .exec(dbconn)
+                .await?;
+                id
+            }
+        };
+        let tagged = tagged_links::Entity::find()
+            .filter(tagged_links::Column::LinkId.eq(link_id))
+            .filter(tagged_links::Column::TagId.eq(tag_id.clone()))
+            .filter(tagged_links::Column::DeletedAt.is_null())
+            .one(dbconn)
+            .await?;
+        if tagged.is_none() {
+            tagged_links::Entity::insert(tagged_links::ActiveModel {
+                id: Set(Ulid::new().to_string()),
+                link_id: Set(link_id.to_string()),
+                tag_id: Set(tag_id),
+                created_by: Set(user_id.clone()),
+                date_created: Set(date),
+                deleted_at: Set(None),
+            })
+            .exec(dbconn)
+            .await?;
+            added.push(name.to_string());
+        }
+    }
+
+    Ok(added)
+}
+
 #[derive(Deserialize)]
 pub struct SubmitRequest {
     timestamptz: DateTimeWithTimeZone,
@@ -117,6 +629,10 @@ pub struct SubmitRequest {
     groups: Option<Vec<Ulid>>, // Ulid -> GroupId
     title: Option<String>,
     sensitive: Option<bool>,
+    tags: Option<Vec<String>>,
+    // when the link was saved before, it's filed under the groups and tags
+    // too instead of being left as it was
+    merge: Option<bool>,
 }
 
 #[derive(Serialize)]
@@ -129,9 +645,83 @@ pub struct SubmitResponse {
     pub date_created: DateTimeWithTimeZone,
 }
 
+// looks up the link a page was saved as before, when merging is asked for
+// it's filed under the groups and tags of the submission too
+async fn saved_before(
+    dbconn: &DatabaseConnection,
+    queue: &Queue,
+    user_id: &UserId,
+    normalized_url: &str,
+    req: &SubmitRequest,
+) -> Result<Option<Link>, (StatusCode, Json<ApiError<'static>>)> {
+    let existing = match links::Entity::find()
+        .filter(links::Column::CreatedBy.eq(user_id.clone()))
+        .filter(links::Column::NormalizedUrl.eq(normalized_url))
+        .filter(links::Column::DeletedAt.is_null())
+        .one(dbconn)
+        .await
+    {
+        Ok(existing) => existing,
+        Err(e) => {
+            error!("looking up links saved under the same address failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ));
+        }
+    };
+    let existing = match existing {
+        Some(existing) => existing,
+        None => return Ok(None),
+    };
+    let added = if req.merge.unwrap_or_default() {
+        match file_link(
+            dbconn,
+            user_id,
+            &existing.id,
+            req.groups.as_deref().unwrap_or_default(),
+            req.tags.as_deref().unwrap_or_default(),
+            req.timestamptz,
+        )
+        .await
+        {
+            Ok(added) => added,
+            Err(e) => {
+                error!("could not merge groups and tags into a saved link: {e}");
+                return Err(resp_err(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "database was unreachable",
+                ));
+            }
+        }
+    } else {
+        Vec::new()
+    };
+    let link = match Link::from_inactive_der(existing) {
+        Ok(link) => link,
+        Err(e) => {
+            error!("der link couldn't be casted into rust repr link: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "link saved before was corrupted",
+            ));
+        }
+    };
+    if !added.is_empty() {
+        if let Err(e) = webhooks::emit(dbconn, queue, Event::Tagged, &link, &added).await {
+            warn!("webhooks for a tagged link couldn't be queued: {e}");
+        }
+    }
+
+    Ok(Some(link))
+}
+
 pub async fn submit(
     Extension(sessions): Extension<Arc<Sessions>>,
     Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(snapshotter): Extension<Option<Arc<Snapshotter>>>,
+    Extension(normalizer): Extension<Arc<Normalizer>>,
     AuthBearer(auth_token): AuthBearer,
     Json(req): Json<SubmitRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
@@ -143,7 +733,7 @@ pub async fn submit(
     let user_id = apikey.created_by as UserId;
 
     // try parsing url
-    let url = match req.link.parse() {
+    let url: Url = match req.link.parse() {
         Ok(ok) => ok,
         Err(e) => {
             return Err(resp_err(
@@ -152,21 +742,71 @@ pub async fn submit(
             ))
         }
     };
+    // links end up on shared pages, where javascript: or data: would run
+    // for whoever clicks them
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(resp_err(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "only http and https links can be saved",
+        ));
+    }
+
+    // only editors and owners may put links into a group
+    for grp in req.groups.iter().flatten() {
+        match identity::group_role(dbconn.clone(), &user_id, grp).await {
+            Ok(Some(role)) if role.can_edit() => (),
+            Ok(Some(_)) => {
+                return Err(resp_err(
+                    StatusCode::FORBIDDEN,
+                    "your role does not allow adding links to one of the groups",
+                ))
+            }
+            Ok(None) => {
+                return Err(resp_err(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "one of the groups does not exist",
+                ))
+            }
+            Err(e) => {
+                error!("looking up group role failed: {e}");
+                return Err(resp_err(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "database was unreachable",
+                ));
+            }
+        }
+    }
+
+    // the same page saved again hands back the link it was saved as
+    let normalized_url = normalizer.normalize(&url);
+    if let Some(link) =
+        saved_before(dbconn.as_ref(), &queue, &user_id, &normalized_url, &req).await?
+    {
+        return Ok((StatusCode::OK, Json(link)));
+    }
 
     // commit to database
-    let link = Link::new(
+    let mut link = Link::new(
         url,
         req.timestamptz,
-        req.title,
+        req.title.clone(),
         req.sensitive.unwrap_or_default(),
         user_id.clone(),
     );
+    link.normalized_url = Some(normalized_url.clone());
     match links::Entity::insert(link.clone().into_der())
         .exec(dbconn.as_ref())
         .await
     {
         Ok(l) => l,
         Err(e) => {
+            // the same page submitted at the same time got saved first, the
+            // unique address index turned this one away
+            if let Ok(Some(link)) =
+                saved_before(dbconn.as_ref(), &queue, &user_id, &normalized_url, &req).await
+            {
+                return Ok((StatusCode::OK, Json(link)));
+            }
             error!("tried committing link to database: {e}");
             return Err(resp_err(
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -174,37 +814,29 @@ pub async fn submit(
             ));
         }
     };
+    // a missing title or preview shouldn't fail the submission
+    queue.push_saved(&[link.id], snapshotter.is_some()).await;
 
-    match req.groups {
-        Some(grps) => {
-            // TODO: check if the group exists first before attempting to assign to it
-            for grp in grps {
-                let grouped_link = grouped_links::ActiveModel {
-                    id: Set(Ulid::new().to_string()),
-                    link_id: Set(link.id.to_string()),
-                    group_id: Set(grp.to_string()),
-                    name: Set(String::new()),
-                    description: Set(None),
-                    created_by: Set(user_id.clone()),
-                    date_created: Set(req.timestamptz),
-                    deleted_at: Set(None),
-                };
-                match grouped_links::Entity::insert(grouped_link)
-                    .exec(dbconn.clone().as_ref())
-                    .await
-                {
-                    Ok(_) => (),
-                    Err(e) => {
-                        error!("could not assign the link to a group: {e}");
-                        return Err(resp_err(
-                            StatusCode::MULTI_STATUS,
-                            "link was saved, but was not assigned to all the groups.",
-                        ));
-                    }
-                }
-            }
-        }
-        None => (),
+    let filed = file_link(
+        dbconn.as_ref(),
+        &user_id,
+        &link.id.to_string(),
+        req.groups.as_deref().unwrap_or_default(),
+        req.tags.as_deref().unwrap_or_default(),
+        req.timestamptz,
+    )
+    .await;
+    // the link is saved either way, receivers get whichever tags made it
+    let tags = filed.as_deref().unwrap_or_default();
+    if let Err(e) = webhooks::emit(dbconn.as_ref(), &queue, Event::Saved, &link, tags).await {
+        warn!("webhooks for a new link couldn't be queued: {e}");
+    }
+    if let Err(e) = filed {
+        error!("could not assign the link to it's groups and tags: {e}");
+        return Err(resp_err(
+            StatusCode::MULTI_STATUS,
+            "link was saved, but was not assigned to all the groups and tags.",
+        ));
     }
 
     // return the response
@@ -215,6 +847,13 @@ pub async fn submit(
 pub struct ListRequest {
     page: Option<usize>,
     links_per_page: Option<usize>,
+    group: Option<GroupId>,
+    tag: Option<String>,
+    broken: Option<bool>,
+    q: Option<String>,
+    status: Option<ReadingStatus>,
+    snoozed: Option<bool>,
+    sort: Option<Sort>,
 }
 
 pub async fn list(
@@ -230,11 +869,22 @@ pub async fn list(
     };
     let user_id = apikey.created_by as UserId;
 
+    let filter = LinkFilter {
+        group: req.group,
+        tag: req.tag,
+        broken: req.broken,
+        q: req.q,
+        status: req.status,
+        snoozed: req.snoozed,
+    };
+    let query = filter.query(dbconn.clone(), &user_id).await?;
+
     let page = req.page.unwrap_or(1);
     let links_per_page = req.links_per_page.unwrap_or(50);
-    let paginator = links::Entity::find()
-        .order_by_desc(links::Column::DateCreated)
-        .filter(links::Column::CreatedBy.eq(user_id))
+    let paginator = req
+        .sort
+        .unwrap_or(Sort::Newest)
+        .order(query)
         .paginate(dbconn.as_ref(), links_per_page);
 
     let links = match paginator.fetch_page(page - 1).await {
@@ -268,3 +918,424 @@ pub async fn list(
 
     Ok((StatusCode::OK, Json(converted_links)))
 }
+
+// looks up a link the user saved themselves, citations belong to whoever
+// saved the link
+async fn find_own_link(
+    dbconn: &DatabaseConnection,
+    user_id: &UserId,
+    link_id: &LinkId,
+) -> Result<Link, (StatusCode, Json<ApiError<'static>>)> {
+    match links::Entity::find_by_id(link_id.to_string())
+        .filter(links::Column::CreatedBy.eq(user_id.clone()))
+        .filter(links::Column::DeletedAt.is_null())
+        .one(dbconn)
+        .await
+    {
+        Ok(Some(der)) => Link::from_inactive_der(der).map_err(|e| {
+            error!("der link couldn't be casted into rust repr link: {e}");
+            resp_err(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "link in database couldn't be processed",
+            )
+        }),
+        Ok(None) => Err(resp_err(StatusCode::NOT_FOUND, "link does not exist")),
+        Err(e) => {
+            error!("fetching a link from the database failed: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ))
+        }
+    }
+}
+
+async fn save_citation(
+    dbconn: &DatabaseConnection,
+    mut link: Link,
+    citation: Citation,
+) -> Result<Link, (StatusCode, Json<ApiError<'static>>)> {
+    link.citation = citation;
+    link.modified_at = Some(Utc::now().into());
+
+    // only the citation columns, the link may have been read before a page
+    // was fetched and changed in the meantime
+    let der = link.clone().into_der();
+    match links::Entity::update_many()
+        .col_expr(links::Column::Authors, Expr::value(der.authors.unwrap()))
+        .col_expr(
+            links::Column::PublishedOn,
+            Expr::value(der.published_on.unwrap()),
+        )
+        .col_expr(links::Column::SiteName, Expr::value(der.site_name.unwrap()))
+        .col_expr(links::Column::Doi, Expr::value(der.doi.unwrap()))
+        .col_expr(links::Column::ModifiedAt, Expr::value(link.modified_at))
+        .filter(links::Column::Id.eq(link.id.to_string()))
+        .exec(dbconn)
+        .await
+    {
+        Ok(_) => {
+            link.created_by.clear();
+            Ok(link)
+        }
+        Err(e) => {
+            error!("tried updating link citation in database: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ))
+        }
+    }
+}
+
+// replaces the citation fields of a link with ones entered by hand
+pub async fn cite(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(link_id): Path<LinkId>,
+    Json(req): Json<Citation>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    let citation = match req.normalized() {
+        Ok(ok) => ok,
+        Err(e) => {
+            debug!("citation was rejected: {e}");
+            return Err(resp_err(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "publication date or doi is not valid",
+            ));
+        }
+    };
+    let link = find_own_link(dbconn.as_ref(), &user_id, &link_id).await?;
+    let link = save_citation(dbconn.as_ref(), link, citation).await?;
+
+    Ok((StatusCode::OK, Json(link)))
+}
+
+// reads the citation fields from the page the link points to, fields that
+// were already filled in are left alone
+pub async fn fetch_citation(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(fetcher): Extension<Arc<Fetcher>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(link_id): Path<LinkId>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    let link = find_own_link(dbconn.as_ref(), &user_id, &link_id).await?;
+    let found = match fetcher.fetch(&link.url).await {
+        Ok(ok) => ok.citation,
+        Err(e) => {
+            info!("page for a citation couldn't be fetched: {e}");
+            return Err(resp_err(
+                StatusCode::BAD_GATEWAY,
+                "the page the link points to couldn't be fetched",
+            ));
+        }
+    };
+
+    let mut citation = link.citation.clone();
+    citation.fill(found);
+    let link = save_citation(dbconn.as_ref(), link, citation).await?;
+
+    Ok((StatusCode::OK, Json(link)))
+}
+
+#[derive(Deserialize)]
+pub struct ReadingRequest {
+    status: ReadingStatus,
+    progress: Option<i16>,
+}
+
+// moves a link between unread, reading and read
+pub async fn reading(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(link_id): Path<LinkId>,
+    Json(req): Json<ReadingRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    if matches!(req.progress, Some(p) if !(0..=100).contains(&p)) {
+        return Err(resp_err(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "progress has to be between 0 and 100",
+        ));
+    }
+    let mut link = find_own_link(dbconn.as_ref(), &user_id, &link_id).await?;
+    let now = Utc::now().into();
+    link.reading = link.reading.mark(req.status, req.progress, now);
+    link.modified_at = Some(now);
+
+    // only the reading columns, the rest of the row may have changed since
+    match links::Entity::update_many()
+        .col_expr(
+            links::Column::ReadingStartedAt,
+            Expr::value(link.reading.reading_started_at),
+        )
+        .col_expr(links::Column::ReadAt, Expr::value(link.reading.read_at))
+        .col_expr(
+            links::Column::ReadingProgress,
+            Expr::value(link.reading.reading_progress),
+        )
+        .col_expr(links::Column::ModifiedAt, Expr::value(link.modified_at))
+        .filter(links::Column::Id.eq(link.id.to_string()))
+        .exec(dbconn.as_ref())
+        .await
+    {
+        Ok(_) => {
+            link.created_by.clear();
+            Ok((StatusCode::OK, Json(link)))
+        }
+        Err(e) => {
+            error!("tried updating the reading status of a link: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ArchiveRequest {
+    archived: bool,
+}
+
+// puts a link away once it's done with, or brings it back
+pub async fn archive(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(link_id): Path<LinkId>,
+    Json(req): Json<ArchiveRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    let mut link = find_own_link(dbconn.as_ref(), &user_id, &link_id).await?;
+    if req.archived == link.archived_at.is_some() {
+        link.created_by.clear();
+        return Ok((StatusCode::OK, Json(link)));
+    }
+    let now = Utc::now().into();
+    link.archived_at = if req.archived { Some(now) } else { None };
+    link.modified_at = Some(now);
+
+    if let Err(e) = links::Entity::update_many()
+        .col_expr(links::Column::ArchivedAt, Expr::value(link.archived_at))
+        .col_expr(links::Column::ModifiedAt, Expr::value(link.modified_at))
+        .filter(links::Column::Id.eq(link.id.to_string()))
+        .exec(dbconn.as_ref())
+        .await
+    {
+        error!("tried archiving a link: {e}");
+        return Err(resp_err(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "database was unreachable",
+        ));
+    }
+    if req.archived {
+        if let Err(e) = webhooks::emit(dbconn.as_ref(), &queue, Event::Archived, &link, &[]).await {
+            warn!("webhooks for an archived link couldn't be queued: {e}");
+        }
+    }
+    link.created_by.clear();
+
+    Ok((StatusCode::OK, Json(link)))
+}
+
+pub async fn delete(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(link_id): Path<LinkId>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    let mut link = find_own_link(dbconn.as_ref(), &user_id, &link_id).await?;
+    link.deleted_at = Some(Utc::now().into());
+    if let Err(e) = links::Entity::update_many()
+        .col_expr(links::Column::DeletedAt, Expr::value(link.deleted_at))
+        .filter(links::Column::Id.eq(link.id.to_string()))
+        .exec(dbconn.as_ref())
+        .await
+    {
+        error!("tried deleting a link: {e}");
+        return Err(resp_err(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "database was unreachable",
+        ));
+    }
+    if let Err(e) = webhooks::emit(dbconn.as_ref(), &queue, Event::Deleted, &link, &[]).await {
+        warn!("webhooks for a deleted link couldn't be queued: {e}");
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct ReminderRequest {
+    // leaving it out wakes the link up again
+    remind_at: Option<DateTimeWithTimeZone>,
+}
+
+// snoozes a link until it's reminder is due
+pub async fn remind(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(link_id): Path<LinkId>,
+    Json(req): Json<ReminderRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    let mut link = find_own_link(dbconn.as_ref(), &user_id, &link_id).await?;
+    if let Err(e) = reminders::snooze(dbconn.as_ref(), &queue, &link.id, req.remind_at).await {
+        error!("tried setting the reminder of a link: {e}");
+        return Err(resp_err(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "database was unreachable",
+        ));
+    }
+    link.remind_at = req.remind_at;
+    link.reminded_at = None;
+    link.created_by.clear();
+
+    Ok((StatusCode::OK, Json(link)))
+}
+
+// the latest state of a link along with the checks that led there
+#[derive(Serialize)]
+pub struct HealthResponse {
+    current: Option<link_health::Model>,
+    history: Vec<link_checks::Model>,
+}
+
+// tells whether a link still works, and for how long it hasn't when it doesn't
+pub async fn health(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(link_id): Path<LinkId>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    let link = find_own_link(dbconn.as_ref(), &user_id, &link_id).await?;
+    let found = futures_util::try_join!(
+        link_health::Entity::find_by_id(link.id.to_string()).one(dbconn.as_ref()),
+        link_checks::Entity::find()
+            .filter(link_checks::Column::LinkId.eq(link.id.to_string()))
+            .order_by_desc(link_checks::Column::CheckedAt)
+            .limit(100)
+            .all(dbconn.as_ref()),
+    );
+    match found {
+        Ok((current, history)) => Ok((StatusCode::OK, Json(HealthResponse { current, history }))),
+        Err(e) => {
+            error!("fetching the health of a link from the database failed: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ))
+        }
+    }
+}
+
+// takes another snapshot of the page, for when it changed since the last one
+pub async fn snapshot(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(snapshotter): Extension<Option<Arc<Snapshotter>>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(link_id): Path<LinkId>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    if snapshotter.is_none() {
+        return Err(resp_err(
+            StatusCode::NOT_IMPLEMENTED,
+            "snapshots are not enabled on this server",
+        ));
+    }
+    let link = find_own_link(dbconn.as_ref(), &user_id, &link_id).await?;
+    match queue.push(Job::Snapshot { link_id: link.id }).await {
+        Ok(()) => Ok(StatusCode::ACCEPTED),
+        Err(e) => {
+            error!("snapshot of a link couldn't be queued: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ))
+        }
+    }
+}
+
+pub async fn snapshots(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(link_id): Path<LinkId>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // get api key context
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    let link = find_own_link(dbconn.as_ref(), &user_id, &link_id).await?;
+    match snapshots::Entity::find()
+        .filter(snapshots::Column::LinkId.eq(link.id.to_string()))
+        .order_by_desc(snapshots::Column::DateCreated)
+        .all(dbconn.as_ref())
+        .await
+    {
+        Ok(found) => Ok((StatusCode::OK, Json(found))),
+        Err(e) => {
+            error!("fetching the snapshots of a link from the database failed: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ))
+        }
+    }
+}
@@ -0,0 +1,455 @@
+use std::{fmt, str::FromStr, sync::Arc};
+
+use {
+    anyhow::{anyhow, Error, Result},
+    axum::{
+        extract::{Extension, Path},
+        http::StatusCode,
+        response::{IntoResponse, Json},
+    },
+    axum_auth::AuthBearer,
+    chrono::Utc,
+    sea_orm::{
+        entity::{prelude::*, Set},
+        DatabaseConnection, QueryOrder,
+    },
+    serde::{Deserialize, Serialize},
+    tera::Context,
+    tracing::{error, warn},
+    ulid::Ulid,
+};
+
+use super::{
+    error::{resp_err, ApiError},
+    groups::GroupId,
+    UserId,
+};
+
+use crate::{
+    config::Configuration,
+    database::entity::{group_members, groups, users},
+    identity,
+    jobs::Queue,
+    mail::Outbox,
+};
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    // editors and owners are allowed to put links into a group
+    pub fn can_edit(&self) -> bool {
+        *self >= Role::Editor
+    }
+
+    // only owners can invite, change or remove members
+    pub fn can_manage(&self) -> bool {
+        *self == Role::Owner
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "owner" => Ok(Role::Owner),
+            _ => Err(anyhow!("unknown group role: {s}")),
+        }
+    }
+}
+
+pub type MemberId = Ulid;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Member {
+    pub id: MemberId,
+    pub group_id: GroupId,
+    pub user_id: Option<UserId>,
+    pub email: Option<String>,
+    pub role: Role,
+    pub invited_by: UserId,
+    pub date_created: DateTimeWithTimeZone,
+    pub modified_at: Option<DateTimeWithTimeZone>,
+    pub deleted_at: Option<DateTimeWithTimeZone>,
+}
+
+impl Member {
+    pub fn new(
+        group_id: GroupId,
+        user_id: Option<UserId>,
+        email: Option<String>,
+        role: Role,
+        created_at: DateTimeWithTimeZone,
+        inviter: UserId,
+    ) -> Self {
+        Self {
+            id: MemberId::new(),
+            group_id,
+            user_id,
+            email,
+            role,
+            invited_by: inviter,
+            date_created: created_at,
+            modified_at: None,
+            deleted_at: None,
+        }
+    }
+
+    // converts the member into it's database entity representation
+    pub fn into_der(self) -> group_members::ActiveModel {
+        group_members::ActiveModel {
+            id: Set(self.id.to_string()),
+            group_id: Set(self.group_id.to_string()),
+            user_id: Set(self.user_id),
+            email: Set(self.email),
+            role: Set(self.role.to_string()),
+            invited_by: Set(self.invited_by),
+            date_created: Set(self.date_created),
+            modified_at: Set(self.modified_at),
+            deleted_at: Set(self.deleted_at),
+        }
+    }
+
+    pub fn from_inactive_der(der: group_members::Model) -> Result<Self, Error> {
+        Ok(Self {
+            id: Ulid::from_string(&der.id)?,
+            group_id: Ulid::from_string(&der.group_id)?,
+            user_id: der.user_id,
+            email: der.email,
+            role: der.role.parse()?,
+            invited_by: der.invited_by,
+            date_created: der.date_created,
+            modified_at: der.modified_at,
+            deleted_at: der.deleted_at,
+        })
+    }
+}
+
+// checks the api key and makes sure the caller holds at least the given role
+// in the group, returning their user id and role when they do
+pub(super) async fn authorize(
+    dbconn: Arc<DatabaseConnection>,
+    auth_token: String,
+    group_id: &GroupId,
+    allowed: fn(&Role) -> bool,
+) -> Result<(UserId, Role), (StatusCode, Json<ApiError<'static>>)> {
+    let apikey = match identity::valid_api_key(dbconn.clone(), auth_token).await {
+        Some(apikey) => apikey,
+        None => return Err(resp_err(StatusCode::UNAUTHORIZED, "api key is not valid")),
+    };
+    let user_id = apikey.created_by as UserId;
+
+    match identity::group_role(dbconn, &user_id, group_id).await {
+        Ok(Some(role)) if allowed(&role) => Ok((user_id, role)),
+        Ok(Some(_)) => Err(resp_err(
+            StatusCode::FORBIDDEN,
+            "your role in this group does not allow that",
+        )),
+        Ok(None) => Err(resp_err(StatusCode::NOT_FOUND, "group does not exist")),
+        Err(e) => {
+            error!("looking up group role failed: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct InviteRequest {
+    pub user: Option<UserId>,
+    pub email: Option<String>,
+    pub role: Role,
+    pub timestamptz: DateTimeWithTimeZone,
+}
+
+// lets whoever was invited by email know about it, they may not have an
+// account yet
+async fn send_invitation(
+    dbconn: &DatabaseConnection,
+    queue: &Queue,
+    outbox: &Outbox,
+    cfg: &Configuration,
+    member: &Member,
+    email: &str,
+) -> Result<()> {
+    let group = groups::Entity::find_by_id(member.group_id.to_string())
+        .one(dbconn)
+        .await?
+        .ok_or_else(|| anyhow!("group {} went away", member.group_id))?;
+    let inviter = users::Entity::find_by_id(member.invited_by.clone())
+        .one(dbconn)
+        .await?
+        .and_then(|user| user.login)
+        .unwrap_or_else(|| "Someone".to_string());
+
+    let mut ctx = Context::new();
+    ctx.insert("inviter", &inviter);
+    ctx.insert("group", &group.name);
+    ctx.insert("role", &member.role);
+    ctx.insert(
+        "sign_in_url",
+        &cfg.public_url("/").map(|url| url.to_string()),
+    );
+    let subject = format!("{inviter} invited you to {}", group.name);
+    outbox
+        .send(queue, email, &subject, "invitation", &ctx)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn invite(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(outbox): Extension<Arc<Outbox>>,
+    Extension(cfg): Extension<Arc<Configuration>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(group_id): Path<GroupId>,
+    Json(req): Json<InviteRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    let (user_id, _) = authorize(dbconn.clone(), auth_token, &group_id, Role::can_manage).await?;
+
+    // invitations by email resolve to an account straight away when someone
+    // has already signed in with that verified address, otherwise they stay
+    // pending until they do
+    let (invitee, email) = match (req.user, req.email) {
+        (Some(invitee), None) => (Some(invitee), None),
+        (None, Some(email)) => {
+            let email = email.trim().to_lowercase();
+            match users::Entity::find()
+                .filter(users::Column::Email.eq(email.clone()))
+                .filter(users::Column::EmailVerified.eq(true))
+                .one(dbconn.as_ref())
+                .await
+            {
+                Ok(found) => (found.map(|u| u.id), Some(email)),
+                Err(e) => {
+                    error!("looking up invited email failed: {e}");
+                    return Err(resp_err(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "database was unreachable",
+                    ));
+                }
+            }
+        }
+        _ => {
+            return Err(resp_err(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "exactly one of user or email must be given",
+            ))
+        }
+    };
+
+    // don't hand out a second membership to someone who already has one
+    let mut existing = group_members::Entity::find()
+        .filter(group_members::Column::GroupId.eq(group_id.to_string()))
+        .filter(group_members::Column::DeletedAt.is_null());
+    existing = match (&invitee, &email) {
+        (Some(invitee), _) => existing.filter(group_members::Column::UserId.eq(invitee.clone())),
+        (None, Some(email)) => existing.filter(group_members::Column::Email.eq(email.clone())),
+        (None, None) => unreachable!(),
+    };
+    match existing.one(dbconn.as_ref()).await {
+        Ok(None) => (),
+        Ok(Some(_)) => {
+            return Err(resp_err(
+                StatusCode::CONFLICT,
+                "already a member of this group",
+            ))
+        }
+        Err(e) => {
+            error!("looking up existing membership failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ));
+        }
+    }
+
+    // commit to database
+    let member = Member::new(group_id, invitee, email, req.role, req.timestamptz, user_id);
+    match group_members::Entity::insert(member.clone().into_der())
+        .exec(dbconn.as_ref())
+        .await
+    {
+        Ok(_) => (),
+        Err(e) => {
+            error!("tried committing group member to database: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ));
+        }
+    };
+
+    // the membership is there either way, the email is only a heads up
+    if let Some(email) = &member.email {
+        if let Err(e) =
+            send_invitation(dbconn.as_ref(), &queue, &outbox, &cfg, &member, email).await
+        {
+            warn!("invitation to {email} couldn't be sent: {e:#}");
+        }
+    }
+
+    // return the response
+    Ok((StatusCode::CREATED, Json(member)))
+}
+
+pub async fn list(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(group_id): Path<GroupId>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    authorize(dbconn.clone(), auth_token, &group_id, |_| true).await?;
+
+    let members = match group_members::Entity::find()
+        .order_by_asc(group_members::Column::DateCreated)
+        .filter(group_members::Column::GroupId.eq(group_id.to_string()))
+        .filter(group_members::Column::DeletedAt.is_null())
+        .all(dbconn.as_ref())
+        .await
+    {
+        Ok(ok) => ok,
+        Err(e) => {
+            error!("fetching group members from the database failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "couldn't retrieve members from database",
+            ));
+        }
+    };
+
+    // convert der's to a rust struct
+    let mut converted_members = Vec::new();
+    for member in members {
+        converted_members.push(match Member::from_inactive_der(member) {
+            Ok(ok) => ok,
+            Err(e) => {
+                error!("der member couldn't be casted into rust repr member: {e}");
+                return Err(resp_err(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "members in database couldn't be processed",
+                ));
+            }
+        })
+    }
+
+    Ok((StatusCode::OK, Json(converted_members)))
+}
+
+async fn find_member(
+    dbconn: &DatabaseConnection,
+    group_id: &GroupId,
+    member_id: &MemberId,
+) -> Result<group_members::Model, (StatusCode, Json<ApiError<'static>>)> {
+    match group_members::Entity::find_by_id(member_id.to_string())
+        .filter(group_members::Column::GroupId.eq(group_id.to_string()))
+        .filter(group_members::Column::DeletedAt.is_null())
+        .one(dbconn)
+        .await
+    {
+        Ok(Some(member)) => Ok(member),
+        Ok(None) => Err(resp_err(StatusCode::NOT_FOUND, "member does not exist")),
+        Err(e) => {
+            error!("fetching group member from the database failed: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpdateRequest {
+    pub role: Role,
+}
+
+pub async fn update(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path((group_id, member_id)): Path<(GroupId, MemberId)>,
+    Json(req): Json<UpdateRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    authorize(dbconn.clone(), auth_token, &group_id, Role::can_manage).await?;
+
+    let mut member: group_members::ActiveModel =
+        find_member(dbconn.as_ref(), &group_id, &member_id)
+            .await?
+            .into();
+    member.role = Set(req.role.to_string());
+    member.modified_at = Set(Some(Utc::now().into()));
+    match member.update(dbconn.as_ref()).await {
+        Ok(updated) => match Member::from_inactive_der(updated) {
+            Ok(member) => Ok((StatusCode::OK, Json(member))),
+            Err(e) => {
+                error!("der member couldn't be casted into rust repr member: {e}");
+                Err(resp_err(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "member in database couldn't be processed",
+                ))
+            }
+        },
+        Err(e) => {
+            error!("tried updating group member in database: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ))
+        }
+    }
+}
+
+pub async fn remove(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path((group_id, member_id)): Path<(GroupId, MemberId)>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    // members are allowed to leave a group on their own, everything else
+    // needs an owner
+    let (user_id, role) = authorize(dbconn.clone(), auth_token, &group_id, |_| true).await?;
+    let member = find_member(dbconn.as_ref(), &group_id, &member_id).await?;
+    if member.user_id.as_ref() != Some(&user_id) && !role.can_manage() {
+        return Err(resp_err(
+            StatusCode::FORBIDDEN,
+            "your role in this group does not allow that",
+        ));
+    }
+
+    let mut member: group_members::ActiveModel = member.into();
+    member.deleted_at = Set(Some(Utc::now().into()));
+    match member.update(dbconn.as_ref()).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => {
+            error!("tried removing group member from database: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ))
+        }
+    }
+}
@@ -1,7 +1,14 @@
-mod error;
+pub mod archives;
+pub mod duplicates;
+pub mod error;
+pub mod exports;
+pub mod feeds;
 pub mod groups;
+pub mod imports;
 pub mod links;
+pub mod members;
 mod oauth2;
+pub mod shares;
 mod user;
 
 use crate::app::User;
@@ -9,13 +9,17 @@ use {
     },
     rand::{distributions::Alphanumeric, Rng},
     rand_pcg::Pcg64Mcg,
+    sea_orm::DatabaseConnection,
     serde::Deserialize,
     tower_cookies::{Cookie, Cookies},
-    tracing::{debug, error, info},
+    tracing::{debug, error, info, warn},
 };
 
 use super::error::{resp_err, ApiError};
-use crate::app::{OpenIDClient, Session, Sessions, User};
+use crate::{
+    app::{OpenIDClient, Session, Sessions, User},
+    identity,
+};
 
 #[derive(Deserialize)]
 pub struct LoginRequest {
@@ -46,24 +50,27 @@ pub async fn authenticate(
     Extension(sessions): Extension<Arc<Sessions>>,
     cookies: Cookies,
     Extension(rng): Extension<Arc<Mutex<Pcg64Mcg>>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
     Query(req): Query<LoginRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
     // TODO: check that the state matches
     let request_token = request_token(openid, &req).await;
     match request_token {
         Ok(Some((token, userinfo))) => {
-            // QUEST: why do I have to do this???
-            let c_rng = rng.clone();
-            let c_rng = c_rng.as_ref().lock().unwrap();
-
-            let id = c_rng
-                .clone() // QUEST: or this????
-                .sample_iter(&Alphanumeric)
-                .take(24)
-                .map(char::from)
-                .collect::<String>();
-
-            std::mem::drop(c_rng); // drop the mutex immediately after we're done
+            // the mutex guard has to be scoped so it isn't held across the
+            // database calls further down
+            let id = {
+                // QUEST: why do I have to do this???
+                let c_rng = rng.clone();
+                let c_rng = c_rng.as_ref().lock().unwrap();
+
+                c_rng
+                    .clone() // QUEST: or this????
+                    .sample_iter(&Alphanumeric)
+                    .take(24)
+                    .map(char::from)
+                    .collect::<String>()
+            };
 
             let login = userinfo.preferred_username.clone();
             let email = userinfo.email.clone();
@@ -87,6 +94,12 @@ pub async fn authenticate(
             cookies.add(auth_cookie);
 
             debug!("user: {user:?}");
+            if let Err(e) = identity::remember_user(dbconn.clone(), &user).await {
+                warn!("user record couldn't be saved: {e}");
+            }
+            if let Err(e) = identity::claim_invitations(dbconn, &user).await {
+                warn!("pending group invitations couldn't be claimed: {e}");
+            }
             sessions.insert(
                 id,
                 Session {
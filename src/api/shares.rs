@@ -0,0 +1,224 @@
+use std::sync::Arc;
+
+use {
+    anyhow::{Error, Result},
+    axum::{
+        extract::{Extension, Path},
+        http::StatusCode,
+        response::{IntoResponse, Json},
+    },
+    axum_auth::AuthBearer,
+    chrono::Utc,
+    sea_orm::{
+        entity::{prelude::*, Set},
+        DatabaseConnection, QueryOrder,
+    },
+    serde::{Deserialize, Serialize},
+    tracing::error,
+    ulid::Ulid,
+};
+
+use super::{
+    error::{resp_err, ApiError},
+    groups::GroupId,
+    members::{self, Role},
+    UserId,
+};
+
+use crate::{database::entity::group_shares, identity};
+
+pub type ShareId = Ulid;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Share {
+    pub id: ShareId,
+    pub group_id: GroupId,
+    pub token: String,
+    pub include_sensitive: bool,
+    pub expires_at: Option<DateTimeWithTimeZone>,
+    pub created_by: UserId,
+    pub date_created: DateTimeWithTimeZone,
+    pub deleted_at: Option<DateTimeWithTimeZone>,
+}
+
+impl Share {
+    pub fn new(
+        group_id: GroupId,
+        include_sensitive: bool,
+        expires_at: Option<DateTimeWithTimeZone>,
+        created_at: DateTimeWithTimeZone,
+        owner: UserId,
+    ) -> Self {
+        Self {
+            id: ShareId::new(),
+            group_id,
+            token: identity::secret_token(32),
+            include_sensitive,
+            expires_at,
+            created_by: owner,
+            date_created: created_at,
+            deleted_at: None,
+        }
+    }
+
+    // converts the share into it's database entity representation
+    pub fn into_der(self) -> group_shares::ActiveModel {
+        group_shares::ActiveModel {
+            id: Set(self.id.to_string()),
+            group_id: Set(self.group_id.to_string()),
+            token: Set(self.token),
+            include_sensitive: Set(self.include_sensitive),
+            expires_at: Set(self.expires_at),
+            created_by: Set(self.created_by),
+            date_created: Set(self.date_created),
+            deleted_at: Set(self.deleted_at),
+        }
+    }
+
+    pub fn from_inactive_der(der: group_shares::Model) -> Result<Self, Error> {
+        Ok(Self {
+            id: Ulid::from_string(&der.id)?,
+            group_id: Ulid::from_string(&der.group_id)?,
+            token: der.token,
+            include_sensitive: der.include_sensitive,
+            expires_at: der.expires_at,
+            created_by: der.created_by,
+            date_created: der.date_created,
+            deleted_at: der.deleted_at,
+        })
+    }
+
+    // a share stops working once it has been revoked or it's expiry passed
+    pub fn is_active(&self) -> bool {
+        self.deleted_at.is_none()
+            && match self.expires_at {
+                Some(expiry) => expiry > Utc::now(),
+                None => true,
+            }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SubmitRequest {
+    pub timestamptz: DateTimeWithTimeZone,
+    pub expires_at: Option<DateTimeWithTimeZone>,
+    pub include_sensitive: Option<bool>,
+}
+
+pub async fn submit(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(group_id): Path<GroupId>,
+    Json(req): Json<SubmitRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    let (user_id, _) =
+        members::authorize(dbconn.clone(), auth_token, &group_id, Role::can_manage).await?;
+
+    // commit to database
+    let share = Share::new(
+        group_id,
+        req.include_sensitive.unwrap_or_default(),
+        req.expires_at,
+        req.timestamptz,
+        user_id,
+    );
+    match group_shares::Entity::insert(share.clone().into_der())
+        .exec(dbconn.as_ref())
+        .await
+    {
+        Ok(_) => (),
+        Err(e) => {
+            error!("tried committing group share to database: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ));
+        }
+    };
+
+    // return the response
+    Ok((StatusCode::CREATED, Json(share)))
+}
+
+pub async fn list(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path(group_id): Path<GroupId>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    members::authorize(dbconn.clone(), auth_token, &group_id, Role::can_manage).await?;
+
+    let shares = match group_shares::Entity::find()
+        .order_by_desc(group_shares::Column::DateCreated)
+        .filter(group_shares::Column::GroupId.eq(group_id.to_string()))
+        .filter(group_shares::Column::DeletedAt.is_null())
+        .all(dbconn.as_ref())
+        .await
+    {
+        Ok(ok) => ok,
+        Err(e) => {
+            error!("fetching group shares from the database failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "couldn't retrieve shares from database",
+            ));
+        }
+    };
+
+    // convert der's to a rust struct
+    let mut converted_shares = Vec::new();
+    for share in shares {
+        converted_shares.push(match Share::from_inactive_der(share) {
+            Ok(mut ok) => {
+                ok.created_by.clear();
+                ok
+            }
+            Err(e) => {
+                error!("der share couldn't be casted into rust repr share: {e}");
+                return Err(resp_err(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "shares in database couldn't be processed",
+                ));
+            }
+        })
+    }
+
+    Ok((StatusCode::OK, Json(converted_shares)))
+}
+
+pub async fn revoke(
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    AuthBearer(auth_token): AuthBearer,
+    Path((group_id, share_id)): Path<(GroupId, ShareId)>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    members::authorize(dbconn.clone(), auth_token, &group_id, Role::can_manage).await?;
+
+    let share = match group_shares::Entity::find_by_id(share_id.to_string())
+        .filter(group_shares::Column::GroupId.eq(group_id.to_string()))
+        .filter(group_shares::Column::DeletedAt.is_null())
+        .one(dbconn.as_ref())
+        .await
+    {
+        Ok(Some(share)) => share,
+        Ok(None) => return Err(resp_err(StatusCode::NOT_FOUND, "share does not exist")),
+        Err(e) => {
+            error!("fetching group share from the database failed: {e}");
+            return Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ));
+        }
+    };
+
+    let mut share: group_shares::ActiveModel = share.into();
+    share.deleted_at = Set(Some(Utc::now().into()));
+    match share.update(dbconn.as_ref()).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => {
+            error!("tried revoking group share in database: {e}");
+            Err(resp_err(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database was unreachable",
+            ))
+        }
+    }
+}
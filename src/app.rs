@@ -7,7 +7,7 @@ use {
     anyhow::{Error, Result},
     axum::{
         extract::Extension,
-        routing::{get, get_service, post},
+        routing::{delete, get, get_service, post, put},
         Router, Server,
     },
     dashmap::DashMap,
@@ -15,7 +15,7 @@ use {
     rand::SeedableRng,
     rand_pcg::Pcg64Mcg,
     reqwest::{StatusCode, Url},
-    sea_orm::{Database, DatabaseConnection},
+    sea_orm::DatabaseConnection,
     serde::{Deserialize, Serialize},
     tera::Tera,
     tower_cookies::CookieManagerLayer,
@@ -23,6 +23,21 @@ use {
     tracing::{info, trace},
 };
 
+use crate::{
+    digests::Digests,
+    health::LinkChecker,
+    inbound::Inbox,
+    interchange::import::PendingImports,
+    jobs::{Job, Queue, Workers},
+    mail::Outbox,
+    metadata::Fetcher,
+    net::Guard,
+    normalize::Normalizer,
+    reminders::Reminders,
+    snapshots::Snapshotter,
+    webhooks::Webhooks,
+};
+
 pub type OpenIDClient = openid::Client<openid::Discovered, openid::StandardClaims>;
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -48,7 +63,7 @@ pub struct Session {
 pub type Sessions = DashMap<String, Session>;
 
 pub(crate) struct Application {
-    cfg: super::config::Configuration,
+    cfg: Arc<super::config::Configuration>,
     addr: SocketAddr,
     router: Router,
     templates: Arc<Tera>,
@@ -60,7 +75,12 @@ pub(crate) struct Application {
 
 impl Application {
     pub async fn prepare(config: super::config::Configuration) -> Result<Self, Error> {
-        let tera = Arc::new(Tera::new("static/templates/**/*.html.tera")?);
+        let config = Arc::new(config);
+        let mut tera = Tera::new("static/templates/**/*.tera")?;
+        // some pages are public, so nothing from the database goes out
+        // unescaped, the text versions of emails are left as they are
+        tera.autoescape_on(vec![".html.tera"]);
+        let tera = Arc::new(tera);
 
         let openid_client = Arc::new(
             openid::DiscoveredClient::discover(
@@ -73,34 +93,199 @@ impl Application {
         );
         trace!("OpenID Config: {:?}", openid_client.config());
 
-        let db_url = format!(
-            "postgres://{}:{}@{}:{}/{}",
-            config.database.user,
-            config.database.pass,
-            config.database.host,
-            config.database.port,
-            config.database.database
-        );
-        let db = Arc::new(Database::connect(db_url).await?);
+        config.referrer_policy()?;
+
+        let db = Arc::new(super::database::connect(&config.database).await?);
         trace!("Database Config: {:?}", config.database);
 
         info!("initializing prng");
         let rng = Arc::new(Mutex::new(Pcg64Mcg::from_entropy()));
 
+        // everything fetched from addresses users hand in goes through this
+        let guard = Arc::new(Guard::new(config.network.as_ref()));
+        let fetcher = Arc::new(Fetcher::new(config.fetcher.as_ref(), &guard, db.clone())?);
+        let checker = Arc::new(LinkChecker::new(&guard, db.clone())?);
+        let snapshotter = match &config.snapshots {
+            Some(cfg) => Some(Arc::new(Snapshotter::new(cfg, &guard, db.clone())?)),
+            None => None,
+        };
+        let normalizer = Arc::new(Normalizer::new(config.normalization.as_ref()));
+        let outbox = Arc::new(Outbox::new(config.smtp.as_ref(), tera.clone(), db.clone())?);
+        let reminders = Arc::new(Reminders::new(config.clone(), db.clone(), outbox.clone())?);
+        let digests = Arc::new(Digests::new(config.clone(), db.clone(), outbox.clone()));
+        let queue = Arc::new(Queue::new(db.clone()));
+        Arc::new(Workers::new(
+            config.jobs.as_ref(),
+            queue.clone(),
+            fetcher.clone(),
+            checker,
+            snapshotter.clone(),
+            normalizer.clone(),
+            reminders,
+            digests,
+            outbox.clone(),
+            Arc::new(Webhooks::new(&guard, db.clone())?),
+        ))
+        .start();
+        // the link checks keep themselves going once the first sweep is queued
+        queue.push_unique(Job::CheckLinks).await?;
+        if snapshotter.is_some() {
+            queue.push_unique(Job::PruneSnapshots).await?;
+        }
+        queue.push_unique(Job::PruneJobs).await?;
+        // links saved before, or under other rules, are caught up with
+        queue.push_unique(Job::NormalizeUrls).await?;
+        if let Some(cfg) = &config.inbound {
+            Arc::new(Inbox::new(
+                cfg,
+                db.clone(),
+                normalizer.clone(),
+                queue.clone(),
+                snapshotter.is_some(),
+            ))
+            .start()
+            .await?;
+        }
+
         let sessions = Arc::new(DashMap::new() as Sessions);
+        let pending_imports = Arc::new(DashMap::new() as PendingImports);
         let apis = Router::new()
             .route("/auth/oauth2/code/oidc", get(super::api::authenticate))
             .route("/oauth2/login/oidc", get(super::api::login))
             .route("/oauth2/logout/oidc", get(super::api::logout))
             .route("/link", post(super::api::links::submit))
             .route("/links", get(super::api::links::list))
+            .route("/link/:id/citation", put(super::api::links::cite))
+            .route(
+                "/link/:id/citation/fetch",
+                post(super::api::links::fetch_citation),
+            )
+            .route("/link/:id/health", get(super::api::links::health))
+            .route("/link/:id/reading", put(super::api::links::reading))
+            .route("/link/:id/reminder", put(super::api::links::remind))
+            .route("/link/:id/archive", put(super::api::links::archive))
+            .route("/link/:id", delete(super::api::links::delete))
+            .route("/duplicates", get(super::api::duplicates::list))
+            .route(
+                "/duplicates/merge",
+                post(super::api::duplicates::merge_links),
+            )
+            .route(
+                "/link/:id/snapshots",
+                get(super::api::links::snapshots).post(super::api::links::snapshot),
+            )
+            .route("/link/:id/archive.warc.gz", get(super::api::archives::link))
             .route("/group", post(super::api::groups::submit))
-            .route("/groups", get(super::api::groups::list));
+            .route("/groups", get(super::api::groups::list))
+            .route(
+                "/feeds",
+                get(super::api::feeds::list).post(super::api::feeds::submit),
+            )
+            .route("/feeds/:id", delete(super::api::feeds::revoke))
+            .route("/import/:format", post(super::api::imports::submit))
+            .route(
+                "/export/bookmarks.html",
+                get(super::api::exports::bookmarks),
+            )
+            .route("/export.jsonl", get(super::api::exports::jsonl))
+            .route("/export/links.csv", get(super::api::exports::links_csv))
+            .route("/export/links.md", get(super::api::exports::links_markdown))
+            .route(
+                "/export/citations.bib",
+                get(super::api::exports::citations_bibtex),
+            )
+            .route(
+                "/export/citations.json",
+                get(super::api::exports::citations_csl_json),
+            )
+            .route("/restore", post(super::api::exports::restore))
+            .route(
+                "/group/:id/members",
+                get(super::api::members::list).post(super::api::members::invite),
+            )
+            .route(
+                "/group/:id/members/:member_id",
+                put(super::api::members::update).delete(super::api::members::remove),
+            )
+            .route(
+                "/group/:id/shares",
+                get(super::api::shares::list).post(super::api::shares::submit),
+            )
+            .route(
+                "/group/:id/shares/:share_id",
+                delete(super::api::shares::revoke),
+            )
+            .route(
+                "/group/:id/archive.warc.gz",
+                get(super::api::archives::group),
+            );
         let router = Router::new()
             .nest("/api/v0", apis)
             .route("/", get(super::pages::index))
             .route("/apikeys", get(super::pages::apikeys::page))
             .route("/apikeys/create", post(super::pages::apikeys::create))
+            .route(
+                "/import",
+                get(super::pages::import::page).post(super::pages::import::upload),
+            )
+            .route("/import/preview", post(super::pages::import::preview))
+            .route("/import/confirm", post(super::pages::import::confirm))
+            .route("/export/links.csv", get(super::pages::export::links_csv))
+            .route(
+                "/export/links.md",
+                get(super::pages::export::links_markdown),
+            )
+            .route(
+                "/export/citations.bib",
+                get(super::pages::export::citations_bibtex),
+            )
+            .route(
+                "/export/citations.json",
+                get(super::pages::export::citations_csl_json),
+            )
+            .route(
+                "/digest",
+                get(super::pages::digest::page).post(super::pages::digest::save),
+            )
+            .route("/duplicates", get(super::pages::duplicates::page))
+            .route("/duplicates/merge", post(super::pages::duplicates::merge))
+            .route("/link/:id/read", get(super::pages::reader::page))
+            .route("/go/:id", get(super::pages::go::open))
+            // where links were opened through before, kept for links to it
+            // that are still around
+            .route("/link/:id/open", get(super::pages::go::open))
+            .route("/link/:id/snooze", post(super::pages::reminders::snooze))
+            .route(
+                "/notifications/:id/dismiss",
+                post(super::pages::reminders::dismiss),
+            )
+            .route("/link/:id/snapshot", get(super::pages::snapshot::view))
+            .route(
+                "/link/:id/archive.warc.gz",
+                get(super::pages::snapshot::link_warc),
+            )
+            .route(
+                "/group/:id/archive.warc.gz",
+                get(super::pages::snapshot::group_warc),
+            )
+            .route(
+                "/webhooks",
+                get(super::pages::webhooks::page).post(super::pages::webhooks::create),
+            )
+            .route("/webhook/:id/delete", post(super::pages::webhooks::delete))
+            .route(
+                "/webhook-delivery/:id/retry",
+                post(super::pages::webhooks::retry),
+            )
+            .route("/admin/jobs", get(super::pages::admin::jobs))
+            .route("/admin/mail", get(super::pages::admin::mail))
+            .route("/admin/mail/test", post(super::pages::admin::test_mail))
+            .route(
+                "/admin/jobs/:id/retry",
+                post(super::pages::admin::retry_job),
+            )
+            .route("/s/:token", get(super::pages::shared::page))
+            .route("/feed/:token/:file", get(super::api::feeds::serve))
             .nest(
                 "/static",
                 get_service(ServeDir::new("static/")).handle_error(
@@ -116,8 +301,16 @@ impl Application {
             .layer(Extension(tera.clone()))
             .layer(Extension(openid_client.clone()))
             .layer(Extension(sessions.clone()))
+            .layer(Extension(pending_imports))
             .layer(Extension(db.clone()))
-            .layer(Extension(rng.clone()));
+            .layer(Extension(fetcher))
+            .layer(Extension(queue))
+            .layer(Extension(outbox))
+            .layer(Extension(snapshotter))
+            .layer(Extension(normalizer))
+            .layer(Extension(guard))
+            .layer(Extension(rng.clone()))
+            .layer(Extension(config.clone()));
         Ok(Self {
             cfg: config,
             addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 3030),
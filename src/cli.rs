@@ -0,0 +1,88 @@
+//! Subcommands for working with the backlog from a shell.
+
+use std::sync::Arc;
+
+use {
+    anyhow::{bail, Context, Error, Result},
+    sea_orm::{entity::prelude::*, Condition},
+};
+
+use crate::{
+    config::Configuration,
+    database::{self, entity::users},
+    interchange::import::{ImportFormat, ImportSession},
+    jobs::Queue,
+    normalize::Normalizer,
+    webhooks,
+};
+
+const IMPORT_USAGE: &str =
+    "usage: link_backlog import <format> <file> --user <user id or login> [--dry-run]";
+
+// imports an export or browser profile file from disk for a user that has
+// signed in at least once
+pub async fn import(cfg: &Configuration, args: &[String]) -> Result<(), Error> {
+    let mut positional = Vec::new();
+    let mut user = None;
+    let mut dry_run = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--user" => user = args.next(),
+            "--dry-run" => dry_run = true,
+            _ => positional.push(arg),
+        }
+    }
+    let (format, path, user) = match (positional.as_slice(), user) {
+        ([format, path], Some(user)) => (format.parse::<ImportFormat>()?, path, user),
+        _ => bail!(IMPORT_USAGE),
+    };
+
+    let file = std::fs::read(path).with_context(|| format!("{path} could not be read"))?;
+    let dbconn = Arc::new(database::connect(&cfg.database).await?);
+
+    let user_id = match users::Entity::find()
+        .filter(
+            Condition::any()
+                .add(users::Column::Id.eq(user.clone()))
+                .add(users::Column::Login.eq(user.clone())),
+        )
+        .one(dbconn.as_ref())
+        .await?
+    {
+        Some(user) => user.id,
+        None => bail!("{user} has never signed in"),
+    };
+
+    let normalizer = Arc::new(Normalizer::new(cfg.normalization.as_ref()));
+    let mut session = match dry_run {
+        true => ImportSession::dry_run(dbconn.clone(), normalizer, user_id),
+        false => ImportSession::new(dbconn.clone(), normalizer, user_id),
+    };
+    let chunks = futures_util::stream::iter(file.chunks(64 * 1024).map(Ok::<_, Error>));
+    let result = session.import_stream(format.parser(), chunks).await;
+    // the server's workers pick the jobs up the next time they look
+    let queue = Queue::new(dbconn.clone());
+    queue
+        .push_saved(&session.saved, cfg.snapshots.is_some())
+        .await;
+    webhooks::emit_saved(dbconn.as_ref(), &queue, &session.saved).await;
+    let report = result?;
+
+    for link in &session.previewed {
+        println!(
+            "{}\t{}",
+            link.url,
+            link.title.as_deref().unwrap_or_default()
+        );
+    }
+    println!(
+        "{} {} links, skipped {} duplicates and {} invalid links",
+        if dry_run { "would import" } else { "imported" },
+        report.imported,
+        report.duplicates,
+        report.invalid
+    );
+
+    Ok(())
+}
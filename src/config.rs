@@ -1,12 +1,19 @@
-use std::{fs::read, path::Path};
+use std::{
+    fs::read,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
 
 use {
-    anyhow::{Error, Result},
+    anyhow::{bail, Error, Result},
+    ipnet::IpNet,
     reqwest::Url,
     serde::{Deserialize, Serialize},
     tracing::Level,
 };
 
+use crate::app::User;
+
 #[derive(Deserialize, Serialize, Debug)]
 pub(crate) struct OpenID {
     pub(crate) client_id: String,
@@ -24,6 +31,181 @@ pub(crate) struct Database {
     pub(crate) database: String,
 }
 
+// the policies browsers understand, see the Referrer-Policy header
+const REFERRER_POLICIES: &[&str] = &[
+    "no-referrer",
+    "no-referrer-when-downgrade",
+    "origin",
+    "origin-when-cross-origin",
+    "same-origin",
+    "strict-origin",
+    "strict-origin-when-cross-origin",
+    "unsafe-url",
+];
+
+// sites that links are opened on don't learn where the visitor came from
+// unless configured otherwise
+pub(crate) const DEFAULT_REFERRER_POLICY: &str = "no-referrer";
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct Server {
+    // where the application can be reached from the outside, used whenever
+    // an absolute link back to it has to be handed out
+    pub(crate) public_url: Url,
+    // what the page of a link opened through the application is told about
+    // where the visitor came from
+    pub(crate) referrer_policy: Option<String>,
+}
+
+// limits for downloading the pages links point to, anything left out falls
+// back to a default
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct Fetcher {
+    // seconds a page may take to download
+    pub(crate) timeout: Option<u64>,
+    // bytes read from a page before the rest is ignored
+    pub(crate) page_limit: Option<usize>,
+    // pages downloaded at the same time
+    pub(crate) concurrency: Option<usize>,
+}
+
+// what pages and webhooks handed in by users may be fetched from, addresses on
+// the inside of the network never are unless they're let through here
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct Network {
+    // networks that may be reached anyway, like 10.20.0.0/16 for an intranet
+    // wiki worth archiving
+    #[serde(default)]
+    pub(crate) allow: Vec<IpNet>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct Jobs {
+    // how many jobs are worked on at the same time
+    pub(crate) workers: Option<usize>,
+    // days finished and dead jobs are kept before they're deleted
+    pub(crate) retention: Option<i64>,
+}
+
+// where snapshots of pages are kept
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub(crate) enum Storage {
+    Filesystem {
+        path: PathBuf,
+    },
+    // anything that speaks the s3 api, like minio, buckets are addressed by
+    // path instead of by subdomain
+    S3 {
+        endpoint: Url,
+        bucket: String,
+        region: Option<String>,
+        access_key: String,
+        secret_key: String,
+    },
+}
+
+// copies of the pages links point to, only taken when this is configured
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct Snapshots {
+    pub(crate) storage: Storage,
+    // bytes a snapshot may take up along with everything inlined into it
+    pub(crate) max_size: Option<usize>,
+    // snapshots kept for each link, older ones are deleted
+    pub(crate) keep: Option<usize>,
+    // days after which a snapshot is deleted, even if it's the only one
+    pub(crate) max_age: Option<i64>,
+}
+
+// rules for a single site on top of the general ones, like the parameters
+// that identify a video and the ones that don't
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct SiteRules {
+    // the site's address and every subdomain of it
+    pub(crate) host: String,
+    #[serde(default)]
+    pub(crate) strip_params: Vec<String>,
+    // when given, every other parameter is stripped
+    pub(crate) only_params: Option<Vec<String>>,
+}
+
+// how addresses are brought into one form so the same page saved twice is
+// recognized, anything left out falls back to a default
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct Normalization {
+    // query parameters stripped on top of the well known tracking ones, a
+    // trailing * matches every parameter starting with what comes before it
+    #[serde(default)]
+    pub(crate) strip_params: Vec<String>,
+    // tracking parameters that are kept after all
+    #[serde(default)]
+    pub(crate) keep_params: Vec<String>,
+    #[serde(default)]
+    pub(crate) sites: Vec<SiteRules>,
+    // whether http and https addresses of a page are the same page
+    pub(crate) ignore_scheme: Option<bool>,
+    // whether www.example.com and example.com are the same site
+    pub(crate) ignore_www: Option<bool>,
+    pub(crate) keep_trailing_slash: Option<bool>,
+    pub(crate) keep_fragments: Option<bool>,
+}
+
+// where reminders go besides the banner in the application
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct Reminders {
+    // receives every reminder as json, along with the link it's about
+    pub(crate) webhook: Option<Url>,
+}
+
+// how the connection to the mail server is secured
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TlsMode {
+    // plain text, only meant for mail catchers and servers on the same host
+    None,
+    // starts out in plain text and switches over before anything is sent
+    Starttls,
+    // tls from the start
+    Tls,
+}
+
+// the mail server email is handed to, nothing is sent without it
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct Smtp {
+    pub(crate) host: String,
+    // the usual port of the tls mode when left out, 25, 587 or 465
+    pub(crate) port: Option<u16>,
+    // starttls when left out
+    pub(crate) tls: Option<TlsMode>,
+    // credentials are only ever sent over tls
+    pub(crate) username: Option<String>,
+    pub(crate) password: Option<String>,
+    // the address email is sent from
+    pub(crate) from: String,
+}
+
+// an smtp listener that saves the links in emails sent to it, it's meant to
+// sit behind the mail server that receives email for the domain, which checks
+// where each email came from
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct Inbound {
+    // like 127.0.0.1:2525, only the mail server in front should reach it
+    pub(crate) listen: SocketAddr,
+    // what the mail server in front calls itself in the Authentication-Results
+    // headers it adds, like mx.backlog.example, emails are only saved when it
+    // found a passing dmarc check or dkim signature for the domain of the
+    // From address, only the topmost header is read so it has to be the
+    // last server to add one
+    pub(crate) authserv_id: String,
+    // the only recipient accepted, like save@backlog.example
+    pub(crate) address: String,
+    // links are put into the sender's own group of this name, which is
+    // created when they don't have one yet
+    pub(crate) group: Option<String>,
+    // bytes an email may have, 10 MiB when left out
+    pub(crate) max_size: Option<usize>,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub(crate) struct Tracing {
     pub(crate) level: String,
@@ -31,8 +213,20 @@ pub(crate) struct Tracing {
 
 #[derive(Deserialize, Serialize, Debug)]
 pub(crate) struct Configuration {
+    // ids or logins of the users allowed onto the admin pages
+    #[serde(default)]
+    pub(crate) admins: Vec<String>,
     pub(crate) openid: OpenID,
     pub(crate) database: Database,
+    pub(crate) server: Option<Server>,
+    pub(crate) fetcher: Option<Fetcher>,
+    pub(crate) network: Option<Network>,
+    pub(crate) normalization: Option<Normalization>,
+    pub(crate) jobs: Option<Jobs>,
+    pub(crate) snapshots: Option<Snapshots>,
+    pub(crate) reminders: Option<Reminders>,
+    pub(crate) smtp: Option<Smtp>,
+    pub(crate) inbound: Option<Inbound>,
     pub(crate) tracing: Option<Tracing>,
 }
 
@@ -41,4 +235,30 @@ impl Configuration {
         // QUEST: is it possible to try/catch this?
         Ok(toml::from_slice(&read(path)?).unwrap())
     }
+
+    pub(crate) fn is_admin(&self, user: &User) -> bool {
+        self.admins
+            .iter()
+            .any(|admin| *admin == user.id || Some(admin) == user.login.as_ref())
+    }
+
+    pub(crate) fn referrer_policy(&self) -> Result<&str, Error> {
+        match self
+            .server
+            .as_ref()
+            .and_then(|server| server.referrer_policy.as_deref())
+        {
+            Some(policy) if REFERRER_POLICIES.contains(&policy) => Ok(policy),
+            Some(policy) => bail!("referrer policy {policy:?} is not known"),
+            None => Ok(DEFAULT_REFERRER_POLICY),
+        }
+    }
+
+    // builds an absolute url to a path of the application, if it's public
+    // address is known
+    pub(crate) fn public_url(&self, path: &str) -> Option<Url> {
+        self.server
+            .as_ref()
+            .and_then(|server| server.public_url.join(path).ok())
+    }
 }
@@ -1,8 +1,9 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.8.0
 
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "groups")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
@@ -20,6 +21,10 @@ pub struct Model {
 pub enum Relation {
     #[sea_orm(has_many = "super::grouped_links::Entity")]
     GroupedLinks,
+    #[sea_orm(has_many = "super::group_members::Entity")]
+    GroupMembers,
+    #[sea_orm(has_many = "super::group_shares::Entity")]
+    GroupShares,
 }
 
 impl Related<super::grouped_links::Entity> for Entity {
@@ -28,4 +33,16 @@ impl Related<super::grouped_links::Entity> for Entity {
     }
 }
 
+impl Related<super::group_members::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::GroupMembers.def()
+    }
+}
+
+impl Related<super::group_shares::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::GroupShares.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
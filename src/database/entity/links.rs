@@ -1,8 +1,9 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.8.0
 
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "links")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
@@ -15,14 +16,52 @@ pub struct Model {
     pub modified_at: Option<DateTimeWithTimeZone>,
     pub archived_at: Option<DateTimeWithTimeZone>,
     pub deleted_at: Option<DateTimeWithTimeZone>,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+    pub authors: Option<Json>,
+    pub published_on: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub site_name: Option<String>,
+    pub doi: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub description: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub image_url: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub canonical_url: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub favicon_url: Option<String>,
+    pub fetched_at: Option<DateTimeWithTimeZone>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub normalized_url: Option<String>,
+    pub reading_started_at: Option<DateTimeWithTimeZone>,
+    pub read_at: Option<DateTimeWithTimeZone>,
+    pub reading_progress: Option<i16>,
+    // backups written before links were counted don't have it
+    #[serde(default)]
+    pub open_count: i32,
+    pub last_opened_at: Option<DateTimeWithTimeZone>,
+    pub remind_at: Option<DateTimeWithTimeZone>,
+    pub reminded_at: Option<DateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::notes::Entity")]
     Notes,
+    #[sea_orm(has_many = "super::notifications::Entity")]
+    Notifications,
     #[sea_orm(has_many = "super::grouped_links::Entity")]
     GroupedLinks,
+    #[sea_orm(has_one = "super::link_articles::Entity")]
+    LinkArticles,
+    #[sea_orm(has_many = "super::link_checks::Entity")]
+    LinkChecks,
+    #[sea_orm(has_one = "super::link_health::Entity")]
+    LinkHealth,
+    #[sea_orm(has_many = "super::link_opens::Entity")]
+    LinkOpens,
+    #[sea_orm(has_many = "super::snapshots::Entity")]
+    Snapshots,
     #[sea_orm(has_many = "super::tagged_links::Entity")]
     TaggedLinks,
 }
@@ -33,12 +72,48 @@ impl Related<super::notes::Entity> for Entity {
     }
 }
 
+impl Related<super::notifications::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Notifications.def()
+    }
+}
+
 impl Related<super::grouped_links::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::GroupedLinks.def()
     }
 }
 
+impl Related<super::link_articles::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LinkArticles.def()
+    }
+}
+
+impl Related<super::link_checks::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LinkChecks.def()
+    }
+}
+
+impl Related<super::link_health::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LinkHealth.def()
+    }
+}
+
+impl Related<super::link_opens::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LinkOpens.def()
+    }
+}
+
+impl Related<super::snapshots::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Snapshots.def()
+    }
+}
+
 impl Related<super::tagged_links::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::TaggedLinks.def()
@@ -3,9 +3,24 @@
 pub mod prelude;
 
 pub mod api_keys;
+pub mod digest_settings;
+pub mod feed_tokens;
+pub mod group_members;
+pub mod group_shares;
 pub mod grouped_links;
 pub mod groups;
+pub mod jobs;
+pub mod link_articles;
+pub mod link_checks;
+pub mod link_health;
+pub mod link_opens;
 pub mod links;
 pub mod notes;
+pub mod notifications;
+pub mod outbox;
+pub mod snapshots;
 pub mod tagged_links;
 pub mod tags;
+pub mod users;
+pub mod webhook_deliveries;
+pub mod webhooks;
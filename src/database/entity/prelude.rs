@@ -1,9 +1,24 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.8.0
 
 pub use super::api_keys::Entity as ApiKeys;
+pub use super::digest_settings::Entity as DigestSettings;
+pub use super::feed_tokens::Entity as FeedTokens;
+pub use super::group_members::Entity as GroupMembers;
+pub use super::group_shares::Entity as GroupShares;
 pub use super::grouped_links::Entity as GroupedLinks;
 pub use super::groups::Entity as Groups;
+pub use super::jobs::Entity as Jobs;
+pub use super::link_articles::Entity as LinkArticles;
+pub use super::link_checks::Entity as LinkChecks;
+pub use super::link_health::Entity as LinkHealth;
+pub use super::link_opens::Entity as LinkOpens;
 pub use super::links::Entity as Links;
 pub use super::notes::Entity as Notes;
+pub use super::notifications::Entity as Notifications;
+pub use super::outbox::Entity as Outbox;
+pub use super::snapshots::Entity as Snapshots;
 pub use super::tagged_links::Entity as TaggedLinks;
 pub use super::tags::Entity as Tags;
+pub use super::users::Entity as Users;
+pub use super::webhook_deliveries::Entity as WebhookDeliveries;
+pub use super::webhooks::Entity as Webhooks;
@@ -0,0 +1,11 @@
+CREATE TABLE "snapshots" (
+	"id" VARCHAR(26) NOT NULL UNIQUE,
+	"link_id" VARCHAR(26) NOT NULL,
+	"storage_key" TEXT NOT NULL,
+	"size" BIGINT NOT NULL,
+	"date_created" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	PRIMARY KEY ("id"),
+	FOREIGN KEY ("link_id") REFERENCES "links" ("id") ON DELETE CASCADE
+);
+
+CREATE INDEX ON "snapshots" ("link_id", "date_created");
@@ -0,0 +1,2 @@
+ALTER TABLE "snapshots" ADD COLUMN "warc_key" TEXT;
+ALTER TABLE "snapshots" ADD COLUMN "warc_size" BIGINT;
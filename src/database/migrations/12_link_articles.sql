@@ -0,0 +1,14 @@
+CREATE TABLE "link_articles" (
+	"link_id" VARCHAR(26) NOT NULL UNIQUE,
+	"byline" TEXT,
+	"content" TEXT NOT NULL,
+	"text" TEXT NOT NULL,
+	"word_count" INTEGER NOT NULL,
+	"reading_minutes" INTEGER NOT NULL,
+	"extracted_at" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	"search" TSVECTOR GENERATED ALWAYS AS (to_tsvector('simple', "text")) STORED,
+	PRIMARY KEY ("link_id"),
+	FOREIGN KEY ("link_id") REFERENCES "links" ("id") ON DELETE CASCADE
+);
+
+CREATE INDEX ON "link_articles" USING GIN ("search");
@@ -0,0 +1,3 @@
+ALTER TABLE "links" ADD COLUMN "normalized_url" TEXT;
+
+CREATE UNIQUE INDEX ON "links" ("created_by", "normalized_url") WHERE "deleted_at" IS NULL;
@@ -0,0 +1,4 @@
+ALTER TABLE "links"
+	ADD COLUMN "reading_started_at" TIMESTAMP WITH TIME ZONE,
+	ADD COLUMN "read_at" TIMESTAMP WITH TIME ZONE,
+	ADD COLUMN "reading_progress" SMALLINT CHECK ("reading_progress" BETWEEN 0 AND 100);
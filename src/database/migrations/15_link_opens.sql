@@ -0,0 +1,14 @@
+CREATE TABLE "link_opens" (
+	"id" VARCHAR(26) NOT NULL UNIQUE,
+	"link_id" VARCHAR(26) NOT NULL,
+	"user_id" VARCHAR(256) NOT NULL,
+	"opened_at" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	PRIMARY KEY ("id"),
+	FOREIGN KEY ("link_id") REFERENCES "links" ("id") ON DELETE CASCADE
+);
+
+CREATE INDEX ON "link_opens" ("link_id", "opened_at");
+
+ALTER TABLE "links"
+	ADD COLUMN "open_count" INTEGER NOT NULL DEFAULT 0,
+	ADD COLUMN "last_opened_at" TIMESTAMP WITH TIME ZONE;
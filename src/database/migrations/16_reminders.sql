@@ -0,0 +1,18 @@
+ALTER TABLE "links"
+	ADD COLUMN "remind_at" TIMESTAMP WITH TIME ZONE,
+	ADD COLUMN "reminded_at" TIMESTAMP WITH TIME ZONE;
+
+CREATE TABLE "notifications" (
+	"id" VARCHAR(26) NOT NULL UNIQUE,
+	"user_id" VARCHAR(256) NOT NULL,
+	"link_id" VARCHAR(26) NOT NULL,
+	"kind" VARCHAR(32) NOT NULL,
+	"due_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+	"date_created" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	"dismissed_at" TIMESTAMP WITH TIME ZONE,
+	PRIMARY KEY ("id"),
+	FOREIGN KEY ("link_id") REFERENCES "links" ("id") ON DELETE CASCADE,
+	UNIQUE ("link_id", "kind", "due_at")
+);
+
+CREATE INDEX ON "notifications" ("user_id") WHERE "dismissed_at" IS NULL;
@@ -0,0 +1,19 @@
+CREATE TABLE "digest_settings" (
+	"user_id" VARCHAR(256) NOT NULL UNIQUE,
+	"frequency" VARCHAR(16) NOT NULL DEFAULT 'off',
+	"weekday" SMALLINT NOT NULL DEFAULT 0,
+	"hour" SMALLINT NOT NULL DEFAULT 8,
+	"timezone" VARCHAR(64) NOT NULL DEFAULT 'UTC',
+	"link_count" SMALLINT NOT NULL DEFAULT 5,
+	"pick" VARCHAR(16) NOT NULL DEFAULT 'oldest',
+	"last_sent_at" TIMESTAMP WITH TIME ZONE,
+	"next_due_at" TIMESTAMP WITH TIME ZONE,
+	"date_created" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	"modified_at" TIMESTAMP WITH TIME ZONE,
+	PRIMARY KEY ("user_id"),
+	CHECK ("frequency" IN ('off', 'daily', 'weekly')),
+	CHECK ("weekday" BETWEEN 0 AND 6),
+	CHECK ("hour" BETWEEN 0 AND 23),
+	CHECK ("link_count" BETWEEN 1 AND 50),
+	CHECK ("pick" IN ('oldest', 'random'))
+);
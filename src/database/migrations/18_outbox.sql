@@ -0,0 +1,15 @@
+CREATE TABLE "outbox" (
+	"id" VARCHAR(26) NOT NULL UNIQUE,
+	"to_address" VARCHAR(320) NOT NULL,
+	"subject" TEXT NOT NULL,
+	"html" TEXT NOT NULL,
+	"text" TEXT NOT NULL,
+	"template" VARCHAR(64) NOT NULL,
+	"attempts" INTEGER NOT NULL DEFAULT 0,
+	"last_error" TEXT,
+	"date_created" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	"sent_at" TIMESTAMP WITH TIME ZONE,
+	PRIMARY KEY ("id")
+);
+
+CREATE INDEX ON "outbox" ("date_created");
@@ -0,0 +1,27 @@
+CREATE TABLE "webhooks" (
+	"id" VARCHAR(26) NOT NULL UNIQUE,
+	"created_by" VARCHAR(256) NOT NULL,
+	"url" TEXT NOT NULL,
+	"secret" VARCHAR(128) NOT NULL,
+	"events" VARCHAR(256) NOT NULL,
+	"date_created" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	"deleted_at" TIMESTAMP WITH TIME ZONE,
+	PRIMARY KEY ("id")
+);
+
+CREATE INDEX ON "webhooks" ("created_by") WHERE "deleted_at" IS NULL;
+
+CREATE TABLE "webhook_deliveries" (
+	"id" VARCHAR(26) NOT NULL UNIQUE,
+	"webhook_id" VARCHAR(26) NOT NULL REFERENCES "webhooks" ("id"),
+	"event" VARCHAR(32) NOT NULL,
+	"payload" TEXT NOT NULL,
+	"attempts" INTEGER NOT NULL DEFAULT 0,
+	"status_code" SMALLINT,
+	"last_error" TEXT,
+	"date_created" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	"delivered_at" TIMESTAMP WITH TIME ZONE,
+	PRIMARY KEY ("id")
+);
+
+CREATE INDEX ON "webhook_deliveries" ("webhook_id", "date_created");
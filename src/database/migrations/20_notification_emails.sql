@@ -0,0 +1,3 @@
+ALTER TABLE "notifications" ADD COLUMN "emailed_at" TIMESTAMP WITH TIME ZONE;
+
+UPDATE "notifications" SET "emailed_at" = "date_created";
@@ -0,0 +1,30 @@
+CREATE TABLE "users" (
+	"id" VARCHAR(256) NOT NULL UNIQUE,
+	"login" VARCHAR(256),
+	"email" VARCHAR(320),
+	"email_verified" BOOLEAN NOT NULL DEFAULT FALSE,
+	"date_created" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	"modified_at" TIMESTAMP WITH TIME ZONE,
+	PRIMARY KEY ("id")
+);
+
+CREATE INDEX ON "users" ("email");
+
+CREATE TABLE "group_members" (
+	"id" VARCHAR(26) NOT NULL UNIQUE,
+	"group_id" VARCHAR(26) NOT NULL,
+	"user_id" VARCHAR(256),
+	"email" VARCHAR(320),
+	"role" VARCHAR(16) NOT NULL,
+	"invited_by" VARCHAR(256) NOT NULL,
+	"date_created" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	"modified_at" TIMESTAMP WITH TIME ZONE,
+	"deleted_at" TIMESTAMP WITH TIME ZONE,
+	PRIMARY KEY ("id"),
+	FOREIGN KEY ("group_id") REFERENCES "groups" ("id") ON DELETE CASCADE,
+	CHECK ("user_id" IS NOT NULL OR "email" IS NOT NULL),
+	CHECK ("role" IN ('owner', 'editor', 'viewer'))
+);
+
+CREATE UNIQUE INDEX ON "group_members" ("group_id", "user_id") WHERE "deleted_at" IS NULL;
+CREATE INDEX ON "group_members" ("email") WHERE "user_id" IS NULL;
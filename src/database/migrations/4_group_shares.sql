@@ -0,0 +1,12 @@
+CREATE TABLE "group_shares" (
+	"id" VARCHAR(26) NOT NULL UNIQUE,
+	"group_id" VARCHAR(26) NOT NULL,
+	"token" VARCHAR(64) NOT NULL UNIQUE,
+	"include_sensitive" BOOLEAN NOT NULL DEFAULT FALSE,
+	"expires_at" TIMESTAMP WITH TIME ZONE,
+	"created_by" VARCHAR(256) NOT NULL,
+	"date_created" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	"deleted_at" TIMESTAMP WITH TIME ZONE,
+	PRIMARY KEY ("id"),
+	FOREIGN KEY ("group_id") REFERENCES "groups" ("id") ON DELETE CASCADE
+);
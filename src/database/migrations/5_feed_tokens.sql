@@ -0,0 +1,13 @@
+CREATE TABLE "feed_tokens" (
+	"id" VARCHAR(26) NOT NULL UNIQUE,
+	"created_by" VARCHAR(256) NOT NULL,
+	"token" VARCHAR(64) NOT NULL UNIQUE,
+	"scope" VARCHAR(16) NOT NULL,
+	"target_id" VARCHAR(26),
+	"include_sensitive" BOOLEAN NOT NULL DEFAULT FALSE,
+	"date_created" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	"deleted_at" TIMESTAMP WITH TIME ZONE,
+	PRIMARY KEY ("id"),
+	CHECK ("scope" IN ('backlog', 'group', 'tag')),
+	CHECK (("scope" = 'backlog') = ("target_id" IS NULL))
+);
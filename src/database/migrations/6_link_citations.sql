@@ -0,0 +1,5 @@
+ALTER TABLE "links"
+	ADD COLUMN "authors" JSONB,
+	ADD COLUMN "published_on" VARCHAR(10),
+	ADD COLUMN "site_name" TEXT,
+	ADD COLUMN "doi" VARCHAR(256);
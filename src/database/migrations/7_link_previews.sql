@@ -0,0 +1,6 @@
+ALTER TABLE "links"
+	ADD COLUMN "description" TEXT,
+	ADD COLUMN "image_url" TEXT,
+	ADD COLUMN "canonical_url" TEXT,
+	ADD COLUMN "favicon_url" TEXT,
+	ADD COLUMN "fetched_at" TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,17 @@
+CREATE TABLE "jobs" (
+	"id" VARCHAR(26) NOT NULL UNIQUE,
+	"kind" VARCHAR(32) NOT NULL,
+	"payload" JSONB NOT NULL,
+	"status" VARCHAR(16) NOT NULL DEFAULT 'queued',
+	"attempts" INTEGER NOT NULL DEFAULT 0,
+	"max_attempts" INTEGER NOT NULL DEFAULT 5,
+	"run_at" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	"locked_at" TIMESTAMP WITH TIME ZONE,
+	"last_error" TEXT,
+	"date_created" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	"finished_at" TIMESTAMP WITH TIME ZONE,
+	PRIMARY KEY ("id"),
+	CHECK ("status" IN ('queued', 'running', 'done', 'dead'))
+);
+
+CREATE INDEX "jobs_runnable" ON "jobs" ("run_at") WHERE "status" IN ('queued', 'running');
@@ -0,0 +1,29 @@
+CREATE TABLE "link_health" (
+	"link_id" VARCHAR(26) NOT NULL UNIQUE,
+	"status_code" INTEGER,
+	"final_url" TEXT,
+	"error" TEXT,
+	"broken" BOOLEAN NOT NULL DEFAULT FALSE,
+	"broken_since" TIMESTAMP WITH TIME ZONE,
+	"last_ok_at" TIMESTAMP WITH TIME ZONE,
+	"checked_at" TIMESTAMP WITH TIME ZONE,
+	"next_check_at" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	PRIMARY KEY ("link_id"),
+	FOREIGN KEY ("link_id") REFERENCES "links" ("id") ON DELETE CASCADE
+);
+
+CREATE INDEX ON "link_health" ("next_check_at");
+
+CREATE TABLE "link_checks" (
+	"id" VARCHAR(26) NOT NULL UNIQUE,
+	"link_id" VARCHAR(26) NOT NULL,
+	"status_code" INTEGER,
+	"final_url" TEXT,
+	"error" TEXT,
+	"broken" BOOLEAN NOT NULL,
+	"checked_at" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+	PRIMARY KEY ("id"),
+	FOREIGN KEY ("link_id") REFERENCES "links" ("id") ON DELETE CASCADE
+);
+
+CREATE INDEX ON "link_checks" ("link_id", "checked_at");
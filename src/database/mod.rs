@@ -1 +1,29 @@
 pub mod entity;
+
+use sea_orm::{Database, DatabaseConnection, DbErr};
+
+use crate::config;
+
+pub async fn connect(cfg: &config::Database) -> Result<DatabaseConnection, DbErr> {
+    let db_url = format!(
+        "postgres://{}:{}@{}:{}/{}",
+        cfg.user, cfg.pass, cfg.host, cfg.port, cfg.database
+    );
+    Database::connect(db_url).await
+}
+
+// the widths of the varchar columns that take text from outside, like pages
+// and the exports of other services
+pub const URL_LENGTH: usize = 1024;
+pub const TITLE_LENGTH: usize = 256;
+pub const NAME_LENGTH: usize = 128;
+pub const DOI_LENGTH: usize = 256;
+
+// cuts text down to the width of a column, varchar widths are counted in
+// characters rather than bytes
+pub fn fit(text: &str, width: usize) -> String {
+    match text.char_indices().nth(width) {
+        Some((end, _)) => text[..end].to_string(),
+        None => text.to_string(),
+    }
+}
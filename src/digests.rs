@@ -0,0 +1,520 @@
+//! Digests bring a few links from the backlog back up by email, along with
+//! what was added to shared groups and which links broke since the last one.
+//! Users opt in and choose when they arrive, in their own timezone.
+
+use std::{collections::HashMap, fmt, str::FromStr, sync::Arc};
+
+use {
+    anyhow::{anyhow, Error, Result},
+    chrono::{DateTime, Datelike, Duration, TimeZone, Utc},
+    chrono_tz::Tz,
+    sea_orm::{
+        entity::prelude::*, sea_query::Expr, Condition, DatabaseConnection, Order, QueryOrder,
+        QuerySelect,
+    },
+    serde::Serialize,
+    tera::Context,
+    tracing::{info, warn},
+};
+
+use crate::{
+    api::UserId,
+    config::Configuration,
+    database::entity::{digest_settings, grouped_links, groups, link_health, links, users},
+    identity,
+    jobs::{Job, Queue},
+    mail::Outbox,
+};
+
+// links from shared groups and broken links listed at most, so a busy group
+// doesn't turn the digest into a wall of links
+const SECTION_LIMIT: u64 = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frequency {
+    Off,
+    Daily,
+    Weekly,
+}
+
+impl Frequency {
+    pub const ALL: [Frequency; 3] = [Frequency::Off, Frequency::Daily, Frequency::Weekly];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Frequency::Off => "off",
+            Frequency::Daily => "daily",
+            Frequency::Weekly => "weekly",
+        }
+    }
+
+    fn period(&self) -> Duration {
+        match self {
+            Frequency::Weekly => Duration::days(7),
+            _ => Duration::days(1),
+        }
+    }
+}
+
+impl fmt::Display for Frequency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Frequency {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Frequency::Off),
+            "daily" => Ok(Frequency::Daily),
+            "weekly" => Ok(Frequency::Weekly),
+            _ => Err(anyhow!("unknown digest frequency: {s}")),
+        }
+    }
+}
+
+// which of the unread links make it into the digest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pick {
+    Oldest,
+    Random,
+}
+
+impl Pick {
+    pub const ALL: [Pick; 2] = [Pick::Oldest, Pick::Random];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Pick::Oldest => "oldest",
+            Pick::Random => "random",
+        }
+    }
+}
+
+impl FromStr for Pick {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oldest" => Ok(Pick::Oldest),
+            "random" => Ok(Pick::Random),
+            _ => Err(anyhow!("unknown digest pick: {s}")),
+        }
+    }
+}
+
+pub fn timezone(name: &str) -> Result<Tz> {
+    name.parse()
+        .map_err(|e| anyhow!("unknown timezone {name:?}: {e}"))
+}
+
+// the first time after the given one the digest is due, weekly digests only
+// go out on their weekday, and hours skipped when the clocks change are
+// made up for an hour later
+pub fn next_due(
+    settings: &digest_settings::Model,
+    after: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>> {
+    let frequency: Frequency = settings.frequency.parse()?;
+    if frequency == Frequency::Off {
+        return Ok(None);
+    }
+    let tz = timezone(&settings.timezone)?;
+
+    let today = after.with_timezone(&tz).date().naive_local();
+    for days in 0..=7 {
+        let date = today + Duration::days(days);
+        if frequency == Frequency::Weekly
+            && date.weekday().num_days_from_monday() != settings.weekday as u32
+        {
+            continue;
+        }
+        let local = date.and_hms(settings.hour as u32, 0, 0);
+        let due = tz
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| {
+                tz.from_local_datetime(&(local + Duration::hours(1)))
+                    .earliest()
+            })
+            .map(|due| due.with_timezone(&Utc));
+        match due {
+            Some(due) if due > after => return Ok(Some(due)),
+            _ => continue,
+        }
+    }
+
+    Ok(None)
+}
+
+// works out when the next digest is due after the settings changed, and
+// queues it, jobs queued for earlier settings find out they're stale
+pub async fn schedule(
+    dbconn: &DatabaseConnection,
+    queue: &Queue,
+    settings: &digest_settings::Model,
+) -> Result<()> {
+    let due = next_due(settings, Utc::now())?;
+    digest_settings::Entity::update_many()
+        .col_expr(
+            digest_settings::Column::NextDueAt,
+            Expr::value(due.map(DateTimeWithTimeZone::from)),
+        )
+        .filter(digest_settings::Column::UserId.eq(settings.user_id.clone()))
+        .exec(dbconn)
+        .await?;
+    if let Some(due) = due {
+        queue
+            .push_at(
+                Job::SendDigest {
+                    user_id: settings.user_id.clone(),
+                },
+                due.into(),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Entry {
+    title: String,
+    url: String,
+    date: DateTimeWithTimeZone,
+    // the group it was added to, or why it's broken
+    detail: Option<String>,
+}
+
+pub struct Digests {
+    cfg: Arc<Configuration>,
+    dbconn: Arc<DatabaseConnection>,
+    outbox: Arc<Outbox>,
+}
+
+impl Digests {
+    pub fn new(
+        cfg: Arc<Configuration>,
+        dbconn: Arc<DatabaseConnection>,
+        outbox: Arc<Outbox>,
+    ) -> Self {
+        Self {
+            cfg,
+            dbconn,
+            outbox,
+        }
+    }
+
+    // links in the digest go through the application when it's address is
+    // known, so opening them counts
+    fn entry(
+        &self,
+        link: &links::Model,
+        date: DateTimeWithTimeZone,
+        detail: Option<String>,
+    ) -> Entry {
+        let url = self
+            .cfg
+            .public_url(&format!("go/{}", link.id))
+            .map(|url| url.to_string())
+            .unwrap_or_else(|| link.url.clone());
+        Entry {
+            title: link.title.clone().unwrap_or_else(|| link.url.clone()),
+            url,
+            date,
+            detail,
+        }
+    }
+
+    async fn unread(&self, settings: &digest_settings::Model) -> Result<Vec<Entry>> {
+        let query = links::Entity::find()
+            .filter(links::Column::CreatedBy.eq(settings.user_id.clone()))
+            .filter(links::Column::DeletedAt.is_null())
+            .filter(links::Column::ReadAt.is_null())
+            // snoozed links stay out of the way until they're due
+            .filter(
+                Condition::any()
+                    .add(links::Column::RemindAt.is_null())
+                    .add(links::Column::RemindAt.lte(Utc::now())),
+            )
+            .limit(settings.link_count as u64);
+        let query = match settings.pick.parse()? {
+            Pick::Oldest => query.order_by_asc(links::Column::DateCreated),
+            Pick::Random => query.order_by(Expr::cust("RANDOM()"), Order::Asc),
+        };
+
+        Ok(query
+            .all(self.dbconn.as_ref())
+            .await?
+            .iter()
+            .map(|link| self.entry(link, link.date_created, None))
+            .collect())
+    }
+
+    // what others added to the groups the user is in
+    async fn shared(&self, user_id: &UserId, since: DateTimeWithTimeZone) -> Result<Vec<Entry>> {
+        let roles = identity::group_roles(self.dbconn.clone(), user_id).await?;
+        if roles.is_empty() {
+            return Ok(Vec::new());
+        }
+        let names: HashMap<String, String> = groups::Entity::find()
+            .filter(groups::Column::Id.is_in(roles.keys().cloned().collect::<Vec<_>>()))
+            .all(self.dbconn.as_ref())
+            .await?
+            .into_iter()
+            .map(|group| (group.id, group.name))
+            .collect();
+
+        Ok(grouped_links::Entity::find()
+            .filter(grouped_links::Column::GroupId.is_in(roles.into_keys().collect::<Vec<_>>()))
+            .filter(grouped_links::Column::CreatedBy.ne(user_id.clone()))
+            .filter(grouped_links::Column::DeletedAt.is_null())
+            .filter(grouped_links::Column::DateCreated.gt(since))
+            .filter(links::Column::DeletedAt.is_null())
+            .order_by_desc(grouped_links::Column::DateCreated)
+            .limit(SECTION_LIMIT)
+            .find_also_related(links::Entity)
+            .all(self.dbconn.as_ref())
+            .await?
+            .into_iter()
+            .filter_map(|(grouped, link)| {
+                let group = names.get(&grouped.group_id).cloned();
+                Some(self.entry(&link?, grouped.date_created, group))
+            })
+            .collect())
+    }
+
+    async fn broken(&self, user_id: &UserId, since: DateTimeWithTimeZone) -> Result<Vec<Entry>> {
+        Ok(link_health::Entity::find()
+            .filter(link_health::Column::Broken.eq(true))
+            .filter(link_health::Column::BrokenSince.gt(since))
+            .filter(links::Column::CreatedBy.eq(user_id.clone()))
+            .filter(links::Column::DeletedAt.is_null())
+            .order_by_desc(link_health::Column::BrokenSince)
+            .limit(SECTION_LIMIT)
+            .find_also_related(links::Entity)
+            .all(self.dbconn.as_ref())
+            .await?
+            .into_iter()
+            .filter_map(|(health, link)| {
+                let reason = match health.status_code {
+                    Some(code) => format!("HTTP {code}"),
+                    None => health.error.unwrap_or_default(),
+                };
+                Some(self.entry(&link?, health.broken_since?, Some(reason)))
+            })
+            .collect())
+    }
+
+    // the digest as it's handed to the template, if there's anything in it
+    async fn compose(
+        &self,
+        settings: &digest_settings::Model,
+        user: &users::Model,
+        frequency: Frequency,
+    ) -> Result<Option<Context>> {
+        let since = settings
+            .last_sent_at
+            .unwrap_or_else(|| (Utc::now() - frequency.period()).into());
+
+        let unread = self.unread(settings).await?;
+        let shared = self.shared(&user.id, since).await?;
+        let broken = self.broken(&user.id, since).await?;
+        // nothing to tell, so nothing is sent
+        if unread.is_empty() && shared.is_empty() && broken.is_empty() {
+            return Ok(None);
+        }
+
+        let mut ctx = Context::new();
+        ctx.insert("login", &user.login);
+        ctx.insert("frequency", frequency.as_str());
+        ctx.insert("unread", &unread);
+        ctx.insert("shared", &shared);
+        ctx.insert("broken", &broken);
+        ctx.insert(
+            "settings_url",
+            &self.cfg.public_url("digest").map(|url| url.to_string()),
+        );
+
+        Ok(Some(ctx))
+    }
+
+    // sends the digest that's due and queues the next one, a job for
+    // settings that have changed since it was queued does nothing, and of
+    // jobs queued for the same time only the first one sends anything
+    pub async fn send(&self, queue: &Queue, user_id: &UserId) -> Result<()> {
+        let settings = match digest_settings::Entity::find_by_id(user_id.clone())
+            .one(self.dbconn.as_ref())
+            .await?
+        {
+            Some(settings) => settings,
+            None => return Ok(()),
+        };
+        let frequency: Frequency = settings.frequency.parse()?;
+        let now = Utc::now();
+        let due = match settings.next_due_at {
+            Some(due) if frequency != Frequency::Off && due <= now => due,
+            _ => return Ok(()),
+        };
+        // moving the due date on claims the digest, whoever finds it moved
+        // already has nothing left to send
+        let next = next_due(&settings, now)?;
+        let claimed = digest_settings::Entity::update_many()
+            .col_expr(
+                digest_settings::Column::NextDueAt,
+                Expr::value(next.map(DateTimeWithTimeZone::from)),
+            )
+            .filter(digest_settings::Column::UserId.eq(user_id.clone()))
+            .filter(digest_settings::Column::NextDueAt.eq(due))
+            .exec(self.dbconn.as_ref())
+            .await?;
+        if claimed.rows_affected == 0 {
+            return Ok(());
+        }
+        // the next one is queued first, retries of this job find the digest
+        // claimed, so failing to send this one mustn't end the ones after it
+        if let Some(due) = next {
+            queue
+                .push_at(
+                    Job::SendDigest {
+                        user_id: user_id.clone(),
+                    },
+                    due.into(),
+                )
+                .await?;
+        }
+
+        let mut sent = false;
+        let user = users::Entity::find_by_id(user_id.clone())
+            .one(self.dbconn.as_ref())
+            .await?;
+        match &user {
+            _ if !self.outbox.is_configured() => {
+                warn!("digest of {user_id} skipped, smtp is not configured")
+            }
+            Some(
+                found @ users::Model {
+                    email: Some(email),
+                    email_verified: true,
+                    ..
+                },
+            ) => {
+                if let Some(ctx) = self.compose(&settings, found, frequency).await? {
+                    let subject = format!("Your {frequency} backlog digest");
+                    self.outbox
+                        .send(queue, email, &subject, "digest", &ctx)
+                        .await?;
+                    sent = true;
+                }
+            }
+            Some(_) => info!("digest of {user_id} skipped, no verified email"),
+            None => warn!("digest of {user_id} skipped, the user is not known"),
+        }
+
+        if sent {
+            digest_settings::Entity::update_many()
+                .col_expr(
+                    digest_settings::Column::LastSentAt,
+                    Expr::value(DateTimeWithTimeZone::from(now)),
+                )
+                .filter(digest_settings::Column::UserId.eq(user_id.clone()))
+                .exec(self.dbconn.as_ref())
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(frequency: &str, weekday: i16, hour: i16) -> digest_settings::Model {
+        digest_settings::Model {
+            user_id: "user".to_string(),
+            frequency: frequency.to_string(),
+            weekday,
+            hour,
+            timezone: "Europe/Berlin".to_string(),
+            link_count: 5,
+            pick: "oldest".to_string(),
+            last_sent_at: None,
+            next_due_at: None,
+            date_created: Utc::now().into(),
+            modified_at: None,
+        }
+    }
+
+    fn at(utc: &str) -> DateTime<Utc> {
+        utc.parse().unwrap()
+    }
+
+    fn due(settings: &digest_settings::Model, after: &str) -> Option<DateTime<Utc>> {
+        next_due(settings, at(after)).unwrap()
+    }
+
+    #[test]
+    fn daily_keeps_the_local_hour_across_clock_changes() {
+        let daily = settings("daily", 0, 8);
+        // 08:00 in winter is 07:00 utc, in summer 06:00
+        assert_eq!(
+            due(&daily, "2022-03-26T07:00:00Z"),
+            Some(at("2022-03-27T06:00:00Z"))
+        );
+        assert_eq!(
+            due(&daily, "2022-10-29T06:00:00Z"),
+            Some(at("2022-10-30T07:00:00Z"))
+        );
+        // not yet due today
+        assert_eq!(
+            due(&daily, "2022-06-01T05:59:00Z"),
+            Some(at("2022-06-01T06:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn skipped_hour_is_made_up_an_hour_later() {
+        // 02:00 doesn't happen on the day the clocks go forward
+        let daily = settings("daily", 0, 2);
+        assert_eq!(
+            due(&daily, "2022-03-26T02:00:00Z"),
+            Some(at("2022-03-27T01:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn repeated_hour_is_only_due_once() {
+        // 02:00 happens twice on the day the clocks go back, the first counts
+        let daily = settings("daily", 0, 2);
+        assert_eq!(
+            due(&daily, "2022-10-29T12:00:00Z"),
+            Some(at("2022-10-30T00:00:00Z"))
+        );
+        assert_eq!(
+            due(&daily, "2022-10-30T00:00:00Z"),
+            Some(at("2022-10-31T01:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn weekly_waits_for_the_weekday() {
+        // mondays at 09:00
+        let weekly = settings("weekly", 0, 9);
+        assert_eq!(
+            due(&weekly, "2022-03-25T12:00:00Z"),
+            Some(at("2022-03-28T07:00:00Z"))
+        );
+        assert_eq!(
+            due(&weekly, "2022-03-28T07:00:00Z"),
+            Some(at("2022-04-04T07:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn off_is_never_due() {
+        assert_eq!(due(&settings("off", 0, 9), "2022-03-25T12:00:00Z"), None);
+    }
+}
@@ -0,0 +1,276 @@
+//! Checking whether saved links still lead anywhere, along with a history of
+//! every check so a short outage can be told apart from a page that's gone.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use {
+    anyhow::Result,
+    chrono::Utc,
+    reqwest::{Method, Response, StatusCode, Url},
+    sea_orm::{
+        entity::{prelude::*, Set},
+        sea_query::Expr,
+        Condition, DatabaseConnection, QuerySelect, QueryTrait,
+    },
+    ulid::Ulid,
+};
+
+use crate::{
+    api::links::LinkId,
+    database::entity::{link_checks, link_health, links},
+    jobs::{Job, Queue},
+    net::{Client, Guard},
+};
+
+// healthy links are looked at again after a week, broken ones every day so
+// it's noticed quickly when they come back
+const HEALTHY_INTERVAL: i64 = 7;
+const BROKEN_INTERVAL: i64 = 1;
+// how often the links that are due get put in the queue, and how many at once
+pub const SWEEP_INTERVAL: i64 = 60 * 60;
+const SWEEP_SIZE: u64 = 500;
+// checks against the same host are spread out by this many seconds
+const HOST_INTERVAL: i64 = 10;
+const TIMEOUT: u64 = 15;
+const REDIRECTS: usize = 10;
+
+// what came back from the link's address
+struct Outcome {
+    status_code: Option<StatusCode>,
+    final_url: Option<String>,
+    error: Option<String>,
+}
+
+impl Outcome {
+    // sites that turn away bots or ask for a login are still there, and being
+    // rate limited doesn't say anything about the page either
+    fn is_broken(&self) -> bool {
+        match self.status_code {
+            Some(
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS,
+            ) => false,
+            Some(code) => code.is_client_error() || code.is_server_error(),
+            None => self.error.is_some(),
+        }
+    }
+}
+
+pub struct LinkChecker {
+    client: Client,
+    dbconn: Arc<DatabaseConnection>,
+}
+
+impl LinkChecker {
+    pub fn new(guard: &Arc<Guard>, dbconn: Arc<DatabaseConnection>) -> Result<Self> {
+        let client = guard.client(Duration::from_secs(TIMEOUT), REDIRECTS)?;
+
+        Ok(Self { client, dbconn })
+    }
+
+    async fn request(&self, method: Method, url: &Url) -> Result<Response> {
+        Ok(self.client.request(method, url.clone())?.send().await?)
+    }
+
+    // asks for the headers only, plenty of servers answer that wrong though,
+    // so anything but success is asked again for the whole page
+    async fn probe(&self, url: &Url) -> Outcome {
+        let resp = match self.request(Method::HEAD, url).await {
+            Ok(resp) if resp.status().is_success() => Ok(resp),
+            _ => self.request(Method::GET, url).await,
+        };
+
+        match resp {
+            Ok(resp) => Outcome {
+                status_code: Some(resp.status()),
+                final_url: Some(resp.url().to_string()).filter(|u| u != url.as_str()),
+                error: None,
+            },
+            Err(e) => Outcome {
+                status_code: None,
+                final_url: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+
+    pub async fn check(&self, link_id: &LinkId) -> Result<()> {
+        // links that were deleted since being queued aren't worth checking
+        let link = match links::Entity::find_by_id(link_id.to_string())
+            .filter(links::Column::DeletedAt.is_null())
+            .one(self.dbconn.as_ref())
+            .await?
+        {
+            Some(link) => link,
+            None => return Ok(()),
+        };
+        // only web pages can be checked, other links like ftp ones would look
+        // broken every time
+        let url: Url = link.url.parse()?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Ok(());
+        }
+        let outcome = self.probe(&url).await;
+        let broken = outcome.is_broken();
+        let status_code = outcome.status_code.map(|c| c.as_u16() as i32);
+        let now = Utc::now();
+
+        link_checks::Entity::insert(link_checks::ActiveModel {
+            id: Set(Ulid::new().to_string()),
+            link_id: Set(link.id.clone()),
+            status_code: Set(status_code),
+            final_url: Set(outcome.final_url.clone()),
+            error: Set(outcome.error.clone()),
+            broken: Set(broken),
+            checked_at: Set(now.into()),
+        })
+        .exec(self.dbconn.as_ref())
+        .await?;
+
+        let previous = link_health::Entity::find_by_id(link.id.clone())
+            .one(self.dbconn.as_ref())
+            .await?;
+        // a link stays broken since the first check that failed in a row
+        let broken_since = match &previous {
+            _ if !broken => None,
+            Some(previous) if previous.broken => previous.broken_since,
+            _ => Some(now.into()),
+        };
+        let last_ok_at = if broken {
+            previous.as_ref().and_then(|p| p.last_ok_at)
+        } else {
+            Some(now.into())
+        };
+        let interval = if broken {
+            BROKEN_INTERVAL
+        } else {
+            HEALTHY_INTERVAL
+        };
+
+        let health = link_health::ActiveModel {
+            link_id: Set(link.id),
+            status_code: Set(status_code),
+            final_url: Set(outcome.final_url),
+            error: Set(outcome.error),
+            broken: Set(broken),
+            broken_since: Set(broken_since),
+            last_ok_at: Set(last_ok_at),
+            checked_at: Set(Some(now.into())),
+            next_check_at: Set((now + chrono::Duration::days(interval)).into()),
+        };
+        match previous {
+            Some(_) => {
+                health.update(self.dbconn.as_ref()).await?;
+            }
+            None => {
+                link_health::Entity::insert(health)
+                    .exec(self.dbconn.as_ref())
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// puts the links that are due for a check into the queue, checks of links on
+// the same host are spaced out so no site gets hammered
+pub async fn schedule(dbconn: &DatabaseConnection, queue: &Queue) -> Result<()> {
+    let now = Utc::now();
+    let due = links::Entity::find()
+        .filter(links::Column::DeletedAt.is_null())
+        .filter(
+            Condition::any()
+                .add(links::Column::Url.starts_with("http://"))
+                .add(links::Column::Url.starts_with("https://")),
+        )
+        .filter(
+            Condition::any()
+                .add(
+                    links::Column::Id.not_in_subquery(
+                        link_health::Entity::find()
+                            .select_only()
+                            .column(link_health::Column::LinkId)
+                            .into_query(),
+                    ),
+                )
+                .add(
+                    links::Column::Id.in_subquery(
+                        link_health::Entity::find()
+                            .select_only()
+                            .column(link_health::Column::LinkId)
+                            .filter(link_health::Column::NextCheckAt.lte(now))
+                            .into_query(),
+                    ),
+                ),
+        )
+        .limit(SWEEP_SIZE)
+        .all(dbconn)
+        .await?;
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    // pushed back right away, so the next sweep doesn't queue them again
+    // while they're still waiting for their turn
+    let ids: Vec<String> = due.iter().map(|l| l.id.clone()).collect();
+    let pending = now + chrono::Duration::days(HEALTHY_INTERVAL);
+    let tracked: Vec<String> = link_health::Entity::find()
+        .filter(link_health::Column::LinkId.is_in(ids.clone()))
+        .all(dbconn)
+        .await?
+        .into_iter()
+        .map(|h| h.link_id)
+        .collect();
+    link_health::Entity::update_many()
+        .col_expr(
+            link_health::Column::NextCheckAt,
+            Expr::value(DateTimeWithTimeZone::from(pending)),
+        )
+        .filter(link_health::Column::LinkId.is_in(tracked.clone()))
+        .exec(dbconn)
+        .await?;
+    let untracked: Vec<link_health::ActiveModel> = ids
+        .iter()
+        .filter(|id| !tracked.contains(id))
+        .map(|id| link_health::ActiveModel {
+            link_id: Set(id.clone()),
+            status_code: Set(None),
+            final_url: Set(None),
+            error: Set(None),
+            broken: Set(false),
+            broken_since: Set(None),
+            last_ok_at: Set(None),
+            checked_at: Set(None),
+            next_check_at: Set(pending.into()),
+        })
+        .collect();
+    if !untracked.is_empty() {
+        link_health::Entity::insert_many(untracked)
+            .exec(dbconn)
+            .await?;
+    }
+
+    let mut per_host: HashMap<String, i64> = HashMap::new();
+    for link in due {
+        let host = link
+            .url
+            .parse::<Url>()
+            .ok()
+            .and_then(|u| u.host_str().map(String::from))
+            .unwrap_or_default();
+        let turn = per_host.entry(host).or_default();
+        let run_at = now + chrono::Duration::seconds(*turn * HOST_INTERVAL);
+        *turn += 1;
+
+        queue
+            .push_at(
+                Job::CheckLink {
+                    link_id: link.id.parse()?,
+                },
+                run_at.into(),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
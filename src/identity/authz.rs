@@ -1,13 +1,16 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use {
-    sea_orm::{entity::prelude::*, DatabaseConnection},
+    chrono::Utc,
+    rand::{distributions::Alphanumeric, rngs::OsRng, Rng},
+    sea_orm::{entity::prelude::*, DatabaseConnection, QueryOrder, Set},
     tracing::warn,
 };
 
 use crate::{
+    api::{groups::GroupId, members::Role, UserId},
     app::{Sessions, User},
-    database::entity::api_keys,
+    database::entity::{api_keys, group_members, groups, users},
     pages::apikeys::APIKey,
 };
 
@@ -45,3 +48,160 @@ pub async fn valid_api_key(dbconn: Arc<DatabaseConnection>, key: String) -> Opti
         }
     }
 }
+
+// returns the role the user holds in a group, owners being whoever created it
+pub async fn group_role(
+    dbconn: Arc<DatabaseConnection>,
+    user_id: &UserId,
+    group_id: &GroupId,
+) -> Result<Option<Role>, DbErr> {
+    let group = match groups::Entity::find_by_id(group_id.to_string())
+        .filter(groups::Column::DeletedAt.is_null())
+        .one(dbconn.as_ref())
+        .await?
+    {
+        Some(group) => group,
+        None => return Ok(None),
+    };
+    if &group.created_by == user_id {
+        return Ok(Some(Role::Owner));
+    }
+
+    let membership = group_members::Entity::find()
+        .filter(group_members::Column::GroupId.eq(group.id))
+        .filter(group_members::Column::UserId.eq(user_id.clone()))
+        .filter(group_members::Column::DeletedAt.is_null())
+        .one(dbconn.as_ref())
+        .await?;
+    Ok(membership.and_then(|m| match m.role.parse() {
+        Ok(role) => Some(role),
+        Err(e) => {
+            warn!("group membership from database has a malformed role: {e}");
+            None
+        }
+    }))
+}
+
+// returns every group the user can see, keyed by the group's id, along with
+// the role they hold in it
+pub async fn group_roles(
+    dbconn: Arc<DatabaseConnection>,
+    user_id: &UserId,
+) -> Result<HashMap<String, Role>, DbErr> {
+    let mut roles = HashMap::new();
+    for membership in group_members::Entity::find()
+        .filter(group_members::Column::UserId.eq(user_id.clone()))
+        .filter(group_members::Column::DeletedAt.is_null())
+        .all(dbconn.as_ref())
+        .await?
+    {
+        match membership.role.parse() {
+            Ok(role) => {
+                roles.insert(membership.group_id, role);
+            }
+            Err(e) => warn!("group membership from database has a malformed role: {e}"),
+        }
+    }
+    for group in groups::Entity::find()
+        .filter(groups::Column::CreatedBy.eq(user_id.clone()))
+        .all(dbconn.as_ref())
+        .await?
+    {
+        roles.insert(group.id, Role::Owner);
+    }
+
+    // memberships of groups that have since been deleted don't count
+    let deleted = groups::Entity::find()
+        .filter(groups::Column::Id.is_in(roles.keys().cloned().collect::<Vec<_>>()))
+        .filter(groups::Column::DeletedAt.is_not_null())
+        .all(dbconn.as_ref())
+        .await?;
+    for group in deleted {
+        roles.remove(&group.id);
+    }
+
+    Ok(roles)
+}
+
+// keeps a record of everyone who signs in, so that invitations and other
+// lookups by email have something to resolve against
+pub async fn remember_user(dbconn: Arc<DatabaseConnection>, user: &User) -> Result<(), DbErr> {
+    let email = user.email.as_ref().map(|e| e.trim().to_lowercase());
+    match users::Entity::find_by_id(user.id.clone())
+        .one(dbconn.as_ref())
+        .await?
+    {
+        Some(existing) => {
+            let mut existing: users::ActiveModel = existing.into();
+            existing.login = Set(user.login.clone());
+            existing.email = Set(email);
+            existing.email_verified = Set(user.activated);
+            existing.modified_at = Set(Some(Utc::now().into()));
+            existing.update(dbconn.as_ref()).await?;
+        }
+        None => {
+            users::Entity::insert(users::ActiveModel {
+                id: Set(user.id.clone()),
+                login: Set(user.login.clone()),
+                email: Set(email),
+                email_verified: Set(user.activated),
+                date_created: Set(Utc::now().into()),
+                modified_at: Set(None),
+            })
+            .exec(dbconn.as_ref())
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+// hands pending email invitations over to the user once they've signed in
+// with that address and it has been verified
+pub async fn claim_invitations(dbconn: Arc<DatabaseConnection>, user: &User) -> Result<(), DbErr> {
+    let email = match (&user.email, user.activated) {
+        (Some(email), true) => email.trim().to_lowercase(),
+        _ => return Ok(()),
+    };
+
+    // one at a time, a user can only be in a group once, so invitations to
+    // groups they're already in are dropped instead of failing all the others
+    let mut joined: Vec<String> = group_members::Entity::find()
+        .filter(group_members::Column::UserId.eq(user.id.clone()))
+        .filter(group_members::Column::DeletedAt.is_null())
+        .all(dbconn.as_ref())
+        .await?
+        .into_iter()
+        .map(|membership| membership.group_id)
+        .collect();
+    for invitation in group_members::Entity::find()
+        .filter(group_members::Column::Email.eq(email))
+        .filter(group_members::Column::UserId.is_null())
+        .filter(group_members::Column::DeletedAt.is_null())
+        .order_by_asc(group_members::Column::DateCreated)
+        .all(dbconn.as_ref())
+        .await?
+    {
+        let group_id = invitation.group_id.clone();
+        let mut der: group_members::ActiveModel = invitation.into();
+        if joined.contains(&group_id) {
+            der.deleted_at = Set(Some(Utc::now().into()));
+        } else {
+            der.user_id = Set(Some(user.id.clone()));
+            joined.push(group_id);
+        }
+        der.update(dbconn.as_ref()).await?;
+    }
+
+    Ok(())
+}
+
+// generates a token for links that grant access without signing in, these
+// come straight from the operating system so they can't be guessed
+pub fn secret_token(amt: usize) -> String {
+    OsRng
+        .sample_iter(&Alphanumeric)
+        .take(amt)
+        .map(char::from)
+        .collect::<String>()
+}
@@ -0,0 +1,443 @@
+//! Just enough of mime to get at the sender, the subject and the text of an
+//! email, including emails that were forwarded as attachments, along with
+//! what the receiving mail server found out about where it came from.
+
+use std::collections::HashMap;
+
+use {
+    encoding_rs::Encoding,
+    scraper::{Html, Selector},
+};
+
+// multiparts nested deeper than this are left alone
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Default)]
+pub struct Message {
+    pub from: Option<String>,
+    pub subject: Option<String>,
+    // every Authentication-Results header, newest first
+    pub authentication: Vec<String>,
+    pub texts: Vec<String>,
+    pub htmls: Vec<String>,
+}
+
+struct Part<'a> {
+    headers: HashMap<String, String>,
+    // the headers that can appear more than once and all matter
+    results: Vec<String>,
+    body: &'a [u8],
+}
+
+fn split_part(raw: &[u8]) -> Part<'_> {
+    let (head, body) = match find(raw, b"\r\n\r\n") {
+        Some(at) => (&raw[..at], &raw[at + 4..]),
+        None => match find(raw, b"\n\n") {
+            Some(at) => (&raw[..at], &raw[at + 2..]),
+            None => (raw, &raw[raw.len()..]),
+        },
+    };
+
+    // lines starting with whitespace carry on the header before them, only
+    // the first of headers that appear twice is kept
+    let mut unfolded: Vec<(String, String)> = Vec::new();
+    for line in String::from_utf8_lossy(head).lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = unfolded.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            unfolded.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let mut headers = HashMap::new();
+    let mut results = Vec::new();
+    for (name, value) in unfolded {
+        if name == "authentication-results" {
+            results.push(value.clone());
+        }
+        headers.entry(name).or_insert(value);
+    }
+
+    Part {
+        headers,
+        results,
+        body,
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// the value of a header like content-type, along with it's parameters
+fn parameters(value: &str) -> (String, HashMap<String, String>) {
+    let mut pieces = value.split(';');
+    let kind = pieces.next().unwrap_or_default().trim().to_lowercase();
+    let params = pieces
+        .filter_map(|piece| piece.split_once('='))
+        .map(|(name, value)| {
+            (
+                name.trim().to_lowercase(),
+                value.trim().trim_matches('"').to_string(),
+            )
+        })
+        .collect();
+    (kind, params)
+}
+
+fn decode_base64(body: &[u8]) -> Vec<u8> {
+    let cleaned: Vec<u8> = body
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    base64::decode(cleaned).unwrap_or_default()
+}
+
+fn decode_quoted_printable(body: &[u8], underscores: bool) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(body.len());
+    let mut i = 0;
+    while i < body.len() {
+        match body[i] {
+            b'=' if body[i + 1..].starts_with(b"\r\n") => i += 3,
+            b'=' if body[i + 1..].starts_with(b"\n") => i += 2,
+            b'=' if i + 2 < body.len() => {
+                match u8::from_str_radix(&String::from_utf8_lossy(&body[i + 1..i + 3]), 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 3;
+                    }
+                    Err(_) => {
+                        decoded.push(b'=');
+                        i += 1;
+                    }
+                }
+            }
+            b'_' if underscores => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+// text in whatever charset it came in, utf-8 when it doesn't say
+fn decode_charset(bytes: &[u8], charset: Option<&str>) -> String {
+    let encoding = charset
+        .and_then(|c| Encoding::for_label(c.as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    encoding.decode(bytes).0.into_owned()
+}
+
+// headers can hold words like =?utf-8?B?...?=, whitespace between two of
+// them doesn't count
+fn decode_words(value: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let word = rest[start + 2..].splitn(3, '?').collect::<Vec<_>>();
+        let (charset, encoding, text) = match word.as_slice() {
+            [charset, encoding, text] => (*charset, *encoding, *text),
+            _ => break,
+        };
+        let end = match text.find("?=") {
+            Some(end) => end,
+            None => break,
+        };
+        let between = &rest[..start];
+        if !(after_word && between.trim().is_empty()) {
+            decoded.push_str(between);
+        }
+        let bytes = match encoding.to_ascii_uppercase().as_str() {
+            "B" => decode_base64(&text.as_bytes()[..end]),
+            _ => decode_quoted_printable(&text.as_bytes()[..end], true),
+        };
+        decoded.push_str(&decode_charset(&bytes, Some(charset)));
+        rest = &text[end + 2..];
+        after_word = true;
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+// the bare address out of "Name <address>"
+fn address(mailbox: &str) -> Option<String> {
+    let address = match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox,
+    };
+    let address = address.trim().to_lowercase();
+    match address.contains('@') {
+        true => Some(address),
+        false => None,
+    }
+}
+
+fn walk(raw: &[u8], message: &mut Message, depth: usize) {
+    let part = split_part(raw);
+    let (kind, params) = parameters(
+        part.headers
+            .get("content-type")
+            .map(String::as_str)
+            .unwrap_or("text/plain"),
+    );
+    let attachment = part
+        .headers
+        .get("content-disposition")
+        .map(|d| d.to_lowercase().starts_with("attachment"))
+        .unwrap_or_default();
+
+    if kind.starts_with("multipart/") {
+        let boundary = match params.get("boundary") {
+            Some(boundary) if depth < MAX_DEPTH => format!("--{boundary}"),
+            _ => return,
+        };
+        let body = String::from_utf8_lossy(part.body);
+        // the preamble before the first boundary and everything after the
+        // closing one are skipped
+        for section in body.split(&boundary).skip(1) {
+            if section.starts_with("--") {
+                break;
+            }
+            walk(
+                section.trim_start_matches(['\r', '\n']).as_bytes(),
+                message,
+                depth + 1,
+            );
+        }
+        return;
+    }
+
+    let body = match part
+        .headers
+        .get("content-transfer-encoding")
+        .map(|e| e.to_lowercase())
+        .as_deref()
+    {
+        Some("base64") => decode_base64(part.body),
+        Some("quoted-printable") => decode_quoted_printable(part.body, false),
+        _ => part.body.to_vec(),
+    };
+    // forwarded emails often come along as attachments of their own
+    if kind == "message/rfc822" && depth < MAX_DEPTH {
+        walk(&body, message, depth + 1);
+        return;
+    }
+    if attachment {
+        return;
+    }
+    let text = decode_charset(&body, params.get("charset").map(String::as_str));
+    match kind.as_str() {
+        "text/plain" => message.texts.push(text),
+        "text/html" => message.htmls.push(text),
+        _ => (),
+    }
+}
+
+pub fn parse(raw: &[u8]) -> Message {
+    let top = split_part(raw);
+    let mut message = Message {
+        from: top.headers.get("from").and_then(|from| address(from)),
+        subject: top
+            .headers
+            .get("subject")
+            .map(|subject| decode_words(subject))
+            .filter(|subject| !subject.trim().is_empty()),
+        authentication: top.results,
+        ..Message::default()
+    };
+    walk(raw, &mut message, 0);
+    message
+}
+
+// comments in headers are put in parentheses, and can be nested
+fn strip_comments(value: &str) -> String {
+    let mut stripped = String::with_capacity(value.len());
+    let mut depth = 0usize;
+    for c in value.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            c if depth == 0 => stripped.push(c),
+            _ => (),
+        }
+    }
+    stripped
+}
+
+// whether a mail server calling itself authserv_id vouched for the domain of
+// the From header, either by a passing dmarc check or by a passing dkim
+// signature of that very domain, only the topmost results count since the
+// server in front just added them, the ones below can be written by anyone
+pub fn authenticated(message: &Message, authserv_id: &str) -> bool {
+    let domain = match message.from.as_deref().and_then(|f| f.rsplit_once('@')) {
+        Some((_, domain)) => domain.to_lowercase(),
+        None => return false,
+    };
+    let header = match message.authentication.first() {
+        Some(header) => strip_comments(header).to_lowercase(),
+        None => return false,
+    };
+
+    let mut results = header.split(';');
+    // the id can be followed by a version
+    let id = results.next().unwrap_or_default();
+    if id.split_whitespace().next() != Some(authserv_id) {
+        return false;
+    }
+
+    results.any(|result| {
+        let mut pieces = result.split_whitespace();
+        let method = pieces.next().unwrap_or_default();
+        let properties: HashMap<&str, &str> =
+            pieces.filter_map(|piece| piece.split_once('=')).collect();
+        match method {
+            "dmarc=pass" => properties.get("header.from") == Some(&domain.as_str()),
+            "dkim=pass" => properties.get("header.d") == Some(&domain.as_str()),
+            _ => false,
+        }
+    })
+}
+
+// forwarding puts "Fwd:" in front of the subject, which says nothing about
+// the links
+pub fn strip_forward(subject: &str) -> &str {
+    let mut subject = subject.trim();
+    loop {
+        let lower = subject.to_lowercase();
+        match ["fwd:", "fw:"]
+            .iter()
+            .find(|prefix| lower.starts_with(*prefix))
+        {
+            Some(prefix) => subject = subject[prefix.len()..].trim_start(),
+            None => return subject,
+        }
+    }
+}
+
+// characters that end an address written out in text, along with those
+// that are more likely punctuation after it than part of it
+const URL_END: &[char] = &['<', '>', '"', '\'', '`', '[', ']', '{', '}', '|', '\\', '^'];
+const URL_TRAILING: &[char] = &['.', ',', ';', ':', '!', '?', ')', '*'];
+
+fn text_urls(text: &str, urls: &mut Vec<String>) {
+    let mut rest = text;
+    while let Some(start) = ["http://", "https://"]
+        .iter()
+        .filter_map(|scheme| rest.find(scheme))
+        .min()
+    {
+        let candidate = &rest[start..];
+        let end = candidate
+            .find(|c: char| c.is_whitespace() || URL_END.contains(&c))
+            .unwrap_or(candidate.len());
+        urls.push(candidate[..end].trim_end_matches(URL_TRAILING).to_string());
+        rest = &candidate[end..];
+    }
+}
+
+fn html_urls(html: &str, urls: &mut Vec<String>) {
+    let document = Html::parse_document(html);
+    let anchors = Selector::parse("a[href]").unwrap();
+    for anchor in document.select(&anchors) {
+        if let Some(href) = anchor.value().attr("href") {
+            let href = href.trim();
+            if href.starts_with("http://") || href.starts_with("https://") {
+                urls.push(href.to_string());
+            }
+        }
+    }
+}
+
+// every web address in the email, in the order they appear, links that
+// stop the newsletter are left out
+pub fn urls(message: &Message) -> Vec<String> {
+    let mut urls = Vec::new();
+    for html in &message.htmls {
+        html_urls(html, &mut urls);
+    }
+    for text in &message.texts {
+        text_urls(text, &mut urls);
+    }
+
+    let mut seen = Vec::new();
+    urls.retain(|url| {
+        let keep = !url.to_lowercase().contains("unsubscribe") && !seen.contains(url);
+        seen.push(url.clone());
+        keep
+    });
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(headers: &str) -> Message {
+        parse(format!("{headers}\r\n\r\nhttps://example.com/\r\n").as_bytes())
+    }
+
+    #[test]
+    fn trusts_results_of_the_configured_server() {
+        let dmarc = message(
+            "Authentication-Results: mx.backlog.example;\r\n dmarc=pass (p=none) header.from=example.com\r\nFrom: Someone <someone@Example.com>",
+        );
+        assert!(authenticated(&dmarc, "mx.backlog.example"));
+
+        let dkim = message(
+            "Authentication-Results: MX.backlog.example 1; spf=fail; dkim=pass header.d=example.com header.s=s1\r\nFrom: someone@example.com",
+        );
+        assert!(authenticated(&dkim, "mx.backlog.example"));
+    }
+
+    #[test]
+    fn refuses_everything_else() {
+        let refused = [
+            // no results at all
+            "From: someone@example.com",
+            // results another server put in
+            "Authentication-Results: mx.elsewhere.example; dmarc=pass header.from=example.com\r\nFrom: someone@example.com",
+            // signed, but by some other domain
+            "Authentication-Results: mx.backlog.example; dkim=pass header.d=attacker.example\r\nFrom: someone@example.com",
+            // checked, but failed
+            "Authentication-Results: mx.backlog.example; dmarc=fail header.from=example.com\r\nFrom: someone@example.com",
+            // a passing result hidden in a comment
+            "Authentication-Results: mx.backlog.example; dmarc=fail (dmarc=pass header.from=example.com) header.from=example.com\r\nFrom: someone@example.com",
+        ];
+        for headers in refused {
+            assert!(
+                !authenticated(&message(headers), "mx.backlog.example"),
+                "{} was trusted",
+                headers
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_results_the_sender_wrote() {
+        // the server in front adds it's results on top, a passing one further
+        // down came with the message
+        let forged = message(
+            "Authentication-Results: mx.backlog.example; dmarc=fail header.from=victim.example\r\nAuthentication-Results: mx.backlog.example; dmarc=pass header.from=victim.example\r\nFrom: someone@victim.example",
+        );
+        assert!(!authenticated(&forged, "mx.backlog.example"));
+
+        let unchecked = message(
+            "Authentication-Results: mx.backlog.example; none\r\nAuthentication-Results: mx.backlog.example; dkim=pass header.d=victim.example\r\nFrom: someone@victim.example",
+        );
+        assert!(!authenticated(&unchecked, "mx.backlog.example"));
+    }
+}
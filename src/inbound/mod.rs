@@ -0,0 +1,386 @@
+//! Saving links by email. A small smtp listener takes emails sent to the
+//! configured address and saves every link in them to the backlog of the
+//! user the sender's verified address belongs to, as long as the mail server
+//! in front of it confirmed the sender.
+
+mod message;
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use {
+    anyhow::{anyhow, Result},
+    sea_orm::{entity::prelude::*, sea_query::Expr, DatabaseConnection},
+    tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+        time::timeout,
+    },
+    tracing::{debug, error, info, warn},
+};
+
+use crate::{
+    api::links::LinkId,
+    config,
+    database::entity::users,
+    interchange::import::{ImportSession, ImportedLink},
+    jobs::Queue,
+    normalize::Normalizer,
+    webhooks,
+};
+
+// used when the configuration doesn't say otherwise
+const MAX_SIZE: usize = 10 * 1024 * 1024;
+// commands are short, anything longer is someone misbehaving
+const MAX_COMMAND: u64 = 1024;
+// lines of the message itself should stay under 1000 bytes, but not every
+// mail program sticks to that
+const MAX_LINE: u64 = 64 * 1024;
+// a newsletter can link to a lot of places, a message with more than this
+// is unlikely to be one worth saving all of
+const MAX_LINKS: usize = 100;
+// how long a client may stay quiet before it's hung up on
+const TIMEOUT: u64 = 5 * 60;
+// clients that keep getting things wrong are hung up on too
+const MAX_ERRORS: usize = 10;
+
+// what's said back to the client, an smtp code and it's text
+struct Reply(u16, String);
+
+impl Reply {
+    fn new(code: u16, text: impl Into<String>) -> Self {
+        Self(code, text.into())
+    }
+}
+
+pub struct Inbox {
+    listen: SocketAddr,
+    address: String,
+    authserv_id: String,
+    group: Option<String>,
+    max_size: usize,
+    dbconn: Arc<DatabaseConnection>,
+    normalizer: Arc<Normalizer>,
+    queue: Arc<Queue>,
+    snapshots: bool,
+}
+
+impl Inbox {
+    pub fn new(
+        cfg: &config::Inbound,
+        dbconn: Arc<DatabaseConnection>,
+        normalizer: Arc<Normalizer>,
+        queue: Arc<Queue>,
+        snapshots: bool,
+    ) -> Self {
+        Self {
+            listen: cfg.listen,
+            address: cfg.address.trim().to_lowercase(),
+            authserv_id: cfg.authserv_id.trim().to_lowercase(),
+            group: cfg.group.clone().filter(|g| !g.trim().is_empty()),
+            max_size: cfg.max_size.unwrap_or(MAX_SIZE),
+            dbconn,
+            normalizer,
+            queue,
+            snapshots,
+        }
+    }
+
+    // binds right away, so an address that's taken fails the start of the
+    // application instead of going unnoticed
+    pub async fn start(self: &Arc<Self>) -> Result<()> {
+        let listener = TcpListener::bind(self.listen).await?;
+        info!("accepting email for {} on {}", self.address, self.listen);
+
+        let inbox = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("could not accept an smtp connection: {e}");
+                        continue;
+                    }
+                };
+                let inbox = inbox.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = inbox.converse(stream).await {
+                        debug!("smtp connection from {peer} ended: {e:#}");
+                    }
+                });
+            }
+        });
+
+        Ok(())
+    }
+
+    // the domain of the accepted address, what the listener calls itself
+    fn hostname(&self) -> &str {
+        self.address
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or("localhost")
+    }
+
+    async fn converse(&self, stream: TcpStream) -> Result<()> {
+        let mut stream = BufReader::new(stream);
+        let mut greeted = false;
+        let mut sender = false;
+        let mut recipient = false;
+        let mut errors = 0;
+
+        write(
+            &mut stream,
+            &Reply::new(220, format!("{} ESMTP ready", self.hostname())),
+        )
+        .await?;
+        loop {
+            let line = read_line(&mut stream, MAX_COMMAND).await?;
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end();
+            let (verb, argument) = line.split_once(' ').unwrap_or((line, ""));
+
+            let reply = match verb.to_uppercase().as_str() {
+                "EHLO" => {
+                    greeted = true;
+                    sender = false;
+                    recipient = false;
+                    write_raw(
+                        &mut stream,
+                        &format!(
+                            "250-{}\r\n250-SIZE {}\r\n250-8BITMIME\r\n250 PIPELINING\r\n",
+                            self.hostname(),
+                            self.max_size
+                        ),
+                    )
+                    .await?;
+                    continue;
+                }
+                "HELO" => {
+                    greeted = true;
+                    sender = false;
+                    recipient = false;
+                    Reply::new(250, self.hostname())
+                }
+                "MAIL" if !greeted => Reply::new(503, "5.5.1 say hello first"),
+                "MAIL" => match parameter(argument, "FROM:") {
+                    Some(_) if self.too_big(argument) => {
+                        Reply::new(552, "5.3.4 message is too big")
+                    }
+                    Some(_) => {
+                        sender = true;
+                        recipient = false;
+                        Reply::new(250, "2.1.0 ok")
+                    }
+                    None => Reply::new(501, "5.5.4 expected MAIL FROM:<address>"),
+                },
+                "RCPT" if !sender => Reply::new(503, "5.5.1 need MAIL first"),
+                "RCPT" => match parameter(argument, "TO:") {
+                    Some(to) if to.to_lowercase() == self.address => {
+                        recipient = true;
+                        Reply::new(250, "2.1.5 ok")
+                    }
+                    Some(_) => Reply::new(550, "5.1.1 no such mailbox here"),
+                    None => Reply::new(501, "5.5.4 expected RCPT TO:<address>"),
+                },
+                "DATA" if !recipient => Reply::new(503, "5.5.1 need RCPT first"),
+                "DATA" => {
+                    write(&mut stream, &Reply::new(354, "end data with <CRLF>.<CRLF>")).await?;
+                    sender = false;
+                    recipient = false;
+                    match self.read_message(&mut stream).await? {
+                        Some(raw) => self.save(&raw).await,
+                        None => Reply::new(552, "5.3.4 message is too big"),
+                    }
+                }
+                "RSET" => {
+                    sender = false;
+                    recipient = false;
+                    Reply::new(250, "2.0.0 ok")
+                }
+                "NOOP" => Reply::new(250, "2.0.0 ok"),
+                "QUIT" => {
+                    write(&mut stream, &Reply::new(221, "2.0.0 bye")).await?;
+                    return Ok(());
+                }
+                _ => Reply::new(502, "5.5.2 command not implemented"),
+            };
+
+            if reply.0 >= 500 {
+                errors += 1;
+            }
+            write(&mut stream, &reply).await?;
+            if errors >= MAX_ERRORS {
+                write(&mut stream, &Reply::new(421, "4.7.0 too many errors")).await?;
+                return Ok(());
+            }
+        }
+    }
+
+    // clients can say up front how big the message is going to be
+    fn too_big(&self, argument: &str) -> bool {
+        argument
+            .split_whitespace()
+            .filter_map(|param| param.to_uppercase().strip_prefix("SIZE=").map(String::from))
+            .any(|size| matches!(size.parse::<usize>(), Ok(size) if size > self.max_size))
+    }
+
+    // reads the message up to the lone dot, nothing is returned when it was
+    // too big, it's still read to the end so the conversation can go on
+    async fn read_message(&self, stream: &mut BufReader<TcpStream>) -> Result<Option<Vec<u8>>> {
+        let mut raw = Vec::new();
+        let mut too_big = false;
+        loop {
+            let line = read_line(stream, MAX_LINE).await?;
+            if line == b".\r\n" || line == b".\n" {
+                break;
+            }
+            if too_big {
+                continue;
+            }
+            // lines starting with a dot got another one put in front
+            let line = line.strip_prefix(b".").unwrap_or(&line);
+            if raw.len() + line.len() > self.max_size {
+                too_big = true;
+                raw = Vec::new();
+                continue;
+            }
+            raw.extend_from_slice(line);
+        }
+
+        match too_big {
+            true => Ok(None),
+            false => Ok(Some(raw)),
+        }
+    }
+
+    async fn sender(&self, address: &str) -> Result<Option<users::Model>, DbErr> {
+        users::Entity::find()
+            .filter(users::Column::EmailVerified.eq(true))
+            .filter(Expr::cust_with_values(
+                "LOWER(\"users\".\"email\") = ?",
+                vec![address.to_string()],
+            ))
+            .one(self.dbconn.as_ref())
+            .await
+    }
+
+    async fn save(&self, raw: &[u8]) -> Reply {
+        let parsed = message::parse(raw);
+        let from = match parsed.from.as_deref() {
+            Some(from) => from,
+            None => return Reply::new(550, "5.7.1 sender is unknown"),
+        };
+        // anyone can write any From header, only the mail server in front
+        // can tell whether it's true
+        if !message::authenticated(&parsed, &self.authserv_id) {
+            info!(
+                "email from {from} refused, {} didn't vouch for it",
+                self.authserv_id
+            );
+            return Reply::new(550, "5.7.1 sender could not be verified");
+        }
+        let user = match self.sender(from).await {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                info!("email from {from} refused, no user has it as their verified address");
+                return Reply::new(550, "5.7.1 sender is unknown");
+            }
+            Err(e) => {
+                error!("tried looking up the sender of an email: {e}");
+                return Reply::new(451, "4.3.0 try again later");
+            }
+        };
+
+        let note = parsed
+            .subject
+            .as_deref()
+            .map(message::strip_forward)
+            .filter(|s| !s.is_empty())
+            .map(String::from);
+        let mut session = ImportSession::new(
+            self.dbconn.clone(),
+            self.normalizer.clone(),
+            user.id.clone(),
+        );
+        for url in message::urls(&parsed).into_iter().take(MAX_LINKS) {
+            let imported = ImportedLink {
+                url,
+                group: self.group.clone(),
+                note: note.clone(),
+                ..ImportedLink::default()
+            };
+            // links that can't be saved, like ones too long for the database,
+            // are counted as invalid and skipped, only the database going
+            // away is worth the email being sent again
+            if let Err(e) = session.add(imported).await {
+                // what was saved so far stays, sending the email again
+                // skips over those as duplicates
+                error!("tried saving a link from an email: {e}");
+                self.queue_for(&session.saved).await;
+                return Reply::new(451, "4.3.0 try again later");
+            }
+        }
+        self.queue_for(&session.saved).await;
+        let saved = session.saved.len();
+
+        let report = &session.report;
+        info!(
+            "saved {} links from an email by {}, {} were already saved, {} were invalid",
+            saved, user.id, report.duplicates, report.invalid
+        );
+        Reply::new(
+            250,
+            format!(
+                "2.0.0 saved {} links, {} were already saved, {} were invalid",
+                saved, report.duplicates, report.invalid
+            ),
+        )
+    }
+
+    // the same work a link submitted by hand gets, and the user's webhooks
+    // hear about it
+    async fn queue_for(&self, saved: &[LinkId]) {
+        self.queue.push_saved(saved, self.snapshots).await;
+        webhooks::emit_saved(self.dbconn.as_ref(), &self.queue, saved).await;
+    }
+}
+
+// the address in "FROM:<address> SIZE=1234"
+fn parameter<'a>(argument: &'a str, name: &str) -> Option<&'a str> {
+    let argument = argument.trim_start();
+    if !argument.to_uppercase().starts_with(name) {
+        return None;
+    }
+    let rest = argument[name.len()..].trim_start();
+    let rest = rest.strip_prefix('<')?;
+    rest.split_once('>').map(|(address, _)| address.trim())
+}
+
+// a line along with it's line break, fails when the client hung up, went
+// quiet or sent a line that's too long
+async fn read_line(stream: &mut BufReader<TcpStream>, limit: u64) -> Result<Vec<u8>> {
+    let mut line = Vec::new();
+    let read = timeout(
+        Duration::from_secs(TIMEOUT),
+        (&mut *stream).take(limit).read_until(b'\n', &mut line),
+    )
+    .await
+    .map_err(|_| anyhow!("client went quiet"))??;
+    match read {
+        0 => Err(anyhow!("client hung up")),
+        _ if !line.ends_with(b"\n") => {
+            write(stream, &Reply::new(500, "5.5.6 line is too long")).await?;
+            Err(anyhow!("client sent a line that's too long"))
+        }
+        _ => Ok(line),
+    }
+}
+
+async fn write(stream: &mut BufReader<TcpStream>, reply: &Reply) -> Result<()> {
+    write_raw(stream, &format!("{} {}\r\n", reply.0, reply.1)).await
+}
+
+async fn write_raw(stream: &mut BufReader<TcpStream>, text: &str) -> Result<()> {
+    stream.get_mut().write_all(text.as_bytes()).await?;
+    Ok(())
+}
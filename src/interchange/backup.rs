@@ -0,0 +1,451 @@
+//! Full account backups as JSON Lines, one typed record per line.
+
+use std::{collections::HashMap, sync::Arc};
+
+use {
+    anyhow::{bail, Error, Result},
+    async_stream::try_stream,
+    axum::body::Bytes,
+    chrono::Utc,
+    futures_util::stream::{self, BoxStream, StreamExt},
+    sea_orm::{entity::prelude::*, ActiveModelTrait, Condition, DatabaseConnection, QueryOrder},
+    serde::{Deserialize, Serialize},
+};
+
+use super::import::Malformed;
+use crate::{
+    api::{links::LinkId, members::Role, UserId},
+    database::entity::{
+        api_keys, group_members, grouped_links, groups, links, notes, tagged_links, tags,
+    },
+    identity,
+};
+
+// bumped whenever a record changes in a way older restores can't read
+pub const FORMAT_VERSION: u32 = 1;
+
+// rows are read from the database this many at a time
+const PAGE_SIZE: usize = 500;
+
+// api keys are only described, their secrets never leave the server
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ApiKeyMeta {
+    pub id: String,
+    pub created_by: String,
+    pub date_created: DateTimeWithTimeZone,
+    pub deleted_at: Option<DateTimeWithTimeZone>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Header {
+        version: u32,
+        user_id: UserId,
+        date_created: DateTimeWithTimeZone,
+    },
+    Group(groups::Model),
+    Membership(group_members::Model),
+    Tag(tags::Model),
+    // boxed since links carry far more columns than anything else
+    Link(Box<links::Model>),
+    Grouping(grouped_links::Model),
+    Tagging(tagged_links::Model),
+    Note(notes::Model),
+    ApiKey(ApiKeyMeta),
+}
+
+fn line(record: &Record) -> Result<Bytes, Error> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    Ok(Bytes::from(line))
+}
+
+// pages through everything the query matches, ordered by id so that rows
+// created while the export runs don't shift the pages
+fn rows<E>(
+    dbconn: Arc<DatabaseConnection>,
+    query: Select<E>,
+    column: E::Column,
+    wrap: fn(E::Model) -> Record,
+) -> BoxStream<'static, Result<Bytes, Error>>
+where
+    E: EntityTrait,
+    E::Model: Sync,
+{
+    Box::pin(try_stream! {
+        let mut pages = query
+            .order_by_asc(column)
+            .paginate(dbconn.as_ref(), PAGE_SIZE);
+        while let Some(page) = pages.fetch_and_next().await? {
+            for model in page {
+                yield line(&wrap(model))?;
+            }
+        }
+    })
+}
+
+// everything the user made along with the groups they're part of, in an order
+// that lets a restore insert every record after the ones it refers to
+pub async fn export(
+    dbconn: Arc<DatabaseConnection>,
+    user_id: UserId,
+) -> Result<BoxStream<'static, Result<Bytes, Error>>, DbErr> {
+    let roles = identity::group_roles(dbconn.clone(), &user_id).await?;
+    let group_ids: Vec<String> = roles.keys().cloned().collect();
+    let owned_ids: Vec<String> = roles
+        .iter()
+        .filter(|(_, role)| role.can_manage())
+        .map(|(id, _)| id.clone())
+        .collect();
+
+    let header = line(&Record::Header {
+        version: FORMAT_VERSION,
+        user_id: user_id.clone(),
+        date_created: Utc::now().into(),
+    });
+
+    let parts = vec![
+        Box::pin(stream::once(async { header })) as BoxStream<'static, _>,
+        rows(
+            dbconn.clone(),
+            groups::Entity::find().filter(
+                Condition::any()
+                    .add(groups::Column::CreatedBy.eq(user_id.clone()))
+                    .add(groups::Column::Id.is_in(group_ids)),
+            ),
+            groups::Column::Id,
+            Record::Group,
+        ),
+        rows(
+            dbconn.clone(),
+            group_members::Entity::find().filter(
+                Condition::any()
+                    .add(group_members::Column::UserId.eq(user_id.clone()))
+                    .add(group_members::Column::GroupId.is_in(owned_ids)),
+            ),
+            group_members::Column::Id,
+            Record::Membership,
+        ),
+        rows(
+            dbconn.clone(),
+            tags::Entity::find().filter(tags::Column::CreatedBy.eq(user_id.clone())),
+            tags::Column::Id,
+            Record::Tag,
+        ),
+        rows(
+            dbconn.clone(),
+            links::Entity::find().filter(links::Column::CreatedBy.eq(user_id.clone())),
+            links::Column::Id,
+            |link| Record::Link(Box::new(link)),
+        ),
+        rows(
+            dbconn.clone(),
+            grouped_links::Entity::find()
+                .filter(grouped_links::Column::CreatedBy.eq(user_id.clone())),
+            grouped_links::Column::Id,
+            Record::Grouping,
+        ),
+        rows(
+            dbconn.clone(),
+            tagged_links::Entity::find()
+                .filter(tagged_links::Column::CreatedBy.eq(user_id.clone())),
+            tagged_links::Column::Id,
+            Record::Tagging,
+        ),
+        rows(
+            dbconn.clone(),
+            notes::Entity::find().filter(notes::Column::CreatedBy.eq(user_id.clone())),
+            notes::Column::Id,
+            Record::Note,
+        ),
+        rows(
+            dbconn,
+            api_keys::Entity::find().filter(api_keys::Column::CreatedBy.eq(user_id)),
+            api_keys::Column::Id,
+            |key| {
+                Record::ApiKey(ApiKeyMeta {
+                    id: key.id,
+                    created_by: key.created_by,
+                    date_created: key.date_created,
+                    deleted_at: key.deleted_at,
+                })
+            },
+        ),
+    ];
+
+    Ok(stream::iter(parts).flatten().boxed())
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct RestoreReport {
+    pub restored: usize,
+    pub existing: usize,
+    pub rejected: usize,
+}
+
+// replays a backup into the user's account, records that are already there
+// are skipped so a restore can be run as often as needed
+pub struct Restore {
+    dbconn: Arc<DatabaseConnection>,
+    user_id: UserId,
+    // whose account the backup was taken of, as the header says
+    author: Option<UserId>,
+    roles: HashMap<String, Role>,
+    buffer: Vec<u8>,
+    line: usize,
+    // the links that were restored, for the work that follows saving a link
+    pub saved: Vec<LinkId>,
+    pub report: RestoreReport,
+}
+
+impl Restore {
+    pub async fn new(dbconn: Arc<DatabaseConnection>, user_id: UserId) -> Result<Self, DbErr> {
+        let roles = identity::group_roles(dbconn.clone(), &user_id).await?;
+        Ok(Self {
+            dbconn,
+            user_id,
+            author: None,
+            roles,
+            buffer: Vec::new(),
+            line: 0,
+            saved: Vec::new(),
+            report: RestoreReport::default(),
+        })
+    }
+
+    async fn owns_link(&self, link_id: &str) -> Result<bool, DbErr> {
+        Ok(links::Entity::find_by_id(link_id.to_string())
+            .filter(links::Column::CreatedBy.eq(self.user_id.clone()))
+            .one(self.dbconn.as_ref())
+            .await?
+            .is_some())
+    }
+
+    async fn owns_tag(&self, tag_id: &str) -> Result<bool, DbErr> {
+        Ok(tags::Entity::find_by_id(tag_id.to_string())
+            .filter(tags::Column::CreatedBy.eq(self.user_id.clone()))
+            .one(self.dbconn.as_ref())
+            .await?
+            .is_some())
+    }
+
+    // notes can be written on links of other members in a shared group
+    async fn can_see_link(&self, link_id: &str) -> Result<bool, DbErr> {
+        if self.owns_link(link_id).await? {
+            return Ok(true);
+        }
+        Ok(grouped_links::Entity::find()
+            .filter(grouped_links::Column::LinkId.eq(link_id))
+            .filter(grouped_links::Column::GroupId.is_in(self.roles.keys().cloned()))
+            .filter(grouped_links::Column::DeletedAt.is_null())
+            .one(self.dbconn.as_ref())
+            .await?
+            .is_some())
+    }
+
+    fn role(&self, group_id: &str) -> Option<Role> {
+        self.roles.get(group_id).copied()
+    }
+
+    // inserts the row unless one with the same id already exists
+    async fn insert<A>(&mut self, exists: bool, row: A) -> Result<(), DbErr>
+    where
+        A: ActiveModelTrait + Send,
+    {
+        if exists {
+            self.report.existing += 1;
+            return Ok(());
+        }
+        <A::Entity as EntityTrait>::insert(row)
+            .exec(self.dbconn.as_ref())
+            .await?;
+        self.report.restored += 1;
+        Ok(())
+    }
+
+    // every record is claimed by the user restoring it, and only attached to
+    // groups, links and tags they're allowed to change
+    pub async fn apply(&mut self, record: Record) -> Result<(), Error> {
+        let db = self.dbconn.clone();
+        let user_id = self.user_id.clone();
+        match record {
+            Record::Header {
+                version,
+                user_id: author,
+                ..
+            } => {
+                if version > FORMAT_VERSION {
+                    bail!(Malformed(format!(
+                        "backup was written by a newer version (format {version})"
+                    )));
+                }
+                self.author = Some(author);
+            }
+            Record::Group(group) => {
+                let exists = groups::Entity::find_by_id(group.id.clone())
+                    .one(db.as_ref())
+                    .await?
+                    .is_some();
+                if !exists {
+                    self.roles.insert(group.id.clone(), Role::Owner);
+                }
+                self.insert(
+                    exists,
+                    groups::ActiveModel::from(groups::Model {
+                        created_by: user_id,
+                        ..group
+                    }),
+                )
+                .await?;
+            }
+            Record::Membership(member) => {
+                let exists = group_members::Entity::find_by_id(member.id.clone())
+                    .one(db.as_ref())
+                    .await?
+                    .is_some();
+                // who else was in a group only carries over into the account
+                // the backup was taken of, and the user's own place in the
+                // groups they restore comes from created_by
+                let same_account = self.author.as_ref() == Some(&user_id);
+                let own = member.user_id.as_ref() == Some(&user_id);
+                let allowed = exists
+                    || same_account
+                        && !own
+                        && matches!(self.role(&member.group_id), Some(r) if r.can_manage());
+                if !allowed {
+                    self.report.rejected += 1;
+                    return Ok(());
+                }
+                self.insert(exists, group_members::ActiveModel::from(member))
+                    .await?;
+            }
+            Record::Tag(tag) => {
+                let exists = tags::Entity::find_by_id(tag.id.clone())
+                    .one(db.as_ref())
+                    .await?
+                    .is_some();
+                self.insert(
+                    exists,
+                    tags::ActiveModel::from(tags::Model {
+                        created_by: user_id,
+                        ..tag
+                    }),
+                )
+                .await?;
+            }
+            Record::Link(link) => {
+                let exists = links::Entity::find_by_id(link.id.clone())
+                    .one(db.as_ref())
+                    .await?
+                    .is_some();
+                // the reader text and snapshots aren't part of backups
+                let link_id = link.id.parse::<LinkId>().ok().filter(|_| !exists);
+                self.insert(
+                    exists,
+                    // worked out again afterwards, the rules may differ from
+                    // where the backup came from
+                    links::ActiveModel::from(links::Model {
+                        created_by: user_id,
+                        normalized_url: None,
+                        ..*link
+                    }),
+                )
+                .await?;
+                self.saved.extend(link_id);
+            }
+            Record::Grouping(grouping) => {
+                let exists = grouped_links::Entity::find_by_id(grouping.id.clone())
+                    .one(db.as_ref())
+                    .await?
+                    .is_some();
+                let allowed = exists
+                    || (matches!(self.role(&grouping.group_id), Some(r) if r.can_edit())
+                        && self.owns_link(&grouping.link_id).await?);
+                if !allowed {
+                    self.report.rejected += 1;
+                    return Ok(());
+                }
+                self.insert(
+                    exists,
+                    grouped_links::ActiveModel::from(grouped_links::Model {
+                        created_by: user_id,
+                        ..grouping
+                    }),
+                )
+                .await?;
+            }
+            Record::Tagging(tagging) => {
+                let exists = tagged_links::Entity::find_by_id(tagging.id.clone())
+                    .one(db.as_ref())
+                    .await?
+                    .is_some();
+                let allowed = exists
+                    || (self.owns_tag(&tagging.tag_id).await?
+                        && self.owns_link(&tagging.link_id).await?);
+                if !allowed {
+                    self.report.rejected += 1;
+                    return Ok(());
+                }
+                self.insert(
+                    exists,
+                    tagged_links::ActiveModel::from(tagged_links::Model {
+                        created_by: user_id,
+                        ..tagging
+                    }),
+                )
+                .await?;
+            }
+            Record::Note(note) => {
+                let exists = notes::Entity::find_by_id(note.id.clone())
+                    .one(db.as_ref())
+                    .await?
+                    .is_some();
+                let allowed = exists || self.can_see_link(&note.link_id).await?;
+                if !allowed {
+                    self.report.rejected += 1;
+                    return Ok(());
+                }
+                self.insert(
+                    exists,
+                    notes::ActiveModel::from(notes::Model {
+                        created_by: user_id,
+                        ..note
+                    }),
+                )
+                .await?;
+            }
+            // without their secret there's nothing to restore
+            Record::ApiKey(_) => self.report.rejected += 1,
+        }
+        Ok(())
+    }
+
+    // applies every line the chunk completes, a line that's cut off waits
+    // for the rest of it
+    pub async fn feed(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        self.buffer.extend_from_slice(chunk);
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let rest = self.buffer.split_off(end + 1);
+            let line = std::mem::replace(&mut self.buffer, rest);
+            self.apply_line(&line).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn finish(&mut self) -> Result<RestoreReport, Error> {
+        let line = std::mem::take(&mut self.buffer);
+        self.apply_line(&line).await?;
+        Ok(self.report.clone())
+    }
+
+    async fn apply_line(&mut self, line: &[u8]) -> Result<(), Error> {
+        self.line += 1;
+        if line.iter().all(u8::is_ascii_whitespace) {
+            return Ok(());
+        }
+        let record: Record = serde_json::from_slice(line)
+            .map_err(|e| Malformed(format!("line {} isn't a valid record: {e}", self.line)))?;
+        self.apply(record).await
+    }
+}
@@ -0,0 +1,90 @@
+//! The `Bookmarks` file in Chrome and Chromium profile directories.
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use super::{
+    import::{ImportedLink, Malformed},
+    netscape::unix_timestamp,
+};
+
+// even profiles with years of bookmarks stay well below this, and the whole
+// file is held in memory twice while it's parsed
+const MAX_SIZE: usize = 64 * 1024 * 1024;
+
+// seconds between 1601-01-01, where chrome's clock starts, and the unix epoch
+const WINDOWS_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+#[derive(Deserialize)]
+struct Node {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    url: Option<String>,
+    date_added: Option<String>,
+    #[serde(default)]
+    children: Vec<Node>,
+}
+
+#[derive(Deserialize)]
+struct Bookmarks {
+    roots: std::collections::BTreeMap<String, serde_json::Value>,
+}
+
+fn walk(node: Node, folder: Option<&str>, out: &mut Vec<ImportedLink>) {
+    match node.kind.as_str() {
+        "url" => out.push(ImportedLink {
+            url: node.url.unwrap_or_default(),
+            title: Some(node.name),
+            // chrome counts microseconds since 1601
+            date_created: node
+                .date_added
+                .and_then(|d| d.parse::<i64>().ok())
+                .filter(|d| *d > 0)
+                .and_then(|d| unix_timestamp(d / 1_000_000 - WINDOWS_EPOCH_OFFSET)),
+            group: folder.map(String::from),
+            ..Default::default()
+        }),
+        "folder" => {
+            let name = node.name;
+            for child in node.children {
+                walk(child, Some(&name), out);
+            }
+        }
+        _ => (),
+    }
+}
+
+// the file is a single json document, so it's collected in full before parsing
+#[derive(Default)]
+pub struct Parser {
+    buffer: Vec<u8>,
+}
+
+impl super::import::Parser for Parser {
+    fn feed(&mut self, chunk: &[u8]) -> Result<Vec<ImportedLink>> {
+        if self.buffer.len() + chunk.len() > MAX_SIZE {
+            return Err(Malformed("chrome bookmarks file is too big".to_string()).into());
+        }
+        self.buffer.extend_from_slice(chunk);
+        Ok(Vec::new())
+    }
+
+    fn finish(&mut self) -> Result<Vec<ImportedLink>> {
+        let bookmarks: Bookmarks = serde_json::from_slice(&std::mem::take(&mut self.buffer))
+            .map_err(|e| Malformed(format!("chrome bookmarks aren't valid: {e}")))?;
+
+        // the roots are the bookmarks bar and the other and mobile bookmarks,
+        // those aren't folders anybody made so they don't become groups
+        let mut out = Vec::new();
+        for root in bookmarks.roots.into_values() {
+            if let Ok(root) = serde_json::from_value::<Node>(root) {
+                for child in root.children {
+                    walk(child, None, &mut out);
+                }
+            }
+        }
+        Ok(out)
+    }
+}
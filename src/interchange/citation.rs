@@ -0,0 +1,455 @@
+//! Citation metadata for links, read out of the pages they point to and
+//! written out for reference managers as BibTeX or CSL-JSON.
+
+use std::{collections::HashSet, fmt::Write};
+
+use {
+    anyhow::{anyhow, Result},
+    chrono::Datelike,
+    scraper::{Html, Selector},
+    serde::{Deserialize, Serialize},
+    serde_json::{json, Value},
+};
+
+use crate::api::links::Link;
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Citation {
+    #[serde(default)]
+    pub authors: Vec<String>,
+    // as precise as it's known, a year, a month or a full date
+    #[serde(default)]
+    pub published_on: Option<String>,
+    #[serde(default)]
+    pub site_name: Option<String>,
+    #[serde(default)]
+    pub doi: Option<String>,
+}
+
+impl Citation {
+    // keeps whatever is already known and only fills in the gaps
+    pub fn fill(&mut self, other: Citation) {
+        if self.authors.is_empty() {
+            self.authors = other.authors;
+        }
+        self.published_on = self.published_on.take().or(other.published_on);
+        self.site_name = self.site_name.take().or(other.site_name);
+        self.doi = self.doi.take().or(other.doi);
+    }
+
+    // tidies up fields that were typed in by hand, a date or doi that can't be
+    // made sense of is an error instead of being dropped silently
+    pub fn normalized(self) -> Result<Self> {
+        let published_on = match self.published_on.filter(|d| !d.trim().is_empty()) {
+            Some(raw) => {
+                Some(normalize_date(&raw).ok_or_else(|| anyhow!("not a valid publication date"))?)
+            }
+            None => None,
+        };
+        let doi = match self.doi.filter(|d| !d.trim().is_empty()) {
+            Some(raw) => Some(normalize_doi(&raw).ok_or_else(|| anyhow!("not a valid doi"))?),
+            None => None,
+        };
+
+        Ok(Self {
+            authors: self
+                .authors
+                .into_iter()
+                .map(|a| a.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|a| !a.is_empty())
+                .collect(),
+            published_on,
+            site_name: self
+                .site_name
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty()),
+            doi,
+        })
+    }
+}
+
+// cuts dates down to the year, month and day, pages write them in all kinds of
+// shapes like 2021/05/02 or full timestamps
+pub fn normalize_date(raw: &str) -> Option<String> {
+    let raw = raw.trim().replace('/', "-");
+    let parts: Vec<&str> = raw.split(['T', ' ']).next()?.split('-').take(3).collect();
+
+    let year: u32 = match parts.first() {
+        Some(y) if y.len() == 4 => y.parse().ok()?,
+        _ => return None,
+    };
+    let month: Option<u32> = match parts.get(1) {
+        Some(m) => Some(m.parse().ok().filter(|m| (1..=12).contains(m))?),
+        None => None,
+    };
+    let day: Option<u32> = match (month, parts.get(2)) {
+        (Some(_), Some(d)) => Some(d.parse().ok().filter(|d| (1..=31).contains(d))?),
+        _ => None,
+    };
+
+    Some(match (month, day) {
+        (Some(m), Some(d)) => format!("{year:04}-{m:02}-{d:02}"),
+        (Some(m), None) => format!("{year:04}-{m:02}"),
+        _ => format!("{year:04}"),
+    })
+}
+
+// dois are stored bare, without the resolver or scheme in front of them
+pub fn normalize_doi(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    let lowered = raw.to_ascii_lowercase();
+    let mut doi = raw;
+    for prefix in [
+        "https://doi.org/",
+        "http://doi.org/",
+        "https://dx.doi.org/",
+        "http://dx.doi.org/",
+        "doi:",
+    ] {
+        if lowered.starts_with(prefix) {
+            doi = raw[prefix.len()..].trim();
+            break;
+        }
+    }
+
+    if doi.starts_with("10.") && doi.contains('/') && !doi.contains(char::is_whitespace) {
+        Some(doi.to_string())
+    } else {
+        None
+    }
+}
+
+// reads the metadata scholarly publishers, news sites and blogs put in their
+// pages, preferring the highwire citation tags google scholar asks for
+pub fn extract(document: &Html) -> Citation {
+    let selector = Selector::parse("meta[content]").unwrap();
+    let metas: Vec<(String, String)> = document
+        .select(&selector)
+        .filter_map(|meta| {
+            let meta = meta.value();
+            let key = meta
+                .attr("name")
+                .or_else(|| meta.attr("property"))
+                .or_else(|| meta.attr("itemprop"))?;
+            let content = meta.attr("content")?.trim();
+            if content.is_empty() {
+                return None;
+            }
+            Some((key.trim().to_ascii_lowercase(), content.to_string()))
+        })
+        .collect();
+    let all = |name: &str| -> Vec<String> {
+        metas
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, content)| content.clone())
+            .collect()
+    };
+
+    // article:author is usually a link to a profile rather than a name
+    let authors = [
+        "citation_author",
+        "dc.creator",
+        "dcterms.creator",
+        "author",
+        "article:author",
+    ]
+    .iter()
+    .map(|name| {
+        all(name)
+            .into_iter()
+            .filter(|a| !a.starts_with("http://") && !a.starts_with("https://"))
+            .collect::<Vec<_>>()
+    })
+    .find(|found| !found.is_empty())
+    .unwrap_or_default();
+
+    let published_on = [
+        "citation_publication_date",
+        "citation_date",
+        "citation_online_date",
+        "dc.date.issued",
+        "dcterms.issued",
+        "dc.date",
+        "article:published_time",
+        "datepublished",
+        "date",
+    ]
+    .iter()
+    .flat_map(|name| all(name))
+    .find_map(|d| normalize_date(&d));
+
+    let site_name = [
+        "citation_journal_title",
+        "citation_conference_title",
+        "og:site_name",
+        "application-name",
+    ]
+    .iter()
+    .flat_map(|name| all(name))
+    .next();
+
+    let doi = [
+        "citation_doi",
+        "prism.doi",
+        "dc.identifier",
+        "dcterms.identifier",
+    ]
+    .iter()
+    .flat_map(|name| all(name))
+    .find_map(|d| normalize_doi(&d));
+
+    Citation {
+        authors,
+        published_on,
+        site_name,
+        doi,
+    }
+    .normalized()
+    .unwrap_or_default()
+}
+
+// names are either written as "family, given" or "given family"
+fn split_name(name: &str) -> (Option<&str>, &str) {
+    match name.split_once(',') {
+        Some((family, given)) => (Some(given.trim()), family.trim()),
+        None => match name.rsplit_once(' ') {
+            Some((given, family)) => (Some(given.trim()), family.trim()),
+            None => (None, name.trim()),
+        },
+    }
+}
+
+fn date_parts(date: &str) -> Vec<u32> {
+    date.split('-').filter_map(|p| p.parse().ok()).collect()
+}
+
+// whether the link is better described as a paper than as a web page
+fn is_article(link: &Link) -> bool {
+    link.citation.doi.is_some() && link.citation.site_name.is_some()
+}
+
+// gives every link a key in the usual family name, year and title word shape,
+// keys that would collide get letters appended like bibliographies do
+fn citation_keys(links: &[Link]) -> Vec<String> {
+    let mut taken = HashSet::new();
+    links
+        .iter()
+        .map(|link| {
+            let simplify = |s: &str| -> String {
+                s.chars()
+                    .filter(|c| c.is_ascii_alphanumeric())
+                    .collect::<String>()
+                    .to_ascii_lowercase()
+            };
+            let author = link
+                .citation
+                .authors
+                .first()
+                .map(|a| simplify(split_name(a).1))
+                .filter(|a| !a.is_empty())
+                .or_else(|| link.url.host_str().map(simplify))
+                .unwrap_or_else(|| String::from("link"));
+            let year = link
+                .citation
+                .published_on
+                .as_deref()
+                .and_then(|d| d.get(..4))
+                .unwrap_or_default()
+                .to_string();
+            let word = link
+                .title
+                .as_deref()
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(simplify)
+                .find(|w| w.len() > 3)
+                .unwrap_or_default();
+
+            let base = format!("{author}{year}{word}");
+            let mut key = base.clone();
+            let mut collisions = 0;
+            while !taken.insert(key.clone()) {
+                key = format!("{base}{}", key_suffix(collisions));
+                collisions += 1;
+            }
+            key
+        })
+        .collect()
+}
+
+// a, b, ..., z, aa, ab, ..., the way spreadsheet columns are counted
+fn key_suffix(mut n: usize) -> String {
+    let mut suffix = Vec::new();
+    loop {
+        suffix.push(b'a' + (n % 26) as u8);
+        n /= 26;
+        if n == 0 {
+            break;
+        }
+        n -= 1;
+    }
+    suffix.reverse();
+    String::from_utf8(suffix).unwrap_or_default()
+}
+
+// escapes the characters latex treats as commands
+fn escape_latex(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// urls are taken verbatim, only unbalanced braces would break the entry
+fn escape_verbatim(s: &str) -> String {
+    s.replace('{', "%7B").replace('}', "%7D")
+}
+
+pub fn write_bibtex(links: &[Link]) -> String {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+
+    let mut out = String::new();
+    for (link, key) in links.iter().zip(citation_keys(links)) {
+        let citation = &link.citation;
+        let kind = if is_article(link) { "article" } else { "misc" };
+        let _ = writeln!(out, "@{kind}{{{key},");
+
+        let title = link.title.clone().unwrap_or_else(|| link.url.to_string());
+        // doubled braces keep the title's capitalization as it is
+        let _ = writeln!(out, "  title = {{{{{}}}}},", escape_latex(&title));
+        if !citation.authors.is_empty() {
+            let authors: Vec<String> = citation.authors.iter().map(|a| escape_latex(a)).collect();
+            let _ = writeln!(out, "  author = {{{}}},", authors.join(" and "));
+        }
+        if let Some(site_name) = &citation.site_name {
+            let field = if is_article(link) {
+                "journal"
+            } else {
+                "howpublished"
+            };
+            let _ = writeln!(out, "  {field} = {{{}}},", escape_latex(site_name));
+        }
+        if let Some(date) = &citation.published_on {
+            let parts = date_parts(date);
+            if let Some(year) = parts.first() {
+                let _ = writeln!(out, "  year = {{{year}}},");
+            }
+            if let Some(month) = parts
+                .get(1)
+                .and_then(|m| (*m as usize).checked_sub(1))
+                .and_then(|m| MONTHS.get(m))
+            {
+                let _ = writeln!(out, "  month = {month},");
+            }
+        }
+        if let Some(doi) = &citation.doi {
+            let _ = writeln!(out, "  doi = {{{}}},", escape_verbatim(doi));
+        }
+        let _ = writeln!(out, "  url = {{{}}},", escape_verbatim(link.url.as_str()));
+        let _ = writeln!(
+            out,
+            "  urldate = {{{}}},",
+            link.date_created.format("%Y-%m-%d")
+        );
+        out.push_str("}\n\n");
+    }
+    out
+}
+
+pub fn write_csl_json(links: &[Link]) -> Value {
+    let items: Vec<Value> = links
+        .iter()
+        .zip(citation_keys(links))
+        .map(|(link, key)| {
+            let citation = &link.citation;
+            let mut item = json!({
+                "id": key,
+                "type": if is_article(link) { "article-journal" } else { "webpage" },
+                "title": link.title.clone().unwrap_or_else(|| link.url.to_string()),
+                "URL": link.url.as_str(),
+                "accessed": {
+                    "date-parts": [[
+                        link.date_created.year(),
+                        link.date_created.month(),
+                        link.date_created.day(),
+                    ]],
+                },
+            });
+
+            if !citation.authors.is_empty() {
+                let authors: Vec<Value> = citation
+                    .authors
+                    .iter()
+                    .map(|name| match split_name(name) {
+                        (Some(given), family) => json!({ "family": family, "given": given }),
+                        (None, literal) => json!({ "literal": literal }),
+                    })
+                    .collect();
+                item["author"] = Value::from(authors);
+            }
+            if let Some(date) = &citation.published_on {
+                item["issued"] = json!({ "date-parts": [date_parts(date)] });
+            }
+            if let Some(site_name) = &citation.site_name {
+                item["container-title"] = Value::from(site_name.as_str());
+            }
+            if let Some(doi) = &citation.doi {
+                item["DOI"] = Value::from(doi.as_str());
+            }
+            item
+        })
+        .collect();
+
+    Value::from(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colliding_keys_stay_unique() {
+        let links: Vec<Link> = (0..250)
+            .map(|_| {
+                Link::new(
+                    "https://github.com/rust-lang/rust".parse().unwrap(),
+                    chrono::Utc::now().into(),
+                    Some(String::from("GitHub - rust-lang/rust")),
+                    false,
+                    String::new(),
+                )
+            })
+            .collect();
+
+        let keys = citation_keys(&links);
+        assert_eq!(keys[0], "githubcomgithub");
+        assert_eq!(keys[1], "githubcomgithuba");
+        assert_eq!(keys[27], "githubcomgithubaa");
+        assert_eq!(keys.iter().collect::<HashSet<_>>().len(), links.len());
+        assert!(keys
+            .iter()
+            .all(|k| k.chars().all(|c| c.is_ascii_alphanumeric())));
+    }
+
+    #[test]
+    fn escapes_latex() {
+        assert_eq!(
+            escape_latex(r"50% of {R&D} costs $5 #1_a ~x^2 \o"),
+            r"50\% of \{R\&D\} costs \$5 \#1\_a \textasciitilde{}x\textasciicircum{}2 \textbackslash{}o"
+        );
+        assert_eq!(escape_latex("Ünïcode stays"), "Ünïcode stays");
+    }
+}
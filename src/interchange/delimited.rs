@@ -0,0 +1,123 @@
+//! Reading comma separated exports a piece at a time, and writing them.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use super::{import::Malformed, AnnotatedLink};
+
+// a record keyed by the lowercased names in the file's header row
+pub struct Row {
+    fields: HashMap<String, String>,
+}
+
+impl Row {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .get(name)
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+    }
+}
+
+#[derive(Default)]
+pub struct Records {
+    buffer: Vec<u8>,
+    headers: Option<Vec<String>>,
+    // how much of the buffer was already checked for complete records, and
+    // whether that ended inside of a quoted field
+    scanned: usize,
+    quoted: bool,
+}
+
+impl Records {
+    fn parse(&mut self, complete: &[u8]) -> Result<Vec<Row>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(complete);
+
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record.map_err(|e| Malformed(format!("csv export isn't valid: {e}")))?;
+            match &self.headers {
+                None => {
+                    self.headers = Some(
+                        record
+                            .iter()
+                            .map(|h| h.trim_start_matches('\u{feff}').trim().to_lowercase())
+                            .collect(),
+                    )
+                }
+                Some(headers) => rows.push(Row {
+                    fields: headers
+                        .iter()
+                        .cloned()
+                        .zip(record.iter().map(String::from))
+                        .collect(),
+                }),
+            }
+        }
+        Ok(rows)
+    }
+
+    // hands back every record that was completed by the chunk, a record
+    // that's cut off stays buffered until the rest of it arrives
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<Row>> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut complete = 0;
+        for (i, b) in self.buffer.iter().enumerate().skip(self.scanned) {
+            match *b {
+                // escaped quotes flip the state twice, which is a no-op
+                b'"' => self.quoted = !self.quoted,
+                b'\n' if !self.quoted => complete = i + 1,
+                _ => (),
+            }
+        }
+        self.scanned = self.buffer.len() - complete;
+
+        let rest = self.buffer.split_off(complete);
+        let done = std::mem::replace(&mut self.buffer, rest);
+        self.parse(&done)
+    }
+
+    pub fn finish(&mut self) -> Result<Vec<Row>> {
+        let done = std::mem::take(&mut self.buffer);
+        self.scanned = 0;
+        self.quoted = false;
+        self.parse(&done)
+    }
+}
+
+// one row per link, the groups and tags are separated by bars like pocket
+// does and the notes by blank lines
+pub fn write(links: &[AnnotatedLink]) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "id",
+        "url",
+        "title",
+        "date_created",
+        "archived_at",
+        "sensitive",
+        "groups",
+        "tags",
+        "notes",
+    ])?;
+    for annotated in links {
+        let link = &annotated.link;
+        writer.write_record([
+            link.id.to_string(),
+            link.url.to_string(),
+            link.title.clone().unwrap_or_default(),
+            link.date_created.to_rfc3339(),
+            link.archived_at.map(|a| a.to_rfc3339()).unwrap_or_default(),
+            link.sensitive.to_string(),
+            annotated.groups.join("|"),
+            annotated.tags.join("|"),
+            annotated.notes.join("\n\n"),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
@@ -0,0 +1,157 @@
+//! Atom, RSS 2.0 and JSON Feed writers.
+
+use std::fmt::Write;
+
+use {sea_orm::prelude::DateTimeWithTimeZone, serde::Serialize};
+
+use super::escape_markup;
+use crate::api::links::Link;
+
+pub struct FeedMeta {
+    pub id: String,
+    pub title: String,
+    pub updated: DateTimeWithTimeZone,
+    pub home_url: Option<String>,
+    pub feed_url: Option<String>,
+}
+
+fn title(link: &Link) -> String {
+    link.title.clone().unwrap_or_else(|| link.url.to_string())
+}
+
+fn updated(link: &Link) -> DateTimeWithTimeZone {
+    link.modified_at.unwrap_or(link.date_created)
+}
+
+pub fn atom(meta: &FeedMeta, links: &[Link]) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    let _ = writeln!(out, "  <id>urn:ulid:{}</id>", escape_markup(&meta.id));
+    let _ = writeln!(out, "  <title>{}</title>", escape_markup(&meta.title));
+    let _ = writeln!(out, "  <updated>{}</updated>", meta.updated.to_rfc3339());
+    if let Some(feed_url) = &meta.feed_url {
+        let _ = writeln!(
+            out,
+            "  <link rel=\"self\" href=\"{}\"/>",
+            escape_markup(feed_url)
+        );
+    }
+    if let Some(home_url) = &meta.home_url {
+        let _ = writeln!(
+            out,
+            "  <link rel=\"alternate\" href=\"{}\"/>",
+            escape_markup(home_url)
+        );
+    }
+    for link in links {
+        out.push_str("  <entry>\n");
+        let _ = writeln!(out, "    <id>urn:ulid:{}</id>", link.id);
+        let _ = writeln!(out, "    <title>{}</title>", escape_markup(&title(link)));
+        let _ = writeln!(
+            out,
+            "    <link rel=\"alternate\" href=\"{}\"/>",
+            escape_markup(link.url.as_str())
+        );
+        let _ = writeln!(
+            out,
+            "    <published>{}</published>",
+            link.date_created.to_rfc3339()
+        );
+        let _ = writeln!(out, "    <updated>{}</updated>", updated(link).to_rfc3339());
+        // atom wants an author on every entry when the feed doesn't have one
+        let _ = writeln!(
+            out,
+            "    <author><name>{}</name></author>",
+            escape_markup(&meta.title)
+        );
+        out.push_str("  </entry>\n");
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+pub fn rss(meta: &FeedMeta, links: &[Link]) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<rss version=\"2.0\">\n  <channel>\n");
+    let _ = writeln!(out, "    <title>{}</title>", escape_markup(&meta.title));
+    if let Some(home_url) = &meta.home_url {
+        let _ = writeln!(out, "    <link>{}</link>", escape_markup(home_url));
+    }
+    let _ = writeln!(
+        out,
+        "    <description>{}</description>",
+        escape_markup(&meta.title)
+    );
+    let _ = writeln!(
+        out,
+        "    <lastBuildDate>{}</lastBuildDate>",
+        meta.updated.to_rfc2822()
+    );
+    for link in links {
+        out.push_str("    <item>\n");
+        let _ = writeln!(out, "      <title>{}</title>", escape_markup(&title(link)));
+        let _ = writeln!(
+            out,
+            "      <link>{}</link>",
+            escape_markup(link.url.as_str())
+        );
+        let _ = writeln!(
+            out,
+            "      <guid isPermaLink=\"false\">urn:ulid:{}</guid>",
+            link.id
+        );
+        let _ = writeln!(
+            out,
+            "      <pubDate>{}</pubDate>",
+            link.date_created.to_rfc2822()
+        );
+        out.push_str("    </item>\n");
+    }
+    out.push_str("  </channel>\n</rss>\n");
+    out
+}
+
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    home_page_url: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    feed_url: Option<&'a str>,
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem {
+    id: String,
+    url: String,
+    title: String,
+    content_text: String,
+    date_published: String,
+    date_modified: String,
+}
+
+pub fn json(meta: &FeedMeta, links: &[Link]) -> String {
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: &meta.title,
+        home_page_url: meta.home_url.as_deref(),
+        feed_url: meta.feed_url.as_deref(),
+        items: links
+            .iter()
+            .map(|link| JsonFeedItem {
+                id: link.id.to_string(),
+                url: link.url.to_string(),
+                title: title(link),
+                content_text: link.url.to_string(),
+                date_published: link.date_created.to_rfc3339(),
+                date_modified: updated(link).to_rfc3339(),
+            })
+            .collect(),
+    };
+    // the feed is made up of nothing but strings, so this can't fail
+    serde_json::to_string(&feed).unwrap()
+}
@@ -0,0 +1,152 @@
+//! Firefox's `places.sqlite` profile database.
+
+use std::collections::HashMap;
+
+use {
+    anyhow::{Context, Result},
+    rusqlite::{config::DbConfig, Connection, OpenFlags},
+};
+
+use super::{
+    import::{ImportedLink, Malformed},
+    netscape::unix_timestamp,
+};
+
+// profiles with years of history get big, but not this big
+const MAX_SIZE: usize = 256 * 1024 * 1024;
+
+// the folders firefox creates itself, they don't become groups
+const ROOT_FOLDERS: [&str; 6] = [
+    "root________",
+    "menu________",
+    "toolbar_____",
+    "unfiled_____",
+    "mobile______",
+    "tags________",
+];
+
+struct Folder {
+    parent: i64,
+    title: String,
+    guid: String,
+}
+
+fn read(db: &Connection) -> rusqlite::Result<Vec<ImportedLink>> {
+    let mut folders = HashMap::new();
+    let mut stmt = db.prepare(
+        "SELECT id, parent, coalesce(title, ''), guid FROM moz_bookmarks WHERE type = 2",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        folders.insert(
+            row.get::<_, i64>(0)?,
+            Folder {
+                parent: row.get(1)?,
+                title: row.get(2)?,
+                guid: row.get(3)?,
+            },
+        );
+    }
+    let tags_root = folders
+        .iter()
+        .find(|(_, f)| f.guid == "tags________")
+        .map(|(id, _)| *id);
+
+    let mut stmt = db.prepare(
+        "SELECT b.parent, coalesce(b.title, p.title), b.dateAdded, b.lastModified, p.url, p.id
+        FROM moz_bookmarks b JOIN moz_places p ON p.id = b.fk
+        WHERE b.type = 1 ORDER BY b.dateAdded",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    // tags are stored as folders under the tags root, holding a bookmark for
+    // every place that has the tag
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    let mut links = Vec::new();
+    while let Some(row) = rows.next()? {
+        let parent: i64 = row.get(0)?;
+        let place: i64 = row.get(5)?;
+        match folders.get(&parent) {
+            Some(folder) if tags_root.is_some() && Some(folder.parent) == tags_root => {
+                tags.entry(place).or_default().push(folder.title.clone());
+                continue;
+            }
+            _ => (),
+        }
+
+        let date_added: Option<i64> = row.get(2)?;
+        let last_modified: Option<i64> = row.get(3)?;
+        links.push((
+            place,
+            ImportedLink {
+                url: row.get(4)?,
+                title: row.get(1)?,
+                date_created: date_added.or(last_modified).and_then(unix_timestamp),
+                group: folders
+                    .get(&parent)
+                    .filter(|f| !ROOT_FOLDERS.contains(&f.guid.as_str()))
+                    .map(|f| f.title.clone()),
+                ..Default::default()
+            },
+        ));
+    }
+
+    Ok(links
+        .into_iter()
+        .map(|(place, mut link)| {
+            link.tags = tags.get(&place).cloned().unwrap_or_default();
+            link
+        })
+        .collect())
+}
+
+// the upload comes from whoever is signed in, so it's only ever read, and
+// sqlite is told not to trust anything in it that could change the file
+fn open(path: &std::path::Path) -> rusqlite::Result<Connection> {
+    // firefox keeps places in wal mode, immutable lets it be read without the
+    // wal and shared memory files that would otherwise be needed next to it
+    let uri = format!("file:{}?immutable=1", path.display());
+    let db = Connection::open_with_flags(
+        uri,
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    db.set_db_config(DbConfig::SQLITE_DBCONFIG_DEFENSIVE, true)?;
+    Ok(db)
+}
+
+// sqlite can only open databases from disk, so the upload is collected and
+// written to a temporary file once it's complete
+#[derive(Default)]
+pub struct Parser {
+    buffer: Vec<u8>,
+}
+
+impl super::import::Parser for Parser {
+    fn feed(&mut self, chunk: &[u8]) -> Result<Vec<ImportedLink>> {
+        if self.buffer.len() + chunk.len() > MAX_SIZE {
+            return Err(Malformed("firefox places database is too big".to_string()).into());
+        }
+        self.buffer.extend_from_slice(chunk);
+        Ok(Vec::new())
+    }
+
+    fn finish(&mut self) -> Result<Vec<ImportedLink>> {
+        // a directory of it's own that only this process can read, removed
+        // along with anything sqlite puts in it when it goes out of scope
+        let dir = tempfile::Builder::new()
+            .prefix("places-")
+            .tempdir()
+            .with_context(|| "temporary directory for the places database couldn't be made")?;
+        let path = dir.path().join("places.sqlite");
+        std::fs::write(&path, std::mem::take(&mut self.buffer))
+            .with_context(|| "places database couldn't be written to a temporary file")?;
+
+        let links = open(&path)
+            .and_then(|db| read(&db))
+            .map_err(|e| Malformed(format!("firefox places database isn't valid: {e}")))?;
+
+        Ok(links)
+    }
+}
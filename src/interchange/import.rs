@@ -0,0 +1,425 @@
+//! Turning links parsed out of other services' exports into backlog entries.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+    sync::Arc,
+};
+
+use {
+    anyhow::{anyhow, Error, Result},
+    chrono::Utc,
+    dashmap::DashMap,
+    futures_util::{Stream, StreamExt},
+    reqwest::Url,
+    sea_orm::{
+        entity::{prelude::*, Set},
+        DatabaseConnection, DatabaseTransaction, TransactionTrait,
+    },
+    serde::{Deserialize, Serialize},
+    ulid::Ulid,
+};
+
+use super::{chrome, firefox, instapaper, netscape, pinboard, pocket};
+use crate::{
+    api::{
+        groups::Group,
+        links::{Link, LinkId},
+        UserId,
+    },
+    database::{
+        self,
+        entity::{grouped_links, groups, links, notes, tagged_links, tags},
+    },
+    normalize::Normalizer,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportFormat {
+    Netscape,
+    Pinboard,
+    Pocket,
+    Instapaper,
+    Firefox,
+    Chrome,
+}
+
+impl ImportFormat {
+    pub fn parser(&self) -> Box<dyn Parser + Send> {
+        match self {
+            ImportFormat::Netscape => Box::new(netscape::Parser::default()),
+            ImportFormat::Pinboard => Box::new(pinboard::Parser::default()),
+            ImportFormat::Pocket => Box::new(pocket::Parser::default()),
+            ImportFormat::Instapaper => Box::new(instapaper::Parser::default()),
+            ImportFormat::Firefox => Box::new(firefox::Parser::default()),
+            ImportFormat::Chrome => Box::new(chrome::Parser::default()),
+        }
+    }
+}
+
+impl fmt::Display for ImportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ImportFormat::Netscape => "netscape",
+            ImportFormat::Pinboard => "pinboard",
+            ImportFormat::Pocket => "pocket",
+            ImportFormat::Instapaper => "instapaper",
+            ImportFormat::Firefox => "firefox",
+            ImportFormat::Chrome => "chrome",
+        })
+    }
+}
+
+impl FromStr for ImportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "netscape" => Ok(ImportFormat::Netscape),
+            "pinboard" => Ok(ImportFormat::Pinboard),
+            "pocket" => Ok(ImportFormat::Pocket),
+            "instapaper" => Ok(ImportFormat::Instapaper),
+            "firefox" => Ok(ImportFormat::Firefox),
+            "chrome" => Ok(ImportFormat::Chrome),
+            _ => Err(anyhow!("unknown import format: {s}")),
+        }
+    }
+}
+
+// a link as it was found in an export, before it's been checked
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ImportedLink {
+    pub url: String,
+    pub title: Option<String>,
+    pub date_created: Option<DateTimeWithTimeZone>,
+    pub archived_at: Option<DateTimeWithTimeZone>,
+    pub tags: Vec<String>,
+    pub group: Option<String>,
+    pub sensitive: bool,
+    pub note: Option<String>,
+}
+
+// parsers are handed the export piece by piece as it's uploaded, and give
+// back whatever links they could complete so far
+pub trait Parser {
+    fn feed(&mut self, chunk: &[u8]) -> Result<Vec<ImportedLink>>;
+    fn finish(&mut self) -> Result<Vec<ImportedLink>>;
+}
+
+// the uploaded file couldn't be read as the format it was uploaded as, as
+// opposed to the import failing for reasons that aren't the user's fault
+#[derive(Debug)]
+pub struct Malformed(pub String);
+
+impl fmt::Display for Malformed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Malformed {}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub duplicates: usize,
+    pub invalid: usize,
+}
+
+// links that were previewed and are waiting for the user to confirm them,
+// every user has at most one import pending
+pub type PendingImports = DashMap<UserId, Vec<ImportedLink>>;
+
+// remembers the tags and groups that were already looked up or created, so
+// big imports don't have to ask the database for them over and over
+pub struct ImportSession {
+    dbconn: Arc<DatabaseConnection>,
+    normalizer: Arc<Normalizer>,
+    user_id: UserId,
+    tags: HashMap<String, String>,
+    groups: HashMap<String, String>,
+    // a dry run only checks links and collects them instead of committing
+    dry_run: bool,
+    seen: HashSet<String>,
+    pub previewed: Vec<ImportedLink>,
+    // the links that were saved, for the work that follows saving a link
+    pub saved: Vec<LinkId>,
+    pub report: ImportReport,
+}
+
+impl ImportSession {
+    pub fn new(
+        dbconn: Arc<DatabaseConnection>,
+        normalizer: Arc<Normalizer>,
+        user_id: UserId,
+    ) -> Self {
+        Self {
+            dbconn,
+            normalizer,
+            user_id,
+            tags: HashMap::new(),
+            groups: HashMap::new(),
+            dry_run: false,
+            seen: HashSet::new(),
+            previewed: Vec::new(),
+            saved: Vec::new(),
+            report: ImportReport::default(),
+        }
+    }
+
+    pub fn dry_run(
+        dbconn: Arc<DatabaseConnection>,
+        normalizer: Arc<Normalizer>,
+        user_id: UserId,
+    ) -> Self {
+        Self {
+            dry_run: true,
+            ..Self::new(dbconn, normalizer, user_id)
+        }
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    async fn tag_id(&mut self, txn: &DatabaseTransaction, name: &str) -> Result<String, DbErr> {
+        if let Some(id) = self.tags.get(name) {
+            return Ok(id.clone());
+        }
+
+        let id = match tags::Entity::find()
+            .filter(tags::Column::CreatedBy.eq(self.user_id.clone()))
+            .filter(tags::Column::Name.eq(name))
+            .filter(tags::Column::DeletedAt.is_null())
+            .one(txn)
+            .await?
+        {
+            Some(tag) => tag.id,
+            None => {
+                let id = Ulid::new().to_string();
+                tags::Entity::insert(tags::ActiveModel {
+                    id: Set(id.clone()),
+                    name: Set(name.to_string()),
+                    description: Set(None),
+                    created_by: Set(self.user_id.clone()),
+                    date_created: Set(Utc::now().into()),
+                    modified_at: Set(None),
+                    deleted_at: Set(None),
+                })
+                .exec(txn)
+                .await?;
+                id
+            }
+        };
+        self.tags.insert(name.to_string(), id.clone());
+
+        Ok(id)
+    }
+
+    async fn group_id(&mut self, txn: &DatabaseTransaction, name: &str) -> Result<String, DbErr> {
+        if let Some(id) = self.groups.get(name) {
+            return Ok(id.clone());
+        }
+
+        let id = match groups::Entity::find()
+            .filter(groups::Column::CreatedBy.eq(self.user_id.clone()))
+            .filter(groups::Column::Name.eq(name))
+            .filter(groups::Column::DeletedAt.is_null())
+            .one(txn)
+            .await?
+        {
+            Some(group) => group.id,
+            None => {
+                let group = Group::new(
+                    name.to_string(),
+                    None,
+                    Utc::now().into(),
+                    self.user_id.clone(),
+                );
+                let id = group.id.to_string();
+                groups::Entity::insert(group.into_der()).exec(txn).await?;
+                id
+            }
+        };
+        self.groups.insert(name.to_string(), id.clone());
+
+        Ok(id)
+    }
+
+    // gives back the id of the link when one was saved, so callers can queue
+    // work for it
+    pub async fn add(&mut self, imported: ImportedLink) -> Result<Option<LinkId>, DbErr> {
+        // only web pages are worth keeping, the rest are things like
+        // bookmarklets and browser internal pages, and nothing could fetch
+        // their titles or snapshots anyway
+        let url: Url = match imported.url.trim().parse() {
+            Ok(url) => url,
+            Err(_) => {
+                self.report.invalid += 1;
+                return Ok(None);
+            }
+        };
+        if !matches!(url.scheme(), "http" | "https")
+            || url.as_str().len() > database::URL_LENGTH
+        {
+            self.report.invalid += 1;
+            return Ok(None);
+        }
+
+        // links repeated within the export are caught by the database, except
+        // during a dry run where nothing lands there
+        let normalized_url = self.normalizer.normalize(&url);
+        if !self.seen.insert(normalized_url.clone())
+            || links::Entity::find()
+                .filter(links::Column::CreatedBy.eq(self.user_id.clone()))
+                .filter(links::Column::NormalizedUrl.eq(normalized_url.clone()))
+                .filter(links::Column::DeletedAt.is_null())
+                .one(self.dbconn.as_ref())
+                .await?
+                .is_some()
+        {
+            self.report.duplicates += 1;
+            return Ok(None);
+        }
+
+        if self.dry_run {
+            self.previewed.push(ImportedLink {
+                url: url.to_string(),
+                ..imported
+            });
+            self.report.imported += 1;
+            return Ok(None);
+        }
+
+        let txn = self.dbconn.begin().await?;
+        let saved = match self.insert(&txn, url, normalized_url, imported).await {
+            Ok(id) => txn.commit().await.map(|_| id),
+            Err(e) => Err(e),
+        };
+        match saved {
+            Ok(id) => {
+                self.report.imported += 1;
+                self.saved.push(id);
+                Ok(Some(id))
+            }
+            // tags and groups made along with the link are gone with it
+            Err(e) => {
+                self.tags.clear();
+                self.groups.clear();
+                Err(e)
+            }
+        }
+    }
+
+    // everything that's saved for one link, so it's saved all or not at all
+    async fn insert(
+        &mut self,
+        txn: &DatabaseTransaction,
+        url: Url,
+        normalized_url: String,
+        imported: ImportedLink,
+    ) -> Result<LinkId, DbErr> {
+        let date_created = imported.date_created.unwrap_or_else(|| Utc::now().into());
+        let mut link = Link::new(
+            url,
+            date_created,
+            imported
+                .title
+                .filter(|t| !t.trim().is_empty())
+                .map(|t| database::fit(&t, database::TITLE_LENGTH)),
+            imported.sensitive,
+            self.user_id.clone(),
+        );
+        link.archived_at = imported.archived_at;
+        link.normalized_url = Some(normalized_url);
+        let id = link.id;
+        let link_id = id.to_string();
+        links::Entity::insert(link.into_der()).exec(txn).await?;
+
+        let mut seen_tags = Vec::new();
+        for name in imported.tags {
+            let name = database::fit(name.trim(), database::NAME_LENGTH);
+            if name.is_empty() || seen_tags.contains(&name) {
+                continue;
+            }
+            let tag_id = self.tag_id(txn, &name).await?;
+            tagged_links::Entity::insert(tagged_links::ActiveModel {
+                id: Set(Ulid::new().to_string()),
+                link_id: Set(link_id.clone()),
+                tag_id: Set(tag_id),
+                created_by: Set(self.user_id.clone()),
+                date_created: Set(date_created),
+                deleted_at: Set(None),
+            })
+            .exec(txn)
+            .await?;
+            seen_tags.push(name);
+        }
+
+        if let Some(name) = imported.group.filter(|g| !g.trim().is_empty()) {
+            let group_id = self
+                .group_id(txn, &database::fit(name.trim(), database::NAME_LENGTH))
+                .await?;
+            grouped_links::Entity::insert(grouped_links::ActiveModel {
+                id: Set(Ulid::new().to_string()),
+                link_id: Set(link_id.clone()),
+                group_id: Set(group_id),
+                name: Set(String::new()),
+                description: Set(None),
+                created_by: Set(self.user_id.clone()),
+                date_created: Set(date_created),
+                deleted_at: Set(None),
+            })
+            .exec(txn)
+            .await?;
+        }
+
+        if let Some(body) = imported.note.filter(|n| !n.trim().is_empty()) {
+            notes::Entity::insert(notes::ActiveModel {
+                id: Set(Ulid::new().to_string()),
+                link_id: Set(link_id),
+                body: Set(Some(body)),
+                // notes follow the link, so a sensitive link can't leak
+                // through a public share
+                private: Set(imported.sensitive),
+                created_by: Set(self.user_id.clone()),
+                date_created: Set(date_created),
+                modified_at: Set(None),
+                deleted_at: Set(None),
+            })
+            .exec(txn)
+            .await?;
+        }
+
+        Ok(id)
+    }
+
+    // streams an export through the parser, committing links as soon as
+    // they're complete instead of holding the whole file in memory
+    pub async fn import_stream<S, B, E>(
+        &mut self,
+        mut parser: Box<dyn Parser + Send>,
+        mut stream: S,
+    ) -> Result<ImportReport, Error>
+    where
+        S: Stream<Item = Result<B, E>> + Unpin,
+        B: AsRef<[u8]>,
+        E: Into<Error>,
+    {
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(Into::into)?;
+            for imported in parser.feed(chunk.as_ref())? {
+                self.add(imported).await?;
+            }
+        }
+        // finishing can mean parsing all of a buffered file, or reading a
+        // database from disk, which would hold up everything else waiting here
+        let finished = tokio::task::spawn_blocking(move || parser.finish()).await??;
+        for imported in finished {
+            self.add(imported).await?;
+        }
+
+        Ok(self.report.clone())
+    }
+}
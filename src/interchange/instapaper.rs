@@ -0,0 +1,57 @@
+//! Instapaper's CSV export.
+
+use anyhow::Result;
+
+use super::{
+    delimited::{Records, Row},
+    import::ImportedLink,
+    netscape::parse_timestamp,
+};
+
+fn convert(row: Row) -> ImportedLink {
+    let date_created = row.get("timestamp").and_then(parse_timestamp);
+
+    // the built in folders say where the link is in the queue, any other
+    // folder was made by the user and becomes a group
+    let (archived_at, group) = match row.get("folder") {
+        Some("Archive") => (
+            Some(date_created.unwrap_or_else(|| chrono::Utc::now().into())),
+            None,
+        ),
+        Some("Unread") | Some("Starred") | None => (None, None),
+        Some(folder) => (None, Some(folder.to_string())),
+    };
+
+    // newer exports write the tags as a json array
+    let tags = match row.get("tags") {
+        Some(raw) if raw.starts_with('[') => serde_json::from_str(raw).unwrap_or_default(),
+        Some(raw) => raw.split(',').map(String::from).collect(),
+        None => Vec::new(),
+    };
+
+    ImportedLink {
+        url: row.get("url").unwrap_or_default().to_string(),
+        title: row.get("title").map(String::from),
+        date_created,
+        archived_at,
+        tags,
+        group,
+        sensitive: false,
+        note: row.get("selection").map(String::from),
+    }
+}
+
+#[derive(Default)]
+pub struct Parser {
+    records: Records,
+}
+
+impl super::import::Parser for Parser {
+    fn feed(&mut self, chunk: &[u8]) -> Result<Vec<ImportedLink>> {
+        Ok(self.records.feed(chunk)?.into_iter().map(convert).collect())
+    }
+
+    fn finish(&mut self) -> Result<Vec<ImportedLink>> {
+        Ok(self.records.finish()?.into_iter().map(convert).collect())
+    }
+}
@@ -0,0 +1,73 @@
+//! Markdown documents of links, sectioned by their groups or tags.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use super::AnnotatedLink;
+
+// escapes the characters markdown would otherwise read as formatting
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '!'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn write_link(out: &mut String, annotated: &AnnotatedLink) {
+    let link = &annotated.link;
+    let title = link.title.clone().unwrap_or_else(|| link.url.to_string());
+    let _ = writeln!(out, "- [{}](<{}>)", escape(&title), link.url);
+
+    // every note becomes it's own quote nested under the link
+    for note in &annotated.notes {
+        out.push('\n');
+        for line in note.lines() {
+            match line.trim_end() {
+                "" => out.push_str("  >\n"),
+                line => {
+                    let _ = writeln!(out, "  > {line}");
+                }
+            }
+        }
+    }
+}
+
+// writes a section per group or tag, links filed under several of them show
+// up in each and the ones filed under none are collected at the end
+pub fn write(
+    title: &str,
+    links: &[AnnotatedLink],
+    sections: fn(&AnnotatedLink) -> &[String],
+    leftover: &str,
+) -> String {
+    let mut sectioned: BTreeMap<&str, Vec<&AnnotatedLink>> = BTreeMap::new();
+    let mut unsectioned = Vec::new();
+    for link in links {
+        if sections(link).is_empty() {
+            unsectioned.push(link);
+        }
+        for name in sections(link) {
+            sectioned.entry(name.as_str()).or_default().push(link);
+        }
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}", escape(title));
+    let unsectioned = match unsectioned.is_empty() {
+        true => None,
+        false => Some((leftover, unsectioned)),
+    };
+    for (name, links) in sectioned.into_iter().chain(unsectioned) {
+        let _ = write!(out, "\n## {}\n\n", escape(name));
+        for link in links {
+            write_link(&mut out, link);
+        }
+    }
+    out
+}
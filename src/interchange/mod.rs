@@ -0,0 +1,95 @@
+//! Formats for moving links in and out of the backlog.
+
+pub mod backup;
+mod chrome;
+pub mod citation;
+pub mod delimited;
+pub mod feed;
+mod firefox;
+pub mod import;
+mod instapaper;
+pub mod markdown;
+pub mod netscape;
+mod pinboard;
+mod pocket;
+
+use crate::api::links::Link;
+
+// a link along with the names of the groups and tags it was filed under and
+// the notes written on it
+pub struct AnnotatedLink {
+    pub link: Link,
+    pub groups: Vec<String>,
+    pub tags: Vec<String>,
+    pub notes: Vec<String>,
+}
+
+// escapes text so it can be placed inside xml or html elements and attributes
+pub fn escape_markup(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// decodes the character references that exporters commonly write, anything
+// it doesn't recognize is left as it is
+pub fn unescape_markup(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        unescaped.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let semi = match rest.bytes().take(12).position(|b| b == b';') {
+            Some(semi) => semi,
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let entity = &rest[1..semi];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let code = if let Some(hex) = entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(dec) = entity.strip_prefix('#') {
+                    dec.parse().ok()
+                } else {
+                    None
+                };
+                code.and_then(char::from_u32)
+            }
+        };
+        match decoded {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
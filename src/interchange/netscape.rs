@@ -0,0 +1,453 @@
+//! The Netscape bookmark file format, as exported by browsers and most
+//! bookmarking services.
+
+use std::fmt::Write;
+
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+use super::{escape_markup, import::ImportedLink, unescape_markup};
+use crate::api::links::Link;
+
+// turns unix timestamps into dates, some exporters write milli or
+// microseconds instead of seconds so those get scaled down first
+pub fn parse_timestamp(raw: &str) -> Option<DateTimeWithTimeZone> {
+    unix_timestamp(raw.trim().parse().ok()?)
+}
+
+pub fn unix_timestamp(mut stamp: i64) -> Option<DateTimeWithTimeZone> {
+    while stamp > 100_000_000_000 {
+        stamp /= 1000;
+    }
+    Utc.timestamp_opt(stamp, 0).single().map(|d| d.into())
+}
+
+// splits the inside of a tag into it's lowercased name and attributes
+fn parse_tag(inner: &str) -> (String, Vec<(String, String)>) {
+    let inner = inner.trim_end_matches('/');
+    let name_end = inner
+        .find(|c: char| c.is_whitespace())
+        .unwrap_or(inner.len());
+    let name = inner[..name_end].to_ascii_lowercase();
+
+    let mut attrs = Vec::new();
+    let mut rest = inner[name_end..].trim_start();
+    while !rest.is_empty() {
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = rest[..key_end].to_ascii_lowercase();
+        rest = rest[key_end..].trim_start();
+
+        let value = if let Some(after_eq) = rest.strip_prefix('=') {
+            let after_eq = after_eq.trim_start();
+            match after_eq.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let body = &after_eq[1..];
+                    let end = body.find(quote).unwrap_or(body.len());
+                    rest = body.get(end + 1..).unwrap_or("").trim_start();
+                    &body[..end]
+                }
+                _ => {
+                    let end = after_eq
+                        .find(|c: char| c.is_whitespace())
+                        .unwrap_or(after_eq.len());
+                    rest = after_eq[end..].trim_start();
+                    &after_eq[..end]
+                }
+            }
+        } else {
+            ""
+        };
+
+        if !key.is_empty() {
+            attrs.push((key, unescape_markup(value)));
+        } else {
+            // never loop on something that can't be parsed
+            break;
+        }
+    }
+
+    (name, attrs)
+}
+
+fn attr<'a>(attrs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attrs
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+// what the text that comes next in the file belongs to
+enum Capture {
+    FolderName(String),
+    LinkTitle(String),
+    Description(String),
+}
+
+#[derive(Default)]
+pub struct Parser {
+    buffer: Vec<u8>,
+    // the folders the parser is currently inside of, the root list doesn't
+    // have a name
+    folders: Vec<Option<String>>,
+    next_folder: Option<String>,
+    capture: Option<Capture>,
+    // a link is held back until it's clear whether a description follows it
+    pending: Option<ImportedLink>,
+}
+
+impl Parser {
+    fn flush(&mut self, out: &mut Vec<ImportedLink>) {
+        if let Some(Capture::Description(text)) = self.capture.take() {
+            if let Some(link) = self.pending.as_mut() {
+                let text = unescape_markup(text.trim());
+                if !text.is_empty() {
+                    link.note = Some(text);
+                }
+            }
+        }
+        if let Some(link) = self.pending.take() {
+            out.push(link);
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some(
+            Capture::FolderName(buf) | Capture::LinkTitle(buf) | Capture::Description(buf),
+        ) = self.capture.as_mut()
+        {
+            buf.push_str(text);
+        }
+    }
+
+    fn tag(&mut self, inner: &str, out: &mut Vec<ImportedLink>) {
+        let closing = inner.starts_with('/');
+        let (name, attrs) = parse_tag(inner.trim_start_matches('/'));
+
+        match (closing, name.as_str()) {
+            (false, "dt") | (false, "dl") | (true, "dl") => {
+                self.flush(out);
+                if name == "dl" {
+                    if closing {
+                        self.folders.pop();
+                    } else {
+                        self.folders.push(self.next_folder.take());
+                    }
+                }
+            }
+            (false, "h3") => {
+                self.flush(out);
+                self.capture = Some(Capture::FolderName(String::new()));
+            }
+            (true, "h3") => {
+                if let Some(Capture::FolderName(folder)) = self.capture.take() {
+                    self.next_folder = Some(unescape_markup(folder.trim()));
+                }
+            }
+            (false, "a") => {
+                self.flush(out);
+                let href = attr(&attrs, "href").unwrap_or_default().to_string();
+                self.pending = Some(ImportedLink {
+                    url: href,
+                    date_created: attr(&attrs, "add_date").and_then(parse_timestamp),
+                    tags: attr(&attrs, "tags")
+                        .map(|t| t.split(',').map(|t| t.trim().to_string()).collect())
+                        .unwrap_or_default(),
+                    group: self.folders.iter().rev().flatten().next().cloned(),
+                    sensitive: matches!(attr(&attrs, "private"), Some("1")),
+                    ..Default::default()
+                });
+                self.capture = Some(Capture::LinkTitle(String::new()));
+            }
+            (true, "a") => {
+                if let Some(Capture::LinkTitle(title)) = self.capture.take() {
+                    if let Some(link) = self.pending.as_mut() {
+                        link.title = Some(unescape_markup(title.trim()));
+                    }
+                }
+            }
+            (false, "dd") if self.pending.is_some() => {
+                self.capture = Some(Capture::Description(String::new()));
+            }
+            // line breaks are the only markup descriptions carry
+            (false, "br") => self.text("\n"),
+            _ => (),
+        }
+    }
+}
+
+impl super::import::Parser for Parser {
+    fn feed(&mut self, chunk: &[u8]) -> Result<Vec<ImportedLink>> {
+        self.buffer.extend_from_slice(chunk);
+
+        // only complete pieces of markup are consumed, whatever is left over
+        // waits for the next chunk
+        let mut out = Vec::new();
+        let mut pos = 0;
+        let buffer = std::mem::take(&mut self.buffer);
+        while let Some(lt) = buffer[pos..].iter().position(|b| *b == b'<') {
+            let lt = pos + lt;
+
+            let end = if buffer[lt..].starts_with(b"<!--") {
+                find(&buffer[lt..], b"-->").map(|e| lt + e + 3)
+            } else {
+                match tag_end(&buffer[lt..]) {
+                    Some(Markup::Tag(e)) => Some(lt + e + 1),
+                    // a '<' in a title or description, it's kept as text
+                    Some(Markup::Text(next)) => {
+                        self.text(&String::from_utf8_lossy(&buffer[pos..lt + next]));
+                        pos = lt + next;
+                        continue;
+                    }
+                    None => None,
+                }
+            };
+            let end = match end {
+                Some(end) => end,
+                None => break,
+            };
+
+            self.text(&String::from_utf8_lossy(&buffer[pos..lt]));
+            let inner = String::from_utf8_lossy(&buffer[lt + 1..end - 1]);
+            if !inner.starts_with('!') {
+                self.tag(inner.trim(), &mut out);
+            }
+            pos = end;
+        }
+        self.buffer = buffer[pos..].to_vec();
+
+        Ok(out)
+    }
+
+    fn finish(&mut self) -> Result<Vec<ImportedLink>> {
+        let rest = std::mem::take(&mut self.buffer);
+        self.text(&String::from_utf8_lossy(&rest));
+
+        let mut out = Vec::new();
+        self.flush(&mut out);
+        Ok(out)
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// how far the markup starting at a '<' goes
+enum Markup {
+    // a tag closed by the bracket at that offset
+    Tag(usize),
+    // another '<' at that offset came first, so the one before was just text
+    Text(usize),
+}
+
+// finds the closing bracket of a tag, skipping over quoted attribute values,
+// only quotes right after a '=' start one so apostrophes in text don't hide
+// the rest of the file
+fn tag_end(buf: &[u8]) -> Option<Markup> {
+    let mut quote = None;
+    let mut last = b'<';
+    for (i, b) in buf.iter().enumerate().skip(1) {
+        match (quote, *b) {
+            (None, b'"') | (None, b'\'') if last == b'=' => quote = Some(*b),
+            (Some(q), c) if c == q => {
+                quote = None;
+                last = c;
+            }
+            (None, b'>') => return Some(Markup::Tag(i)),
+            (None, b'<') => return Some(Markup::Text(i)),
+            (None, c) if !c.is_ascii_whitespace() => last = c,
+            _ => (),
+        }
+    }
+    None
+}
+
+// a link along with everything the bookmark file can carry about it
+pub struct Bookmark {
+    pub link: Link,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+}
+
+// a group written out as a folder of bookmarks
+pub struct Folder {
+    pub name: String,
+    pub date_created: DateTimeWithTimeZone,
+    pub bookmarks: Vec<Bookmark>,
+}
+
+fn write_bookmark(out: &mut String, bookmark: &Bookmark, indent: &str) {
+    let link = &bookmark.link;
+    let _ = write!(
+        out,
+        "{indent}<DT><A HREF=\"{}\" ADD_DATE=\"{}\"",
+        escape_markup(link.url.as_str()),
+        link.date_created.timestamp()
+    );
+    if let Some(modified_at) = link.modified_at {
+        let _ = write!(out, " LAST_MODIFIED=\"{}\"", modified_at.timestamp());
+    }
+    if link.sensitive {
+        out.push_str(" PRIVATE=\"1\"");
+    }
+    if link.archived_at.is_none() {
+        out.push_str(" TOREAD=\"1\"");
+    }
+    // commas separate tags, so they can't appear inside of one
+    let tags: Vec<String> = bookmark.tags.iter().map(|t| t.replace(',', " ")).collect();
+    if !tags.is_empty() {
+        let _ = write!(out, " TAGS=\"{}\"", escape_markup(&tags.join(",")));
+    }
+    let title = link.title.clone().unwrap_or_else(|| link.url.to_string());
+    let _ = writeln!(out, ">{}</A>", escape_markup(&title));
+
+    if let Some(note) = &bookmark.note {
+        let _ = writeln!(
+            out,
+            "{indent}<DD>{}",
+            escape_markup(note).replace('\n', "<BR>")
+        );
+    }
+}
+
+// writes the bookmark file the way browsers do, so every browser and
+// bookmark manager that reads their exports can read this one too
+pub fn write(folders: &[Folder], unfiled: &[Bookmark]) -> String {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE NETSCAPE-Bookmark-file-1>\n");
+    out.push_str("<!-- This is an automatically generated file.\n");
+    out.push_str("     It will be read and overwritten.\n");
+    out.push_str("     DO NOT EDIT! -->\n");
+    out.push_str("<META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n");
+    out.push_str("<TITLE>Bookmarks</TITLE>\n");
+    out.push_str("<H1>Bookmarks</H1>\n");
+    out.push_str("<DL><p>\n");
+    for folder in folders {
+        let _ = writeln!(
+            out,
+            "    <DT><H3 ADD_DATE=\"{}\">{}</H3>",
+            folder.date_created.timestamp(),
+            escape_markup(&folder.name)
+        );
+        out.push_str("    <DL><p>\n");
+        for bookmark in &folder.bookmarks {
+            write_bookmark(&mut out, bookmark, "        ");
+        }
+        out.push_str("    </DL><p>\n");
+    }
+    for bookmark in unfiled {
+        write_bookmark(&mut out, bookmark, "    ");
+    }
+    out.push_str("</DL><p>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interchange::import::Parser as _;
+
+    const EXPORT: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<!-- This is an automatically generated file. -->
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks</H1>
+<DL><p>
+    <DT><H3 ADD_DATE="1650000000">Reading &amp; Notes</H3>
+    <DL><p>
+        <DT><A HREF="https://example.com/a?x=1&amp;y=2" ADD_DATE="1650000000000" TAGS="rust, web" PRIVATE="1">First &lt;post&gt;</A>
+        <DD>Worth a second look<br>later
+        <DT><A HREF='https://example.com/b' ADD_DATE=1650000001>Second</A>
+    </DL><p>
+    <DT><A HREF="https://example.com/c">Unfiled</A>
+</DL><p>
+"#;
+
+    // the file arrives in pieces that split tags and text anywhere
+    fn parse(chunk_size: usize) -> Vec<ImportedLink> {
+        let mut parser = Parser::default();
+        let mut links = Vec::new();
+        for chunk in EXPORT.as_bytes().chunks(chunk_size) {
+            links.extend(parser.feed(chunk).unwrap());
+        }
+        links.extend(parser.finish().unwrap());
+        links
+    }
+
+    #[test]
+    fn reads_bookmarks() {
+        let links = parse(EXPORT.len());
+        assert_eq!(links.len(), 3);
+
+        assert_eq!(links[0].url, "https://example.com/a?x=1&y=2");
+        assert_eq!(links[0].title.as_deref(), Some("First <post>"));
+        assert_eq!(links[0].tags, ["rust", "web"]);
+        assert_eq!(links[0].group.as_deref(), Some("Reading & Notes"));
+        assert!(links[0].sensitive);
+        assert_eq!(links[0].note.as_deref(), Some("Worth a second look\nlater"));
+        assert_eq!(
+            links[0].date_created.map(|d| d.timestamp()),
+            Some(1_650_000_000)
+        );
+
+        assert_eq!(links[1].url, "https://example.com/b");
+        assert_eq!(links[1].group.as_deref(), Some("Reading & Notes"));
+        assert!(!links[1].sensitive);
+        assert_eq!(links[1].note, None);
+        assert_eq!(
+            links[1].date_created.map(|d| d.timestamp()),
+            Some(1_650_000_001)
+        );
+
+        assert_eq!(links[2].url, "https://example.com/c");
+        assert_eq!(links[2].group, None);
+        assert!(links[2].tags.is_empty());
+    }
+
+    #[test]
+    fn chunk_boundaries_dont_matter() {
+        let whole = parse(EXPORT.len());
+        for size in [1, 2, 7, 64] {
+            let pieces = parse(size);
+            assert_eq!(pieces.len(), whole.len(), "chunks of {}", size);
+            for (a, b) in pieces.iter().zip(&whole) {
+                assert_eq!(
+                    (&a.url, &a.title, &a.group, &a.note),
+                    (&b.url, &b.title, &b.group, &b.note),
+                    "chunks of {}",
+                    size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stray_brackets_stay_text() {
+        let export = r#"<DL><p>
+    <DT><A HREF="https://example.com/a">a <b's guide</A>
+    <DD>for when x < y's turn
+    <DT><A HREF="https://example.com/b" TAGS="it's">Second</A>
+    <DT><A HREF="https://example.com/c">Third</A>
+</DL><p>
+"#;
+        for size in [1, 5, export.len()] {
+            let mut parser = Parser::default();
+            let mut links = Vec::new();
+            for chunk in export.as_bytes().chunks(size) {
+                links.extend(parser.feed(chunk).unwrap());
+            }
+            links.extend(parser.finish().unwrap());
+
+            assert_eq!(links.len(), 3, "chunks of {}", size);
+            assert_eq!(links[0].title.as_deref(), Some("a <b's guide"));
+            assert_eq!(links[0].note.as_deref(), Some("for when x < y's turn"));
+            assert_eq!(links[1].tags, ["it's"]);
+            assert_eq!(links[2].url, "https://example.com/c");
+        }
+    }
+}
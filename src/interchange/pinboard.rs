@@ -0,0 +1,70 @@
+//! Pinboard's JSON export, as returned by `posts/all?format=json`.
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use super::import::{ImportedLink, Malformed};
+
+// every post pinboard knows about fits in far less, and the whole export is
+// held in memory twice while it's parsed
+const MAX_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct Post {
+    href: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    extended: String,
+    time: Option<String>,
+    #[serde(default)]
+    shared: String,
+    #[serde(default)]
+    toread: String,
+    #[serde(default)]
+    tags: String,
+}
+
+// the export is one big array, so it's collected in full before parsing
+#[derive(Default)]
+pub struct Parser {
+    buffer: Vec<u8>,
+}
+
+impl super::import::Parser for Parser {
+    fn feed(&mut self, chunk: &[u8]) -> Result<Vec<ImportedLink>> {
+        if self.buffer.len() + chunk.len() > MAX_SIZE {
+            return Err(Malformed("pinboard export is too big".to_string()).into());
+        }
+        self.buffer.extend_from_slice(chunk);
+        Ok(Vec::new())
+    }
+
+    fn finish(&mut self) -> Result<Vec<ImportedLink>> {
+        let posts: Vec<Post> = serde_json::from_slice(&std::mem::take(&mut self.buffer))
+            .map_err(|e| Malformed(format!("pinboard export isn't valid: {e}")))?;
+
+        Ok(posts
+            .into_iter()
+            .map(|post| {
+                let date_created = post
+                    .time
+                    .and_then(|t| chrono::DateTime::parse_from_rfc3339(&t).ok());
+                ImportedLink {
+                    url: post.href,
+                    title: Some(post.description),
+                    date_created,
+                    // everything pinboard doesn't have queued up was read
+                    archived_at: match post.toread.as_str() {
+                        "yes" => None,
+                        _ => Some(date_created.unwrap_or_else(|| chrono::Utc::now().into())),
+                    },
+                    tags: post.tags.split_whitespace().map(String::from).collect(),
+                    group: None,
+                    sensitive: post.shared == "no",
+                    note: Some(post.extended),
+                }
+            })
+            .collect())
+    }
+}
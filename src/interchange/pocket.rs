@@ -0,0 +1,46 @@
+//! Pocket's CSV export, the `part_000000.csv` files in the download.
+
+use anyhow::Result;
+
+use super::{
+    delimited::{Records, Row},
+    import::ImportedLink,
+    netscape::parse_timestamp,
+};
+
+fn convert(row: Row) -> ImportedLink {
+    let date_created = row.get("time_added").and_then(parse_timestamp);
+    ImportedLink {
+        url: row.get("url").unwrap_or_default().to_string(),
+        // pocket fills in the url when it never found a title
+        title: row
+            .get("title")
+            .filter(|t| Some(*t) != row.get("url"))
+            .map(String::from),
+        date_created,
+        archived_at: match row.get("status") {
+            Some("archive") => Some(date_created.unwrap_or_else(|| chrono::Utc::now().into())),
+            _ => None,
+        },
+        tags: row
+            .get("tags")
+            .map(|t| t.split('|').map(String::from).collect())
+            .unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+#[derive(Default)]
+pub struct Parser {
+    records: Records,
+}
+
+impl super::import::Parser for Parser {
+    fn feed(&mut self, chunk: &[u8]) -> Result<Vec<ImportedLink>> {
+        Ok(self.records.feed(chunk)?.into_iter().map(convert).collect())
+    }
+
+    fn finish(&mut self) -> Result<Vec<ImportedLink>> {
+        Ok(self.records.finish()?.into_iter().map(convert).collect())
+    }
+}
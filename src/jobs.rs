@@ -0,0 +1,448 @@
+//! Background work kept in the database, so it survives restarts and failed
+//! attempts are tried again later instead of being lost.
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use {
+    anyhow::{anyhow, Error, Result},
+    chrono::Utc,
+    rand::Rng,
+    sea_orm::{
+        entity::{prelude::*, Set},
+        sea_query::Expr,
+        DatabaseBackend, DatabaseConnection, Statement,
+    },
+    serde::{Deserialize, Serialize},
+    serde_json::json,
+    tokio::sync::Notify,
+    tracing::{error, info, warn},
+    ulid::Ulid,
+};
+
+use crate::{
+    api::{links::LinkId, UserId},
+    config,
+    database::entity::jobs,
+    digests::Digests,
+    health::{self, LinkChecker},
+    mail::{MailId, Outbox},
+    metadata::Fetcher,
+    normalize::{self, Normalizer},
+    reminders::Reminders,
+    snapshots::{self, Snapshotter},
+    webhooks::{DeliveryId, Webhooks},
+};
+
+// used when the configuration doesn't say otherwise
+const WORKERS: usize = 2;
+// how long an idle worker waits before looking again, new jobs pushed by this
+// process wake it up earlier
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_ATTEMPTS: i32 = 5;
+const BASE_BACKOFF: i64 = 30;
+const MAX_BACKOFF: i64 = 6 * 60 * 60;
+// days finished jobs are kept around for
+const RETENTION: i64 = 7;
+pub const PRUNE_INTERVAL: i64 = 24 * 60 * 60;
+// how often a running job's lock is renewed, it's only taken over once it
+// missed several of these
+const HEARTBEAT: Duration = Duration::from_secs(60);
+
+// takes the job that's been waiting the longest, other workers skip over it
+// instead of waiting on it's lock, jobs whose lock wasn't renewed for a while
+// belonged to a worker that went away and are taken over
+const CLAIM: &str = r#"UPDATE "jobs"
+SET "status" = 'running', "locked_at" = NOW(), "attempts" = "attempts" + 1
+WHERE "id" = (
+    SELECT "id" FROM "jobs"
+    WHERE ("status" = 'queued' AND "run_at" <= NOW())
+        OR ("status" = 'running' AND "locked_at" < NOW() - INTERVAL '5 minutes')
+    ORDER BY "run_at"
+    LIMIT 1
+    FOR UPDATE SKIP LOCKED
+)
+RETURNING *"#;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", content = "payload", rename_all = "snake_case")]
+pub enum Job {
+    FetchMetadata { link_id: LinkId },
+    CheckLink { link_id: LinkId },
+    // queues the links that are due for a check, and then itself again
+    CheckLinks,
+    Snapshot { link_id: LinkId },
+    // deletes snapshots that are too old, and then queues itself again
+    PruneSnapshots,
+    // brings the stored addresses of links in line with the current rules
+    NormalizeUrls,
+    // queued for when a snoozed link is due
+    Remind { link_id: LinkId },
+    // queued for when the user's digest is due, and then again by itself
+    SendDigest { user_id: UserId },
+    // hands an email from the outbox to the mail server
+    SendMail { mail_id: MailId },
+    // sends an event to a subscriber's webhook
+    DeliverWebhook { delivery_id: DeliveryId },
+    // deletes jobs that finished long enough ago, and then queues itself again
+    PruneJobs,
+}
+
+impl Job {
+    // splits the job into the kind and payload columns
+    fn into_row(self) -> Result<(String, Json)> {
+        let mut value = serde_json::to_value(self)?;
+        let kind = value["kind"]
+            .as_str()
+            .ok_or_else(|| anyhow!("job has no kind"))?
+            .to_string();
+        Ok((kind, value["payload"].take()))
+    }
+
+    fn from_row(row: &jobs::Model) -> Result<Self> {
+        Ok(serde_json::from_value(
+            json!({ "kind": row.kind, "payload": row.payload }),
+        )?)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Queued,
+    Running,
+    Done,
+    // gave up after running out of attempts
+    Dead,
+}
+
+impl Status {
+    pub const ALL: [Status; 4] = [Status::Queued, Status::Running, Status::Done, Status::Dead];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Queued => "queued",
+            Status::Running => "running",
+            Status::Done => "done",
+            Status::Dead => "dead",
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// waits twice as long after every failed attempt, with some jitter so jobs
+// that failed together don't all come back at once
+fn backoff(attempts: i32) -> chrono::Duration {
+    let exponent = attempts.clamp(1, 20) as u32 - 1;
+    let seconds = BASE_BACKOFF
+        .saturating_mul(2i64.pow(exponent))
+        .min(MAX_BACKOFF);
+    let jitter = rand::thread_rng().gen_range(0..=seconds / 4);
+    chrono::Duration::seconds(seconds + jitter)
+}
+
+// where jobs are put, it takes nothing but the database so jobs can be
+// queued from anywhere, the command line included, the workers of the server
+// pick them up from there
+pub struct Queue {
+    dbconn: Arc<DatabaseConnection>,
+    wakeup: Notify,
+}
+
+impl Queue {
+    pub fn new(dbconn: Arc<DatabaseConnection>) -> Self {
+        Self {
+            dbconn,
+            wakeup: Notify::new(),
+        }
+    }
+
+    pub async fn push(&self, job: Job) -> Result<()> {
+        self.push_at(job, Utc::now().into()).await
+    }
+
+    // runs the job once it's time has come, not before
+    pub async fn push_at(&self, job: Job, run_at: DateTimeWithTimeZone) -> Result<()> {
+        let (kind, payload) = job.into_row()?;
+        let now = Utc::now();
+        jobs::Entity::insert(jobs::ActiveModel {
+            id: Set(Ulid::new().to_string()),
+            kind: Set(kind),
+            payload: Set(payload),
+            status: Set(Status::Queued.to_string()),
+            attempts: Set(0),
+            max_attempts: Set(MAX_ATTEMPTS),
+            run_at: Set(run_at),
+            locked_at: Set(None),
+            last_error: Set(None),
+            date_created: Set(now.into()),
+            finished_at: Set(None),
+        })
+        .exec(self.dbconn.as_ref())
+        .await?;
+        self.wakeup.notify_one();
+
+        Ok(())
+    }
+
+    // leaves the job out when the same one is already waiting or running,
+    // for jobs that keep rescheduling themselves and must only exist once
+    pub async fn push_unique(&self, job: Job) -> Result<()> {
+        let (kind, payload) = job.clone().into_row()?;
+        let pending = jobs::Entity::find()
+            .filter(jobs::Column::Kind.eq(kind))
+            .filter(jobs::Column::Payload.eq(payload))
+            .filter(jobs::Column::Status.is_in([Status::Queued.as_str(), Status::Running.as_str()]))
+            .one(self.dbconn.as_ref())
+            .await?;
+        if pending.is_some() {
+            return Ok(());
+        }
+
+        self.push(job).await
+    }
+
+    // the work every new link gets, however it was saved, a link without a
+    // title or snapshot is still saved so failing to queue it is only logged
+    pub async fn push_saved(&self, link_ids: &[LinkId], snapshots: bool) {
+        for link_id in link_ids {
+            let link_id = *link_id;
+            if let Err(e) = self.push(Job::FetchMetadata { link_id }).await {
+                warn!("fetching metadata for link {link_id} couldn't be queued: {e}");
+            }
+            if snapshots {
+                if let Err(e) = self.push(Job::Snapshot { link_id }).await {
+                    warn!("snapshot of link {link_id} couldn't be queued: {e}");
+                }
+            }
+        }
+    }
+
+    // puts a dead job back in line with a fresh set of attempts
+    pub async fn retry(&self, id: &str) -> Result<bool, DbErr> {
+        let row = match jobs::Entity::find_by_id(id.to_string())
+            .filter(jobs::Column::Status.eq(Status::Dead.as_str()))
+            .one(self.dbconn.as_ref())
+            .await?
+        {
+            Some(row) => row,
+            None => return Ok(false),
+        };
+
+        let mut der: jobs::ActiveModel = row.into();
+        der.status = Set(Status::Queued.to_string());
+        der.attempts = Set(0);
+        der.run_at = Set(Utc::now().into());
+        der.finished_at = Set(None);
+        der.update(self.dbconn.as_ref()).await?;
+        self.wakeup.notify_one();
+
+        Ok(true)
+    }
+}
+
+// runs the jobs, each kind by the part of the application it belongs to
+pub struct Workers {
+    workers: usize,
+    retention: i64,
+    queue: Arc<Queue>,
+    fetcher: Arc<Fetcher>,
+    checker: Arc<LinkChecker>,
+    snapshotter: Option<Arc<Snapshotter>>,
+    normalizer: Arc<Normalizer>,
+    reminders: Arc<Reminders>,
+    digests: Arc<Digests>,
+    outbox: Arc<Outbox>,
+    webhooks: Arc<Webhooks>,
+}
+
+impl Workers {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        cfg: Option<&config::Jobs>,
+        queue: Arc<Queue>,
+        fetcher: Arc<Fetcher>,
+        checker: Arc<LinkChecker>,
+        snapshotter: Option<Arc<Snapshotter>>,
+        normalizer: Arc<Normalizer>,
+        reminders: Arc<Reminders>,
+        digests: Arc<Digests>,
+        outbox: Arc<Outbox>,
+        webhooks: Arc<Webhooks>,
+    ) -> Self {
+        Self {
+            workers: cfg.and_then(|c| c.workers).unwrap_or(WORKERS),
+            retention: cfg.and_then(|c| c.retention).unwrap_or(RETENTION),
+            queue,
+            fetcher,
+            checker,
+            snapshotter,
+            normalizer,
+            reminders,
+            digests,
+            outbox,
+            webhooks,
+        }
+    }
+
+    async fn claim(&self) -> Result<Option<jobs::Model>, DbErr> {
+        jobs::Entity::find()
+            .from_raw_sql(Statement::from_string(
+                DatabaseBackend::Postgres,
+                CLAIM.to_string(),
+            ))
+            .one(self.queue.dbconn.as_ref())
+            .await
+    }
+
+    // snapshot jobs can outlive the configuration that queued them
+    fn snapshotter(&self) -> Result<&Snapshotter> {
+        self.snapshotter
+            .as_deref()
+            .ok_or_else(|| anyhow!("snapshots are not configured"))
+    }
+
+    async fn run(&self, job: Job) -> Result<()> {
+        match job {
+            Job::FetchMetadata { link_id } => self.fetcher.refresh(&link_id).await,
+            Job::CheckLink { link_id } => self.checker.check(&link_id).await,
+            // the next sweep is only queued once this one went through, so
+            // retries of a failed sweep don't start chains of their own
+            Job::CheckLinks => {
+                health::schedule(self.queue.dbconn.as_ref(), &self.queue).await?;
+                let next = Utc::now() + chrono::Duration::seconds(health::SWEEP_INTERVAL);
+                self.queue.push_at(Job::CheckLinks, next.into()).await
+            }
+            Job::Snapshot { link_id } => self.snapshotter()?.take(&link_id).await,
+            Job::PruneSnapshots => {
+                self.snapshotter()?.prune().await?;
+                let next = Utc::now() + chrono::Duration::seconds(snapshots::PRUNE_INTERVAL);
+                self.queue.push_at(Job::PruneSnapshots, next.into()).await
+            }
+            Job::NormalizeUrls => {
+                normalize::renormalize(self.queue.dbconn.as_ref(), &self.normalizer).await
+            }
+            Job::Remind { link_id } => self.reminders.remind(&self.queue, &link_id).await,
+            Job::SendDigest { user_id } => self.digests.send(&self.queue, &user_id).await,
+            Job::SendMail { mail_id } => self.outbox.deliver(&mail_id).await,
+            Job::DeliverWebhook { delivery_id } => self.webhooks.deliver(&delivery_id).await,
+            Job::PruneJobs => {
+                self.prune().await?;
+                let next = Utc::now() + chrono::Duration::seconds(PRUNE_INTERVAL);
+                self.queue.push_at(Job::PruneJobs, next.into()).await
+            }
+        }
+    }
+
+    // every link check is a job of it's own, so finished jobs would pile up
+    // without end, dead ones are kept just as long for retrying them
+    async fn prune(&self) -> Result<()> {
+        let cutoff = Utc::now() - chrono::Duration::days(self.retention);
+        let pruned = jobs::Entity::delete_many()
+            .filter(jobs::Column::Status.is_in([Status::Done.as_str(), Status::Dead.as_str()]))
+            .filter(jobs::Column::FinishedAt.lt(cutoff))
+            .exec(self.queue.dbconn.as_ref())
+            .await?;
+        info!("pruned {} finished jobs", pruned.rows_affected);
+
+        Ok(())
+    }
+
+    // renews the lock of a job for as long as it runs, long jobs like
+    // normalizing every link would otherwise be taken over and run twice
+    async fn heartbeat(self: Arc<Self>, id: String) {
+        loop {
+            tokio::time::sleep(HEARTBEAT).await;
+            let now: DateTimeWithTimeZone = Utc::now().into();
+            let renewed = jobs::Entity::update_many()
+                .col_expr(jobs::Column::LockedAt, Expr::value(now))
+                .filter(jobs::Column::Id.eq(id.clone()))
+                .filter(jobs::Column::Status.eq(Status::Running.as_str()))
+                .exec(self.queue.dbconn.as_ref())
+                .await;
+            if let Err(e) = renewed {
+                warn!("lock of job {id} couldn't be renewed: {e}");
+            }
+        }
+    }
+
+    // records how the attempt went, failures are tried again later until the
+    // job runs out of attempts, jobs that can't even be read are buried
+    // right away since trying again won't change anything
+    async fn settle(
+        &self,
+        row: jobs::Model,
+        result: Result<(), Error>,
+        retryable: bool,
+    ) -> Result<(), DbErr> {
+        let now = Utc::now();
+        let mut der: jobs::ActiveModel = row.clone().into();
+        der.locked_at = Set(None);
+        match result {
+            Ok(()) => {
+                der.status = Set(Status::Done.to_string());
+                der.finished_at = Set(Some(now.into()));
+                der.last_error = Set(None);
+            }
+            Err(e) if !retryable || row.attempts >= row.max_attempts => {
+                warn!("{} job {} gave up: {e:#}", row.kind, row.id);
+                der.status = Set(Status::Dead.to_string());
+                der.finished_at = Set(Some(now.into()));
+                der.last_error = Set(Some(format!("{e:#}")));
+            }
+            Err(e) => {
+                info!(
+                    "{} job {} failed, trying again later: {e:#}",
+                    row.kind, row.id
+                );
+                der.status = Set(Status::Queued.to_string());
+                der.run_at = Set((now + backoff(row.attempts)).into());
+                der.last_error = Set(Some(format!("{e:#}")));
+            }
+        }
+        der.update(self.queue.dbconn.as_ref()).await?;
+
+        Ok(())
+    }
+
+    async fn work(self: Arc<Self>) {
+        loop {
+            let row = match self.claim().await {
+                Ok(Some(row)) => row,
+                Ok(None) => {
+                    let _ = tokio::time::timeout(POLL_INTERVAL, self.queue.wakeup.notified()).await;
+                    continue;
+                }
+                Err(e) => {
+                    error!("claiming a job from the database failed: {e}");
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                    continue;
+                }
+            };
+
+            let settled = match Job::from_row(&row) {
+                Ok(job) => {
+                    let heartbeat = tokio::spawn(self.clone().heartbeat(row.id.clone()));
+                    let result = self.run(job).await;
+                    heartbeat.abort();
+                    self.settle(row, result, true).await
+                }
+                Err(e) => self.settle(row, Err(e), false).await,
+            };
+            if let Err(e) = settled {
+                error!("outcome of a job couldn't be saved: {e}");
+            }
+        }
+    }
+
+    // starts the workers, they run for as long as the application does
+    pub fn start(self: &Arc<Self>) {
+        info!("starting {} job workers", self.workers);
+        for _ in 0..self.workers {
+            tokio::spawn(self.clone().work());
+        }
+    }
+}
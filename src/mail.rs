@@ -0,0 +1,241 @@
+//! Sending email through the configured mail server. Messages are rendered
+//! from templates and kept in the outbox, a job hands each one to the mail
+//! server and tries again later when that doesn't work out.
+
+use std::{sync::Arc, time::Duration};
+
+use {
+    anyhow::{anyhow, bail, Result},
+    chrono::Utc,
+    lettre::{
+        message::{Mailbox, MultiPart},
+        transport::smtp::{authentication::Credentials, extension::ClientId},
+        AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+    },
+    sea_orm::{
+        entity::{prelude::*, Set},
+        sea_query::Expr,
+        DatabaseConnection,
+    },
+    tera::{Context, Tera},
+    tracing::{error, info, warn},
+    ulid::Ulid,
+};
+
+use crate::{
+    config::{self, TlsMode},
+    database::entity::outbox,
+    jobs::{Job, Queue},
+};
+
+pub type MailId = Ulid;
+
+const TIMEOUT: u64 = 30;
+
+pub struct Message {
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+// hands messages to the mail server, a connection is made for every message
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn new(cfg: &config::Smtp) -> Result<Self> {
+        let tls = cfg.tls.unwrap_or(TlsMode::Starttls);
+        let port = cfg.port.unwrap_or(match tls {
+            TlsMode::None => 25,
+            TlsMode::Starttls => 587,
+            TlsMode::Tls => 465,
+        });
+        let credentials = match (&cfg.username, &cfg.password) {
+            (Some(username), Some(password)) => {
+                Some(Credentials::new(username.clone(), password.clone()))
+            }
+            (None, None) => None,
+            _ => bail!("smtp needs both a username and a password, or neither"),
+        };
+        if credentials.is_some() && tls == TlsMode::None {
+            bail!("smtp credentials are only sent over tls");
+        }
+        let from: Mailbox = cfg
+            .from
+            .parse()
+            .map_err(|e| anyhow!("smtp from address {:?} is not valid: {e}", cfg.from))?;
+
+        let builder = match tls {
+            TlsMode::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&cfg.host),
+            TlsMode::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&cfg.host)?,
+            TlsMode::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&cfg.host)?,
+        };
+        let mut builder = builder
+            .port(port)
+            .timeout(Some(Duration::from_secs(TIMEOUT)))
+            .hello_name(ClientId::Domain(from.email.domain().to_string()));
+        if let Some(credentials) = credentials {
+            builder = builder.credentials(credentials);
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+
+    // a text and a html version of the same message, mail clients show the
+    // last one they understand
+    fn format(&self, message: &Message) -> Result<lettre::Message> {
+        let to: Mailbox = message
+            .to
+            .parse()
+            .map_err(|e| anyhow!("{:?} is not a valid address: {e}", message.to))?;
+        Ok(lettre::Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(message.subject.as_str())
+            .message_id(Some(format!(
+                "<{}@{}>",
+                Ulid::new(),
+                self.from.email.domain()
+            )))
+            .multipart(MultiPart::alternative_plain_html(
+                message.text.clone(),
+                message.html.clone(),
+            ))?)
+    }
+
+    pub async fn send(&self, message: &Message) -> Result<()> {
+        self.transport.send(self.format(message)?).await?;
+        Ok(())
+    }
+}
+
+// every email goes through here, it's kept until the mail server took it
+pub struct Outbox {
+    tmpl: Arc<Tera>,
+    dbconn: Arc<DatabaseConnection>,
+    mailer: Option<Mailer>,
+}
+
+impl Outbox {
+    pub fn new(
+        cfg: Option<&config::Smtp>,
+        tmpl: Arc<Tera>,
+        dbconn: Arc<DatabaseConnection>,
+    ) -> Result<Self> {
+        let mailer = match cfg {
+            Some(cfg) => Some(Mailer::new(cfg)?),
+            None => None,
+        };
+        Ok(Self {
+            tmpl,
+            dbconn,
+            mailer,
+        })
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.mailer.is_some()
+    }
+
+    // renders email/<template>.html.tera and email/<template>.txt.tera and
+    // puts the message in the outbox, nothing happens without a mail server
+    pub async fn send(
+        &self,
+        queue: &Queue,
+        to: &str,
+        subject: &str,
+        template: &str,
+        ctx: &Context,
+    ) -> Result<Option<MailId>> {
+        if !self.is_configured() {
+            info!("{template} email to {to} not sent, smtp is not configured");
+            return Ok(None);
+        }
+
+        let id = Ulid::new();
+        outbox::Entity::insert(outbox::ActiveModel {
+            id: Set(id.to_string()),
+            to_address: Set(to.to_string()),
+            subject: Set(subject.to_string()),
+            html: Set(self
+                .tmpl
+                .render(&format!("email/{template}.html.tera"), ctx)?),
+            text: Set(self
+                .tmpl
+                .render(&format!("email/{template}.txt.tera"), ctx)?),
+            template: Set(template.to_string()),
+            attempts: Set(0),
+            last_error: Set(None),
+            date_created: Set(Utc::now().into()),
+            sent_at: Set(None),
+        })
+        .exec(self.dbconn.as_ref())
+        .await?;
+        queue.push(Job::SendMail { mail_id: id }).await?;
+
+        Ok(Some(id))
+    }
+
+    // hands the message to the mail server, what went wrong is kept with it
+    // and the job tries again later
+    pub async fn deliver(&self, id: &MailId) -> Result<()> {
+        let row = match outbox::Entity::find_by_id(id.to_string())
+            .one(self.dbconn.as_ref())
+            .await?
+        {
+            Some(row) if row.sent_at.is_none() => row,
+            _ => return Ok(()),
+        };
+        let mailer = self
+            .mailer
+            .as_ref()
+            .ok_or_else(|| anyhow!("smtp is not configured"))?;
+
+        let message = Message {
+            to: row.to_address.clone(),
+            subject: row.subject.clone(),
+            html: row.html.clone(),
+            text: row.text.clone(),
+        };
+        if let Err(e) = mailer.send(&message).await {
+            warn!(
+                "sending {:?} to {} failed: {e:#}",
+                message.subject, message.to
+            );
+            let attempts = row.attempts + 1;
+            let mut der: outbox::ActiveModel = row.into();
+            der.attempts = Set(attempts);
+            der.last_error = Set(Some(format!("{e:#}")));
+            der.update(self.dbconn.as_ref()).await?;
+            return Err(e);
+        }
+        info!("sent {:?} to {}", message.subject, message.to);
+
+        // the mail server has the message now, failing the job from here on
+        // would only send it again
+        let sent = outbox::Entity::update_many()
+            .col_expr(
+                outbox::Column::SentAt,
+                Expr::value(DateTimeWithTimeZone::from(Utc::now())),
+            )
+            .col_expr(
+                outbox::Column::Attempts,
+                Expr::col(outbox::Column::Attempts).add(1),
+            )
+            .col_expr(outbox::Column::LastError, Expr::value(None::<String>))
+            .filter(outbox::Column::Id.eq(id.to_string()))
+            .exec(self.dbconn.as_ref())
+            .await;
+        if let Err(e) = sent {
+            error!("{id} was sent, but couldn't be marked as sent: {e}");
+        }
+
+        Ok(())
+    }
+}
@@ -2,10 +2,24 @@
 
 mod api;
 mod app;
+mod cli;
 mod config;
 mod database;
+mod digests;
+mod health;
 mod identity;
+mod inbound;
+mod interchange;
+mod jobs;
+mod mail;
+mod metadata;
+mod net;
+mod normalize;
 mod pages;
+mod reader;
+mod reminders;
+mod snapshots;
+mod webhooks;
 
 use std::{str::FromStr, sync::Arc};
 
@@ -50,7 +64,12 @@ pub fn main() -> Result<(), Error> {
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
     rt.block_on(async {
+        if let Some("import") = args.first().map(String::as_str) {
+            return cli::import(&cfg, &args[1..]).await;
+        }
+
         let mut app = Application::prepare(cfg).await?;
         app.listen_and_serve().await?;
 
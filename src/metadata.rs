@@ -0,0 +1,290 @@
+//! Titles, descriptions and previews read out of the pages links point to,
+//! fetched in the background after a link was saved.
+
+use std::{sync::Arc, time::Duration};
+
+use {
+    anyhow::Result,
+    chrono::Utc,
+    reqwest::{header, Url},
+    scraper::{Html, Selector},
+    sea_orm::{
+        entity::{prelude::*, Set},
+        DatabaseConnection,
+    },
+    serde::{Deserialize, Serialize},
+    tokio::sync::Semaphore,
+};
+
+use crate::{
+    api::links::LinkId,
+    config,
+    database::{
+        self,
+        entity::{link_articles, links},
+    },
+    interchange::citation::{self, Citation},
+    net::{Client, Guard},
+    reader::{self, Article},
+};
+
+// used when the configuration doesn't say otherwise
+const TIMEOUT: u64 = 10;
+const PAGE_LIMIT: usize = 1024 * 1024;
+const CONCURRENCY: usize = 4;
+const REDIRECTS: usize = 5;
+
+// what a page says about itself besides it's title
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Preview {
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub image_url: Option<String>,
+    #[serde(default)]
+    pub canonical_url: Option<String>,
+    #[serde(default)]
+    pub favicon_url: Option<String>,
+    #[serde(default)]
+    pub fetched_at: Option<DateTimeWithTimeZone>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PageMetadata {
+    pub title: Option<String>,
+    pub preview: Preview,
+    pub citation: Citation,
+    pub article: Option<Article>,
+}
+
+// collapses the whitespace pages pad their titles and descriptions with
+fn clean(text: &str) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+// references to other resources only count when a browser could follow them
+fn resolve(base: &Url, href: &str) -> Option<String> {
+    base.join(href.trim())
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .map(String::from)
+}
+
+// reads the title and preview of a page, opengraph tags are preferred since
+// they're written for exactly this, plain html is the fallback
+pub fn parse(html: &str, base: &Url) -> PageMetadata {
+    let document = Html::parse_document(html);
+
+    let selector = Selector::parse("meta[content]").unwrap();
+    let metas: Vec<(String, String)> = document
+        .select(&selector)
+        .filter_map(|meta| {
+            let meta = meta.value();
+            let key = meta.attr("property").or_else(|| meta.attr("name"))?;
+            Some((
+                key.trim().to_ascii_lowercase(),
+                meta.attr("content")?.to_string(),
+            ))
+        })
+        .collect();
+    let meta = |names: &[&str]| -> Option<String> {
+        names.iter().find_map(|name| {
+            metas
+                .iter()
+                .filter(|(key, _)| key == name)
+                .find_map(|(_, content)| clean(content))
+        })
+    };
+    let link_href = |rel: &str| -> Option<String> {
+        let selector = Selector::parse(&format!("link[rel~=\"{rel}\" i][href]")).unwrap();
+        document
+            .select(&selector)
+            .find_map(|link| resolve(base, link.value().attr("href")?))
+    };
+
+    let title = meta(&["og:title", "twitter:title"]).or_else(|| {
+        let selector = Selector::parse("title").unwrap();
+        document
+            .select(&selector)
+            .next()
+            .and_then(|title| clean(&title.text().collect::<String>()))
+    });
+    let description = meta(&["og:description", "twitter:description", "description"]);
+    let image_url = meta(&["og:image", "og:image:url", "twitter:image"])
+        .and_then(|image| resolve(base, &image));
+    let canonical_url =
+        link_href("canonical").or_else(|| meta(&["og:url"]).and_then(|url| resolve(base, &url)));
+    // browsers look for the icon at the root when the page doesn't name one
+    let favicon_url = link_href("icon")
+        .or_else(|| link_href("apple-touch-icon"))
+        .or_else(|| resolve(base, "/favicon.ico"));
+
+    // the authors named for citations are usually spelled out more cleanly
+    // than a byline in the text
+    let citation = citation::extract(&document);
+    let article = reader::extract(&document, base).map(|mut article| {
+        if !citation.authors.is_empty() {
+            article.byline = Some(citation.authors.join(", "));
+        }
+        article
+    });
+
+    PageMetadata {
+        title,
+        preview: Preview {
+            description,
+            image_url,
+            canonical_url,
+            favicon_url,
+            fetched_at: Some(Utc::now().into()),
+        },
+        citation,
+        article,
+    }
+}
+
+pub struct Fetcher {
+    client: Client,
+    page_limit: usize,
+    permits: Semaphore,
+    dbconn: Arc<DatabaseConnection>,
+}
+
+impl Fetcher {
+    pub fn new(
+        cfg: Option<&config::Fetcher>,
+        guard: &Arc<Guard>,
+        dbconn: Arc<DatabaseConnection>,
+    ) -> Result<Self> {
+        let timeout = cfg.and_then(|c| c.timeout).unwrap_or(TIMEOUT);
+        let client = guard.client(Duration::from_secs(timeout), REDIRECTS)?;
+
+        Ok(Self {
+            client,
+            page_limit: cfg.and_then(|c| c.page_limit).unwrap_or(PAGE_LIMIT),
+            permits: Semaphore::new(cfg.and_then(|c| c.concurrency).unwrap_or(CONCURRENCY)),
+            dbconn,
+        })
+    }
+
+    // downloads the page and reads it's metadata, only so many pages are
+    // downloaded at once and only the start of each is read
+    pub async fn fetch(&self, url: &Url) -> Result<PageMetadata> {
+        let _permit = self.permits.acquire().await?;
+
+        let mut resp = self
+            .client
+            .get(url.clone())?
+            .header(header::ACCEPT, "text/html,application/xhtml+xml")
+            .send()
+            .await?
+            .error_for_status()?;
+        let base = resp.url().clone();
+
+        // anything else, like pdfs and images, has nothing to read
+        let is_html = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|t| t.to_str().ok())
+            .map(|t| t.contains("html"))
+            .unwrap_or(true);
+        if !is_html {
+            return Ok(PageMetadata {
+                preview: Preview {
+                    fetched_at: Some(Utc::now().into()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= self.page_limit {
+                body.truncate(self.page_limit);
+                break;
+            }
+        }
+
+        Ok(parse(&String::from_utf8_lossy(&body), &base))
+    }
+
+    // fills in the link's metadata, run from the job queue so saving a link
+    // never has to wait on the page it points to
+    pub async fn refresh(&self, link_id: &LinkId) -> Result<()> {
+        // links deleted or merged away since being queued have nothing to fill
+        let link = match links::Entity::find_by_id(link_id.to_string())
+            .filter(links::Column::DeletedAt.is_null())
+            .one(self.dbconn.as_ref())
+            .await?
+        {
+            Some(link) => link,
+            None => return Ok(()),
+        };
+        // only web pages can be fetched, trying others again wouldn't help
+        let url: Url = link.url.parse()?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Ok(());
+        }
+        let found = self.fetch(&url).await?;
+
+        // whatever the user entered themselves is never overwritten
+        let mut der: links::ActiveModel = link.clone().into();
+        // pages can have titles of any length, the column can't
+        if link.title.is_none() {
+            der.title = Set(found
+                .title
+                .map(|title| database::fit(&title, database::TITLE_LENGTH)));
+        }
+        if link.authors.is_none() && !found.citation.authors.is_empty() {
+            der.authors = Set(Some(Json::from(found.citation.authors)));
+        }
+        if link.published_on.is_none() {
+            der.published_on = Set(found.citation.published_on);
+        }
+        if link.site_name.is_none() {
+            der.site_name = Set(found.citation.site_name);
+        }
+        // a doi that's cut short points somewhere else, so it's left out
+        if link.doi.is_none() {
+            der.doi = Set(found
+                .citation
+                .doi
+                .filter(|doi| doi.chars().count() <= database::DOI_LENGTH));
+        }
+        der.description = Set(found.preview.description);
+        der.image_url = Set(found.preview.image_url);
+        der.canonical_url = Set(found.preview.canonical_url);
+        der.favicon_url = Set(found.preview.favicon_url);
+        der.fetched_at = Set(found.preview.fetched_at);
+        der.update(self.dbconn.as_ref()).await?;
+
+        // the article is read again every time, pages get corrected
+        if let Some(article) = found.article {
+            let existing = link_articles::Entity::find_by_id(link_id.to_string())
+                .one(self.dbconn.as_ref())
+                .await?;
+            let der = link_articles::ActiveModel {
+                link_id: Set(link_id.to_string()),
+                byline: Set(article.byline),
+                content: Set(article.content),
+                text: Set(article.text),
+                word_count: Set(article.word_count as i32),
+                reading_minutes: Set(article.reading_minutes as i32),
+                extracted_at: Set(Utc::now().into()),
+            };
+            match existing {
+                Some(_) => der.update(self.dbconn.as_ref()).await?,
+                None => der.insert(self.dbconn.as_ref()).await?,
+            };
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+//! Requests to addresses users hand in, like the pages links point to and
+//! webhook receivers. They all go through the client here, which won't reach
+//! the machines around this one, such as cloud metadata endpoints or admin
+//! pages only listening on localhost, not even by way of a redirect.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use {
+    anyhow::{anyhow, Result},
+    hyper::client::connect::dns::Name,
+    ipnet::IpNet,
+    reqwest::{
+        dns::{Addrs, Resolve, Resolving},
+        redirect, Method, RequestBuilder, Url,
+    },
+};
+
+use crate::config;
+
+// loopback, private, shared, link-local, unique-local, multicast, reserved and
+// unspecified addresses, along with the benchmarking and protocol ranges
+const INTERNAL: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/128",
+    "::1/128",
+    "fc00::/7",
+    "fe80::/10",
+    "ff00::/8",
+];
+
+pub struct Guard {
+    internal: Vec<IpNet>,
+    allow: Vec<IpNet>,
+}
+
+impl Guard {
+    pub fn new(cfg: Option<&config::Network>) -> Self {
+        Self {
+            internal: INTERNAL.iter().map(|net| net.parse().unwrap()).collect(),
+            allow: cfg.map(|c| c.allow.clone()).unwrap_or_default(),
+        }
+    }
+
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        // ipv4 addresses can be written as ipv6 ones, and are treated the same
+        let ip = match ip {
+            IpAddr::V6(v6) => match v6.segments() {
+                [0, 0, 0, 0, 0, 0xffff, _, _] | [0x64, 0xff9b, 0, 0, 0, 0, _, _] => {
+                    let [.., a, b, c, d] = v6.octets();
+                    IpAddr::from([a, b, c, d])
+                }
+                _ => ip,
+            },
+            IpAddr::V4(_) => ip,
+        };
+        self.allow.iter().any(|net| net.contains(&ip))
+            || !self.internal.iter().any(|net| net.contains(&ip))
+    }
+
+    // hosts written out as an address are connected to without asking the
+    // resolver, so they're checked before the request is made
+    pub fn check(&self, url: &Url) -> Result<()> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!("only http and https addresses can be fetched"));
+        }
+        let host = url.host_str().ok_or_else(|| anyhow!("{url} has no host"))?;
+        // ipv6 hosts come in brackets
+        match host.trim_start_matches('[').trim_end_matches(']').parse() {
+            Ok(ip) if !self.allows(ip) => Err(anyhow!("{ip} is on the inside of the network")),
+            _ => Ok(()),
+        }
+    }
+
+    // for addresses that are kept to be fetched later, like webhooks, so the
+    // user hears about it right away, they're checked again when fetched
+    pub async fn check_resolved(&self, url: &Url) -> Result<()> {
+        self.check(url)?;
+        let host = url.host_str().unwrap_or_default();
+        let port = url.port_or_known_default().unwrap_or_default();
+        let name = host.trim_start_matches('[').trim_end_matches(']');
+        for addr in tokio::net::lookup_host((name, port)).await? {
+            if !self.allows(addr.ip()) {
+                return Err(anyhow!("{host} is on the inside of the network"));
+            }
+        }
+        Ok(())
+    }
+
+    // redirects are followed up to the given number of times, never to an
+    // address that couldn't have been asked for directly, with none the
+    // redirect itself comes back
+    pub fn client(self: &Arc<Self>, timeout: Duration, redirects: usize) -> Result<Client> {
+        let guard = self.clone();
+        let policy = redirect::Policy::custom(move |attempt| {
+            if redirects == 0 {
+                return attempt.stop();
+            }
+            if attempt.previous().len() > redirects {
+                return attempt.error(anyhow!("too many redirects"));
+            }
+            match guard.check(attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(e) => attempt.error(e),
+            }
+        });
+        let inner = reqwest::Client::builder()
+            .timeout(timeout)
+            .redirect(policy)
+            .dns_resolver(Arc::new(Resolver(self.clone())))
+            // a proxy would look the address up itself, past the resolver
+            .no_proxy()
+            .user_agent(concat!("link_backlog/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+
+        Ok(Client {
+            inner,
+            guard: self.clone(),
+        })
+    }
+}
+
+// looks names up like usual, but only hands back the addresses that are
+// allowed, which fails the connection when none of them are
+struct Resolver(Arc<Guard>);
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let guard = self.0.clone();
+        Box::pin(async move {
+            let found: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| guard.allows(addr.ip()))
+                .collect();
+            if found.is_empty() {
+                return Err(anyhow!("{} is on the inside of the network", name.as_str()).into());
+            }
+            let addrs: Addrs = Box::new(found.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Client {
+    inner: reqwest::Client,
+    guard: Arc<Guard>,
+}
+
+impl Client {
+    pub fn request(&self, method: Method, url: Url) -> Result<RequestBuilder> {
+        self.guard.check(&url)?;
+        Ok(self.inner.request(method, url))
+    }
+
+    pub fn get(&self, url: Url) -> Result<RequestBuilder> {
+        self.request(Method::GET, url)
+    }
+
+    pub fn post(&self, url: Url) -> Result<RequestBuilder> {
+        self.request(Method::POST, url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard(allow: &[&str]) -> Guard {
+        Guard::new(Some(&config::Network {
+            allow: allow.iter().map(|net| net.parse().unwrap()).collect(),
+        }))
+    }
+
+    #[test]
+    fn refuses_internal_addresses() {
+        let guard = guard(&[]);
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.20.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.100.100.200",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(!guard.allows(ip.parse().unwrap()), "{} was allowed", ip);
+        }
+    }
+
+    #[test]
+    fn allows_public_addresses() {
+        let guard = guard(&[]);
+        for ip in ["93.184.216.34", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(guard.allows(ip.parse().unwrap()), "{} was refused", ip);
+        }
+    }
+
+    #[test]
+    fn allow_list_lets_networks_through() {
+        let guard = guard(&["10.20.0.0/16"]);
+        assert!(guard.allows("10.20.3.4".parse().unwrap()));
+        assert!(!guard.allows("10.21.3.4".parse().unwrap()));
+    }
+
+    #[test]
+    fn checks_literal_hosts() {
+        let guard = guard(&[]);
+        let check = |url: &str| guard.check(&url.parse().unwrap()).is_ok();
+        assert!(check("https://example.com/page"));
+        assert!(!check("http://169.254.169.254/latest/meta-data/"));
+        assert!(!check("http://[::1]:8080/admin"));
+        assert!(!check("http://0x7f000001/"));
+        assert!(!check("ftp://example.com/file"));
+    }
+}
@@ -0,0 +1,370 @@
+//! Brings the addresses of links into one form, so the same page saved twice
+//! is recognized even when it's address was written a little differently or
+//! came with tracking parameters attached.
+
+use {
+    anyhow::Result,
+    reqwest::Url,
+    sea_orm::{entity::prelude::*, sea_query::Expr, DatabaseConnection, QueryOrder, QuerySelect},
+    tracing::{info, warn},
+};
+
+use crate::{config, database::entity::links};
+
+// how many links are looked at a time when the stored addresses are updated
+const BATCH_SIZE: u64 = 500;
+
+// parameters that only tell the site where a visitor came from, a trailing *
+// matches every parameter starting with what comes before it
+const TRACKING_PARAMS: &[&str] = &[
+    "utm_*",
+    "_ga",
+    "_gl",
+    "_hsenc",
+    "_hsmi",
+    "dclid",
+    "fbclid",
+    "gbraid",
+    "gclid",
+    "gclsrc",
+    "igshid",
+    "mc_cid",
+    "mc_eid",
+    "mkt_tok",
+    "msclkid",
+    "oly_anon_id",
+    "oly_enc_id",
+    "ref_src",
+    "twclid",
+    "vero_id",
+    "wbraid",
+    "yclid",
+];
+
+fn matches(pattern: &str, param: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => param.starts_with(prefix),
+        None => param == pattern,
+    }
+}
+
+// a site's rules apply to it and all of it's subdomains
+struct Site {
+    host: String,
+    strip_params: Vec<String>,
+    only_params: Option<Vec<String>>,
+}
+
+pub struct Normalizer {
+    strip_params: Vec<String>,
+    keep_params: Vec<String>,
+    sites: Vec<Site>,
+    ignore_scheme: bool,
+    ignore_www: bool,
+    keep_trailing_slash: bool,
+    keep_fragments: bool,
+}
+
+impl Normalizer {
+    pub fn new(cfg: Option<&config::Normalization>) -> Self {
+        let lowercase = |params: &[String]| -> Vec<String> {
+            params.iter().map(|p| p.to_ascii_lowercase()).collect()
+        };
+        let mut strip_params: Vec<String> = TRACKING_PARAMS.iter().map(|p| p.to_string()).collect();
+        strip_params.extend(cfg.iter().flat_map(|c| lowercase(&c.strip_params)));
+
+        Self {
+            strip_params,
+            keep_params: cfg.map(|c| lowercase(&c.keep_params)).unwrap_or_default(),
+            sites: cfg
+                .iter()
+                .flat_map(|c| c.sites.iter())
+                .map(|site| Site {
+                    host: site.host.trim_start_matches("www.").to_ascii_lowercase(),
+                    strip_params: lowercase(&site.strip_params),
+                    only_params: site.only_params.as_deref().map(lowercase),
+                })
+                .collect(),
+            ignore_scheme: cfg.and_then(|c| c.ignore_scheme).unwrap_or(true),
+            ignore_www: cfg.and_then(|c| c.ignore_www).unwrap_or(false),
+            keep_trailing_slash: cfg.and_then(|c| c.keep_trailing_slash).unwrap_or(false),
+            keep_fragments: cfg.and_then(|c| c.keep_fragments).unwrap_or(false),
+        }
+    }
+
+    // the host is lowercased and default ports are left out by the parser
+    // already, everything else is up to the rules
+    pub fn normalize(&self, url: &Url) -> String {
+        if !matches!(url.scheme(), "http" | "https") {
+            let mut url = url.clone();
+            url.set_fragment(None);
+            return url.to_string();
+        }
+
+        let scheme = if self.ignore_scheme {
+            "https"
+        } else {
+            url.scheme()
+        };
+        let mut host = url.host_str().unwrap_or_default().trim_end_matches('.');
+        if self.ignore_www {
+            host = host.strip_prefix("www.").unwrap_or(host);
+        }
+        let site = self.sites.iter().find(|site| {
+            let host = host.strip_prefix("www.").unwrap_or(host);
+            host == site.host || host.ends_with(&format!(".{}", site.host))
+        });
+
+        let mut normalized = format!("{scheme}://{host}");
+        // a port that's the default of the other scheme is the default here
+        // too, when both schemes are treated as one
+        match url.port() {
+            Some(443) if scheme == "https" => (),
+            Some(80) if self.ignore_scheme => (),
+            Some(port) => normalized.push_str(&format!(":{port}")),
+            None => (),
+        }
+
+        let mut path = url.path();
+        if !self.keep_trailing_slash && path.len() > 1 {
+            path = path.trim_end_matches('/');
+        }
+        normalized.push_str(path);
+
+        // the parameters that are left are sorted, their order doesn't change
+        // what page is shown
+        let mut params: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(key, _)| {
+                let key = key.to_ascii_lowercase();
+                if let Some(only) = site.and_then(|s| s.only_params.as_ref()) {
+                    return only.iter().any(|p| matches(p, &key));
+                }
+                if self.keep_params.iter().any(|p| matches(p, &key)) {
+                    return true;
+                }
+                !self
+                    .strip_params
+                    .iter()
+                    .chain(site.iter().flat_map(|s| s.strip_params.iter()))
+                    .any(|p| matches(p, &key))
+            })
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        if !params.is_empty() {
+            params.sort();
+            let mut encoded = url.clone();
+            encoded.query_pairs_mut().clear().extend_pairs(params);
+            normalized.push('?');
+            normalized.push_str(encoded.query().unwrap_or_default());
+        }
+
+        // fragments usually only point somewhere on the page, except on sites
+        // that route with them
+        if let Some(fragment) = url.fragment() {
+            if self.keep_fragments || fragment.starts_with('/') || fragment.starts_with('!') {
+                normalized.push('#');
+                normalized.push_str(fragment);
+            }
+        }
+
+        normalized
+    }
+}
+
+// brings the stored addresses in line with the current rules, links that
+// would end up the same as another link of their owner are left without one
+// until the duplicates are merged
+pub async fn renormalize(dbconn: &DatabaseConnection, normalizer: &Normalizer) -> Result<()> {
+    let mut after = String::new();
+    let mut changed = 0;
+    loop {
+        let batch = links::Entity::find()
+            .filter(links::Column::Id.gt(after.clone()))
+            .order_by_asc(links::Column::Id)
+            .limit(BATCH_SIZE)
+            .all(dbconn)
+            .await?;
+        let last = match batch.last() {
+            Some(link) => link.id.clone(),
+            None => break,
+        };
+
+        for link in batch {
+            let normalized = match link.url.parse() {
+                Ok(url) => Some(normalizer.normalize(&url)),
+                Err(e) => {
+                    warn!("address of link {} couldn't be parsed: {e}", link.id);
+                    None
+                }
+            };
+            if normalized == link.normalized_url {
+                continue;
+            }
+
+            let taken = match &normalized {
+                Some(normalized) if link.deleted_at.is_none() => links::Entity::find()
+                    .filter(links::Column::Id.ne(link.id.clone()))
+                    .filter(links::Column::CreatedBy.eq(link.created_by.clone()))
+                    .filter(links::Column::NormalizedUrl.eq(normalized.clone()))
+                    .filter(links::Column::DeletedAt.is_null())
+                    .one(dbconn)
+                    .await?
+                    .is_some(),
+                _ => false,
+            };
+            let normalized = if taken { None } else { normalized };
+            if normalized == link.normalized_url {
+                continue;
+            }
+
+            links::Entity::update_many()
+                .col_expr(links::Column::NormalizedUrl, Expr::value(normalized))
+                .filter(links::Column::Id.eq(link.id))
+                .exec(dbconn)
+                .await?;
+            changed += 1;
+        }
+
+        after = last;
+    }
+    if changed > 0 {
+        info!("updated the normalized addresses of {changed} links");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configured(cfg: &str) -> Normalizer {
+        Normalizer::new(Some(&toml::from_str(cfg).unwrap()))
+    }
+
+    fn normalize(normalizer: &Normalizer, url: &str) -> String {
+        normalizer.normalize(&url.parse().unwrap())
+    }
+
+    #[test]
+    fn strips_tracking_params() {
+        let normalizer = Normalizer::new(None);
+        assert_eq!(
+            normalize(
+                &normalizer,
+                "https://example.com/post?utm_source=feed&id=3&fbclid=abc&UTM_Medium=x"
+            ),
+            "https://example.com/post?id=3"
+        );
+        assert_eq!(
+            normalize(&normalizer, "https://example.com/post?b=2&a=1&gclid=x"),
+            "https://example.com/post?a=1&b=2"
+        );
+    }
+
+    #[test]
+    fn configured_params_are_stripped_or_kept() {
+        let normalizer = configured(
+            r#"
+            strip_params = ["ref", "session_*"]
+            keep_params = ["utm_campaign"]
+            "#,
+        );
+        assert_eq!(
+            normalize(
+                &normalizer,
+                "https://example.com/?ref=hn&session_id=1&utm_campaign=launch&utm_source=x"
+            ),
+            "https://example.com/?utm_campaign=launch"
+        );
+    }
+
+    #[test]
+    fn scheme_and_www() {
+        let normalizer = Normalizer::new(None);
+        assert_eq!(
+            normalize(&normalizer, "http://Example.com:80/page"),
+            "https://example.com/page"
+        );
+        assert_eq!(
+            normalize(&normalizer, "http://www.example.com/page"),
+            "https://www.example.com/page"
+        );
+
+        let normalizer = configured("ignore_scheme = false\nignore_www = true");
+        assert_eq!(
+            normalize(&normalizer, "http://www.example.com/page"),
+            "http://example.com/page"
+        );
+        assert_eq!(
+            normalize(&normalizer, "http://example.com:443/page"),
+            "http://example.com:443/page"
+        );
+    }
+
+    #[test]
+    fn trailing_slash() {
+        let normalizer = Normalizer::new(None);
+        assert_eq!(
+            normalize(&normalizer, "https://example.com/docs/"),
+            "https://example.com/docs"
+        );
+        assert_eq!(
+            normalize(&normalizer, "https://example.com/"),
+            "https://example.com/"
+        );
+
+        let normalizer = configured("keep_trailing_slash = true");
+        assert_eq!(
+            normalize(&normalizer, "https://example.com/docs/"),
+            "https://example.com/docs/"
+        );
+    }
+
+    #[test]
+    fn fragments() {
+        let normalizer = Normalizer::new(None);
+        assert_eq!(
+            normalize(&normalizer, "https://example.com/page#section"),
+            "https://example.com/page"
+        );
+        assert_eq!(
+            normalize(&normalizer, "https://example.com/#!/inbox/1"),
+            "https://example.com/#!/inbox/1"
+        );
+        assert_eq!(
+            normalize(&normalizer, "https://example.com/#/settings"),
+            "https://example.com/#/settings"
+        );
+
+        let normalizer = configured("keep_fragments = true");
+        assert_eq!(
+            normalize(&normalizer, "https://example.com/page#section"),
+            "https://example.com/page#section"
+        );
+    }
+
+    #[test]
+    fn site_only_params() {
+        let normalizer = configured(
+            r#"
+            [[sites]]
+            host = "www.youtube.com"
+            only_params = ["v", "t"]
+            "#,
+        );
+        assert_eq!(
+            normalize(
+                &normalizer,
+                "https://m.youtube.com/watch?feature=share&v=abc&list=x&t=10"
+            ),
+            "https://m.youtube.com/watch?t=10&v=abc"
+        );
+        // other sites keep their parameters
+        assert_eq!(
+            normalize(&normalizer, "https://example.com/watch?feature=share"),
+            "https://example.com/watch?feature=share"
+        );
+    }
+}
@@ -0,0 +1,307 @@
+use std::sync::Arc;
+
+use {
+    axum::{
+        extract::{Extension, Form, Path, Query},
+        http::StatusCode,
+        response::{Html, Redirect},
+    },
+    sea_orm::{entity::prelude::*, DatabaseConnection, QueryOrder, QuerySelect},
+    serde::{Deserialize, Serialize},
+    tera::{Context, Tera},
+    tower_cookies::Cookies,
+    tracing::{error, warn},
+};
+
+use crate::{
+    app::{Sessions, User},
+    config::Configuration,
+    database::entity::{jobs, outbox},
+    identity,
+    jobs::{Queue, Status},
+    mail::Outbox,
+};
+
+// emails shown on the mail page, the newest first
+const OUTBOX_ENTRIES: u64 = 50;
+
+#[derive(Deserialize)]
+pub struct JobsParameters {
+    page: Option<usize>,
+    jobs_per_page: Option<usize>,
+    status: Option<String>,
+}
+
+// a job as the page shows it, the payload is only there to be read
+#[derive(Serialize)]
+struct JobEntry {
+    id: String,
+    kind: String,
+    payload: String,
+    status: String,
+    attempts: i32,
+    max_attempts: i32,
+    run_at: DateTimeWithTimeZone,
+    last_error: Option<String>,
+    date_created: DateTimeWithTimeZone,
+    finished_at: Option<DateTimeWithTimeZone>,
+}
+
+#[derive(Serialize)]
+struct StatusCount {
+    status: &'static str,
+    count: usize,
+}
+
+fn render(tmpl: &Tera, ctx: &Context, code: StatusCode) -> (StatusCode, Html<String>) {
+    (
+        code,
+        Html(tmpl.render("admin_jobs.html.tera", ctx).unwrap()),
+    )
+}
+
+fn render_mail(tmpl: &Tera, ctx: &Context, code: StatusCode) -> (StatusCode, Html<String>) {
+    (
+        code,
+        Html(tmpl.render("admin_mail.html.tera", ctx).unwrap()),
+    )
+}
+
+// an email as the page shows it, without the body
+#[derive(Serialize)]
+struct OutboxEntry {
+    id: String,
+    to_address: String,
+    subject: String,
+    template: String,
+    attempts: i32,
+    last_error: Option<String>,
+    date_created: DateTimeWithTimeZone,
+    sent_at: Option<DateTimeWithTimeZone>,
+}
+
+#[derive(Deserialize)]
+pub struct TestMailForm {
+    to: String,
+}
+
+// only the users named in the configuration get past this
+fn admin(
+    cookies: &Cookies,
+    sessions: Arc<Sessions>,
+    cfg: &Configuration,
+    ctx: &mut Context,
+) -> Result<User, StatusCode> {
+    let user = cookies
+        .get("sess")
+        .and_then(|c| identity::user_from_session(sessions, c.value().to_string()))
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    ctx.insert("user", &user);
+
+    if cfg.is_admin(&user) {
+        Ok(user)
+    } else {
+        ctx.insert("error", "Only administrators can see this page.");
+        Err(StatusCode::FORBIDDEN)
+    }
+}
+
+pub async fn jobs(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(cfg): Extension<Arc<Configuration>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Query(req): Query<JobsParameters>,
+) -> (StatusCode, Html<String>) {
+    let mut ctx = Context::new();
+    if let Err(code) = admin(&cookies, sessions, &cfg, &mut ctx) {
+        return render(&tmpl, &ctx, code);
+    }
+
+    let mut counts = Vec::new();
+    for status in Status::ALL {
+        match jobs::Entity::find()
+            .filter(jobs::Column::Status.eq(status.as_str()))
+            .count(dbconn.as_ref())
+            .await
+        {
+            Ok(count) => counts.push(StatusCount {
+                status: status.as_str(),
+                count,
+            }),
+            Err(e) => warn!("jobs couldn't be counted: {e}"),
+        }
+    }
+    ctx.insert("counts", &counts);
+
+    let mut query = jobs::Entity::find();
+    if let Some(status) = Status::ALL
+        .iter()
+        .find(|s| Some(s.as_str()) == req.status.as_deref())
+    {
+        query = query.filter(jobs::Column::Status.eq(status.as_str()));
+        ctx.insert("current_status", status.as_str());
+        ctx.insert("filter", &format!("&status={status}"));
+    }
+
+    let page = req.page.unwrap_or(1);
+    let jobs_per_page = req.jobs_per_page.unwrap_or(50);
+    let paginator = query
+        .clone()
+        .order_by_desc(jobs::Column::DateCreated)
+        .paginate(dbconn.as_ref(), jobs_per_page);
+    match query.count(dbconn.as_ref()).await {
+        Ok(total_jobs) => ctx.insert("pages", &total_jobs.div_ceil(jobs_per_page)),
+        Err(e) => warn!("amount of pages couldn't be counted: {e}"),
+    }
+
+    match paginator.fetch_page(page - 1).await {
+        Ok(found) => {
+            let found: Vec<JobEntry> = found
+                .into_iter()
+                .map(|job| JobEntry {
+                    payload: job.payload.to_string(),
+                    id: job.id,
+                    kind: job.kind,
+                    status: job.status,
+                    attempts: job.attempts,
+                    max_attempts: job.max_attempts,
+                    run_at: job.run_at,
+                    last_error: job.last_error,
+                    date_created: job.date_created,
+                    finished_at: job.finished_at,
+                })
+                .collect();
+            ctx.insert("jobs", &found);
+        }
+        Err(e) => {
+            error!("fetching a page of jobs from the database failed: {e}");
+            ctx.insert("error", "Database did not return any jobs.");
+            return render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    ctx.insert("current_page", &page);
+
+    render(&tmpl, &ctx, StatusCode::OK)
+}
+
+pub async fn retry_job(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(cfg): Extension<Arc<Configuration>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Path(id): Path<String>,
+) -> Result<Redirect, (StatusCode, Html<String>)> {
+    let mut ctx = Context::new();
+    if let Err(code) = admin(&cookies, sessions, &cfg, &mut ctx) {
+        return Err(render(&tmpl, &ctx, code));
+    }
+
+    match queue.retry(&id).await {
+        Ok(true) => Ok(Redirect::to("/admin/jobs?status=dead".parse().unwrap())),
+        Ok(false) => {
+            ctx.insert("error", "Only dead jobs can be retried.");
+            Err(render(&tmpl, &ctx, StatusCode::NOT_FOUND))
+        }
+        Err(e) => {
+            error!("tried putting a job back into the queue: {e}");
+            ctx.insert("error", "Database was unreachable.");
+            Err(render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+pub async fn mail(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(cfg): Extension<Arc<Configuration>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(outbox): Extension<Arc<Outbox>>,
+) -> (StatusCode, Html<String>) {
+    let mut ctx = Context::new();
+    let user = match admin(&cookies, sessions, &cfg, &mut ctx) {
+        Ok(user) => user,
+        Err(code) => return render_mail(&tmpl, &ctx, code),
+    };
+    ctx.insert("smtp", &outbox.is_configured());
+    ctx.insert("default_to", &user.email);
+
+    match outbox::Entity::find()
+        .order_by_desc(outbox::Column::DateCreated)
+        .limit(OUTBOX_ENTRIES)
+        .all(dbconn.as_ref())
+        .await
+    {
+        Ok(found) => {
+            let found: Vec<OutboxEntry> = found
+                .into_iter()
+                .map(|mail| OutboxEntry {
+                    id: mail.id,
+                    to_address: mail.to_address,
+                    subject: mail.subject,
+                    template: mail.template,
+                    attempts: mail.attempts,
+                    last_error: mail.last_error,
+                    date_created: mail.date_created,
+                    sent_at: mail.sent_at,
+                })
+                .collect();
+            ctx.insert("mails", &found);
+        }
+        Err(e) => {
+            error!("fetching the outbox from the database failed: {e}");
+            ctx.insert("error", "Database did not return any emails.");
+            return render_mail(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    render_mail(&tmpl, &ctx, StatusCode::OK)
+}
+
+// sends a test email through the outbox, to see whether the mail server
+// configuration works
+pub async fn test_mail(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(cfg): Extension<Arc<Configuration>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(outbox): Extension<Arc<Outbox>>,
+    Form(form): Form<TestMailForm>,
+) -> Result<Redirect, (StatusCode, Html<String>)> {
+    let mut ctx = Context::new();
+    let user = match admin(&cookies, sessions, &cfg, &mut ctx) {
+        Ok(user) => user,
+        Err(code) => return Err(render_mail(&tmpl, &ctx, code)),
+    };
+
+    let to = form.to.trim();
+    if !to.contains('@') {
+        ctx.insert("error", "That is not an email address.");
+        return Err(render_mail(&tmpl, &ctx, StatusCode::UNPROCESSABLE_ENTITY));
+    }
+    if !outbox.is_configured() {
+        ctx.insert(
+            "error",
+            "Email is not set up, add a [smtp] section to the configuration.",
+        );
+        return Err(render_mail(&tmpl, &ctx, StatusCode::CONFLICT));
+    }
+
+    let mut mail_ctx = Context::new();
+    mail_ctx.insert("sender", user.login.as_ref().unwrap_or(&user.id));
+    match outbox
+        .send(&queue, to, "Test email", "test", &mail_ctx)
+        .await
+    {
+        Ok(_) => Ok(Redirect::to("/admin/mail".parse().unwrap())),
+        Err(e) => {
+            error!("tried putting a test email in the outbox: {e:#}");
+            ctx.insert("error", "The test email couldn't be put in the outbox.");
+            Err(render_mail(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
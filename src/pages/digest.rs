@@ -0,0 +1,195 @@
+use std::sync::Arc;
+
+use {
+    axum::{
+        extract::{Extension, Form},
+        http::StatusCode,
+        response::{Html, Redirect},
+    },
+    chrono::Utc,
+    chrono_tz::TZ_VARIANTS,
+    sea_orm::{
+        entity::{prelude::*, Set},
+        DatabaseConnection,
+    },
+    serde::Deserialize,
+    tera::{Context, Tera},
+    tower_cookies::Cookies,
+    tracing::error,
+};
+
+use crate::{
+    app::{Sessions, User},
+    config::Configuration,
+    database::entity::digest_settings,
+    digests::{self, Frequency, Pick},
+    identity,
+    jobs::Queue,
+};
+
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+#[derive(Deserialize)]
+pub struct DigestForm {
+    frequency: String,
+    weekday: i16,
+    hour: i16,
+    timezone: String,
+    link_count: i16,
+    pick: String,
+}
+
+fn render(tmpl: &Tera, ctx: &Context, code: StatusCode) -> (StatusCode, Html<String>) {
+    (code, Html(tmpl.render("digest.html.tera", ctx).unwrap()))
+}
+
+fn signed_in(
+    tmpl: &Tera,
+    cookies: &Cookies,
+    sessions: Arc<Sessions>,
+    ctx: &mut Context,
+) -> Result<User, (StatusCode, Html<String>)> {
+    let user = cookies
+        .get("sess")
+        .and_then(|c| identity::user_from_session(sessions, c.value().to_string()))
+        .ok_or_else(|| render(tmpl, ctx, StatusCode::UNAUTHORIZED))?;
+    ctx.insert("user", &user);
+    Ok(user)
+}
+
+// what a user who never opted in starts out with
+fn defaults(user_id: &str) -> digest_settings::Model {
+    digest_settings::Model {
+        user_id: user_id.to_string(),
+        frequency: Frequency::Off.as_str().to_string(),
+        weekday: 0,
+        hour: 8,
+        timezone: "UTC".to_string(),
+        link_count: 5,
+        pick: Pick::Oldest.as_str().to_string(),
+        last_sent_at: None,
+        next_due_at: None,
+        date_created: Utc::now().into(),
+        modified_at: None,
+    }
+}
+
+pub async fn page(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(cfg): Extension<Arc<Configuration>>,
+) -> Result<Html<String>, (StatusCode, Html<String>)> {
+    let mut ctx = Context::new();
+    let user = signed_in(&tmpl, &cookies, sessions, &mut ctx)?;
+
+    let settings = match digest_settings::Entity::find_by_id(user.id.clone())
+        .one(dbconn.as_ref())
+        .await
+    {
+        Ok(settings) => settings.unwrap_or_else(|| defaults(&user.id)),
+        Err(e) => {
+            error!("tried looking up digest settings: {e}");
+            ctx.insert("error", "Database was unreachable.");
+            return Err(render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    };
+
+    ctx.insert("settings", &settings);
+    ctx.insert(
+        "frequencies",
+        &Frequency::ALL
+            .iter()
+            .map(|f| f.as_str())
+            .collect::<Vec<_>>(),
+    );
+    ctx.insert(
+        "picks",
+        &Pick::ALL.iter().map(|p| p.as_str()).collect::<Vec<_>>(),
+    );
+    ctx.insert("weekdays", &WEEKDAYS);
+    ctx.insert(
+        "timezones",
+        &TZ_VARIANTS.iter().map(|tz| tz.name()).collect::<Vec<_>>(),
+    );
+    ctx.insert("smtp", &cfg.smtp.is_some());
+
+    Ok(Html(tmpl.render("digest.html.tera", &ctx).unwrap()))
+}
+
+pub async fn save(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Form(form): Form<DigestForm>,
+) -> Result<Redirect, (StatusCode, Html<String>)> {
+    let mut ctx = Context::new();
+    let user = signed_in(&tmpl, &cookies, sessions, &mut ctx)?;
+
+    let valid = form.frequency.parse::<Frequency>().is_ok()
+        && form.pick.parse::<Pick>().is_ok()
+        && digests::timezone(&form.timezone).is_ok()
+        && (0..7).contains(&form.weekday)
+        && (0..24).contains(&form.hour)
+        && (1..=50).contains(&form.link_count);
+    if !valid {
+        ctx.insert("error", "Those digest settings are not valid.");
+        return Err(render(&tmpl, &ctx, StatusCode::UNPROCESSABLE_ENTITY));
+    }
+
+    let existing = match digest_settings::Entity::find_by_id(user.id.clone())
+        .one(dbconn.as_ref())
+        .await
+    {
+        Ok(existing) => existing,
+        Err(e) => {
+            error!("tried looking up digest settings: {e}");
+            ctx.insert("error", "Database was unreachable.");
+            return Err(render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    };
+    let mut der: digest_settings::ActiveModel = match &existing {
+        Some(existing) => existing.clone().into(),
+        None => defaults(&user.id).into(),
+    };
+    der.frequency = Set(form.frequency);
+    der.weekday = Set(form.weekday);
+    der.hour = Set(form.hour);
+    der.timezone = Set(form.timezone);
+    der.link_count = Set(form.link_count);
+    der.pick = Set(form.pick);
+    let saved = match existing {
+        Some(_) => {
+            der.modified_at = Set(Some(Utc::now().into()));
+            der.update(dbconn.as_ref()).await
+        }
+        None => der.insert(dbconn.as_ref()).await,
+    };
+    let settings = match saved {
+        Ok(settings) => settings,
+        Err(e) => {
+            error!("tried saving digest settings: {e}");
+            ctx.insert("error", "Database was unreachable.");
+            return Err(render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    };
+
+    if let Err(e) = digests::schedule(dbconn.as_ref(), &queue, &settings).await {
+        error!("tried scheduling a digest: {e}");
+        ctx.insert("error", "Database was unreachable.");
+        return Err(render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    Ok(Redirect::to("/digest".parse().unwrap()))
+}
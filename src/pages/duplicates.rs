@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use {
+    axum::{
+        extract::{Extension, Form},
+        http::StatusCode,
+        response::{Html, Redirect},
+    },
+    sea_orm::DatabaseConnection,
+    tera::{Context, Tera},
+    tower_cookies::Cookies,
+    tracing::error,
+};
+
+use crate::{
+    api::{duplicates, links::LinkId},
+    app::Sessions,
+    identity,
+    jobs::Queue,
+    normalize::Normalizer,
+};
+
+fn render(tmpl: &Tera, ctx: &Context, code: StatusCode) -> (StatusCode, Html<String>) {
+    (
+        code,
+        Html(tmpl.render("duplicates.html.tera", ctx).unwrap()),
+    )
+}
+
+// the links that look like they're the same page, each group with a form to
+// merge them
+pub async fn page(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(normalizer): Extension<Arc<Normalizer>>,
+) -> (StatusCode, Html<String>) {
+    let mut ctx = Context::new();
+    let user = match cookies
+        .get("sess")
+        .and_then(|c| identity::user_from_session(sessions, c.value().to_string()))
+    {
+        Some(user) => user,
+        None => return render(&tmpl, &ctx, StatusCode::UNAUTHORIZED),
+    };
+    ctx.insert("user", &user);
+
+    match duplicates::find(dbconn.as_ref(), &normalizer, &user.id).await {
+        Ok(clusters) => ctx.insert("clusters", &clusters),
+        Err(e) => {
+            error!("looking for duplicate links failed: {e}");
+            ctx.insert("error", "Database was unreachable.");
+            return render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    render(&tmpl, &ctx, StatusCode::OK)
+}
+
+// the form sends the link to keep as "into" and every checked link as "links"
+pub async fn merge(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(normalizer): Extension<Arc<Normalizer>>,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<Redirect, (StatusCode, Html<String>)> {
+    let mut ctx = Context::new();
+    let user = match cookies
+        .get("sess")
+        .and_then(|c| identity::user_from_session(sessions, c.value().to_string()))
+    {
+        Some(user) => user,
+        None => return Err(render(&tmpl, &ctx, StatusCode::UNAUTHORIZED)),
+    };
+    ctx.insert("user", &user);
+
+    let into: Option<LinkId> = fields
+        .iter()
+        .find(|(key, _)| key == "into")
+        .and_then(|(_, value)| value.parse().ok());
+    let links: Vec<LinkId> = fields
+        .iter()
+        .filter(|(key, _)| key == "links")
+        .filter_map(|(_, value)| value.parse().ok())
+        .collect();
+    let into = match into {
+        Some(into) if links.iter().any(|id| *id != into) => into,
+        _ => {
+            ctx.insert(
+                "error",
+                "Choose the link to keep and at least one other link to merge into it.",
+            );
+            return Err(render(&tmpl, &ctx, StatusCode::UNPROCESSABLE_ENTITY));
+        }
+    };
+
+    match duplicates::merge(
+        dbconn.as_ref(),
+        &queue,
+        &normalizer,
+        &user.id,
+        &into,
+        &links,
+    )
+    .await
+    {
+        Ok(Some(_)) => Ok(Redirect::to("/duplicates".parse().unwrap())),
+        Ok(None) => {
+            ctx.insert("error", "One of the links does not exist.");
+            Err(render(&tmpl, &ctx, StatusCode::NOT_FOUND))
+        }
+        Err(e) => {
+            error!("merging links failed: {e}");
+            ctx.insert("error", "Database was unreachable.");
+            Err(render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use {
+    axum::{
+        extract::{Extension, Query},
+        http::StatusCode,
+        response::{IntoResponse, Json},
+    },
+    sea_orm::DatabaseConnection,
+    tower_cookies::Cookies,
+};
+
+use crate::{
+    api::{
+        error::{resp_err, ApiError},
+        exports::{self, LinksFormat, LinksRequest},
+    },
+    app::Sessions,
+    identity,
+};
+
+// the same exports the api offers, for the buttons on the index page
+async fn download(
+    cookies: Cookies,
+    sessions: Arc<Sessions>,
+    dbconn: Arc<DatabaseConnection>,
+    req: LinksRequest,
+    format: LinksFormat,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    let user = cookies
+        .get("sess")
+        .and_then(|c| identity::user_from_session(sessions, c.value().to_string()))
+        .ok_or_else(|| resp_err(StatusCode::UNAUTHORIZED, "not signed in"))?;
+
+    exports::links_document(dbconn, user.id, req, format).await
+}
+
+pub async fn links_csv(
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Query(req): Query<LinksRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    download(cookies, sessions, dbconn, req, LinksFormat::Csv).await
+}
+
+pub async fn links_markdown(
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Query(req): Query<LinksRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    download(cookies, sessions, dbconn, req, LinksFormat::Markdown).await
+}
+
+pub async fn citations_bibtex(
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Query(req): Query<LinksRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    download(cookies, sessions, dbconn, req, LinksFormat::Bibtex).await
+}
+
+pub async fn citations_csl_json(
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Query(req): Query<LinksRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    download(cookies, sessions, dbconn, req, LinksFormat::CslJson).await
+}
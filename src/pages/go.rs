@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use {
+    axum::{
+        extract::{Extension, Path},
+        http::{header, HeaderValue, StatusCode},
+        response::{Html, IntoResponse, Redirect, Response},
+    },
+    chrono::Utc,
+    sea_orm::{
+        entity::{prelude::*, Set},
+        sea_query::Expr,
+        DatabaseConnection,
+    },
+    tera::{Context, Tera},
+    tower_cookies::Cookies,
+    tracing::{error, warn},
+    ulid::Ulid,
+};
+
+use crate::{
+    api::links::{self as api_links, LinkId, Reading, ReadingStatus},
+    app::Sessions,
+    config::{Configuration, DEFAULT_REFERRER_POLICY},
+    database::entity::{link_opens, links},
+    identity,
+};
+
+// the reader shows errors about a link just as well
+fn render(tmpl: &Tera, ctx: &Context, code: StatusCode) -> (StatusCode, Html<String>) {
+    (code, Html(tmpl.render("reader.html.tera", ctx).unwrap()))
+}
+
+// counts the open and remembers who it was, opening one of their own links
+// counts as having read it for the owner
+async fn record(
+    dbconn: &DatabaseConnection,
+    link: &links::Model,
+    user_id: &str,
+) -> Result<(), DbErr> {
+    let now: DateTimeWithTimeZone = Utc::now().into();
+    link_opens::Entity::insert(link_opens::ActiveModel {
+        id: Set(Ulid::new().to_string()),
+        link_id: Set(link.id.clone()),
+        user_id: Set(user_id.to_string()),
+        opened_at: Set(now),
+    })
+    .exec(dbconn)
+    .await?;
+
+    let mut update = links::Entity::update_many()
+        .col_expr(
+            links::Column::OpenCount,
+            Expr::col(links::Column::OpenCount).add(1),
+        )
+        .col_expr(links::Column::LastOpenedAt, Expr::value(now));
+    if link.created_by == user_id && link.read_at.is_none() {
+        let reading = Reading::new(link.reading_started_at, link.read_at, link.reading_progress)
+            .mark(ReadingStatus::Read, None, now);
+        update = update
+            .col_expr(
+                links::Column::ReadingStartedAt,
+                Expr::value(reading.reading_started_at),
+            )
+            .col_expr(links::Column::ReadAt, Expr::value(reading.read_at))
+            .col_expr(
+                links::Column::ReadingProgress,
+                Expr::value(reading.reading_progress),
+            );
+    }
+    update
+        .filter(links::Column::Id.eq(link.id.clone()))
+        .exec(dbconn)
+        .await?;
+
+    Ok(())
+}
+
+// sends the user on to the page behind a link, the links on the index go
+// through here so it's known what actually gets read
+pub async fn open(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(cfg): Extension<Arc<Configuration>>,
+    Path(link_id): Path<LinkId>,
+) -> Result<Response, (StatusCode, Html<String>)> {
+    let mut ctx = Context::new();
+    let user = match cookies
+        .get("sess")
+        .and_then(|c| identity::user_from_session(sessions, c.value().to_string()))
+    {
+        Some(user) => user,
+        None => return Err(render(&tmpl, &ctx, StatusCode::UNAUTHORIZED)),
+    };
+    ctx.insert("user", &user);
+
+    let link = match api_links::visible_link(dbconn.clone(), &user.id, &link_id).await {
+        Ok(Some(link)) => link,
+        Ok(None) => {
+            ctx.insert("error", "That link does not exist.");
+            return Err(render(&tmpl, &ctx, StatusCode::NOT_FOUND));
+        }
+        Err(e) => {
+            error!("looking up a link to open failed: {e}");
+            ctx.insert("error", "Database was unreachable.");
+            return Err(render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    };
+    let url = match link.url.parse() {
+        Ok(url) => url,
+        Err(e) => {
+            error!("address of link {} couldn't be parsed: {e}", link.id);
+            ctx.insert("error", "The address of that link is not valid.");
+            return Err(render(&tmpl, &ctx, StatusCode::UNPROCESSABLE_ENTITY));
+        }
+    };
+
+    // not being able to count the open shouldn't keep anyone from the page
+    if let Err(e) = record(dbconn.as_ref(), &link, &user.id).await {
+        warn!("open of link {} couldn't be recorded: {e}", link.id);
+    }
+
+    // the policy was checked when the application started
+    let policy = cfg.referrer_policy().unwrap_or(DEFAULT_REFERRER_POLICY);
+    let mut response = Redirect::to(url).into_response();
+    if let Ok(value) = HeaderValue::from_str(policy) {
+        response
+            .headers_mut()
+            .insert(header::REFERRER_POLICY, value);
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use {
+    axum::{
+        extract::{Extension, Multipart},
+        response::Html,
+    },
+    sea_orm::DatabaseConnection,
+    tera::{Context, Tera},
+    tower_cookies::Cookies,
+    tracing::error,
+};
+
+use crate::{
+    app::{Sessions, User},
+    identity,
+    interchange::import::{ImportFormat, ImportSession, Malformed, PendingImports},
+    jobs::Queue,
+    normalize::Normalizer,
+    snapshots::Snapshotter,
+    webhooks,
+};
+
+// how many of the previewed links are listed on the page
+const PREVIEW_LENGTH: usize = 200;
+
+fn render(tmpl: &Tera, ctx: &Context) -> Html<String> {
+    Html(tmpl.render("import.html.tera", ctx).unwrap())
+}
+
+fn signed_in(cookies: &Cookies, sessions: Arc<Sessions>, ctx: &mut Context) -> Option<User> {
+    let user = identity::user_from_session(sessions, cookies.get("sess")?.value().to_string())?;
+    ctx.insert("user", &user);
+    Some(user)
+}
+
+// runs the uploaded export through the session, the form sends the format
+// first, followed by the file itself
+async fn read_upload(mut req: Multipart, session: &mut ImportSession, ctx: &mut Context) {
+    let mut format = ImportFormat::Netscape;
+    loop {
+        let field = match req.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => return ctx.insert("error", "No file was uploaded."),
+            Err(e) => {
+                error!("reading multipart upload failed: {e}");
+                return ctx.insert("error", "The upload was malformed.");
+            }
+        };
+
+        match field.name() {
+            Some("format") => {
+                format = match field.text().await.map(|f| f.parse()) {
+                    Ok(Ok(ok)) => ok,
+                    _ => return ctx.insert("error", "That import format isn't supported."),
+                }
+            }
+            Some("file") => {
+                let result = session.import_stream(format.parser(), field).await;
+                ctx.insert("report", &session.report);
+                match result {
+                    Ok(_) => (),
+                    Err(e) if e.is::<Malformed>() && session.is_dry_run() => {
+                        ctx.insert("error", &format!("{e}."))
+                    }
+                    Err(e) if e.is::<Malformed>() => ctx.insert(
+                        "error",
+                        &format!("{e}, links before the problem were saved."),
+                    ),
+                    Err(e) => {
+                        error!("import stopped partway through: {e}");
+                        ctx.insert(
+                            "error",
+                            "The import stopped partway through, links before the failure were saved.",
+                        );
+                    }
+                }
+                return;
+            }
+            _ => (),
+        }
+    }
+}
+
+pub async fn page(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+) -> Html<String> {
+    let mut ctx = Context::new();
+    signed_in(&cookies, sessions, &mut ctx);
+
+    render(&tmpl, &ctx)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn upload(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(normalizer): Extension<Arc<Normalizer>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(snapshotter): Extension<Option<Arc<Snapshotter>>>,
+    req: Multipart,
+) -> Html<String> {
+    let mut ctx = Context::new();
+    let user = match signed_in(&cookies, sessions, &mut ctx) {
+        Some(user) => user,
+        None => return render(&tmpl, &ctx),
+    };
+
+    let mut session = ImportSession::new(dbconn.clone(), normalizer, user.id);
+    read_upload(req, &mut session, &mut ctx).await;
+    queue
+        .push_saved(&session.saved, snapshotter.is_some())
+        .await;
+    webhooks::emit_saved(dbconn.as_ref(), &queue, &session.saved).await;
+
+    render(&tmpl, &ctx)
+}
+
+pub async fn preview(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(normalizer): Extension<Arc<Normalizer>>,
+    Extension(pending): Extension<Arc<PendingImports>>,
+    req: Multipart,
+) -> Html<String> {
+    let mut ctx = Context::new();
+    let user = match signed_in(&cookies, sessions, &mut ctx) {
+        Some(user) => user,
+        None => return render(&tmpl, &ctx),
+    };
+
+    let mut session = ImportSession::dry_run(dbconn, normalizer, user.id.clone());
+    read_upload(req, &mut session, &mut ctx).await;
+
+    // a new preview replaces whatever was waiting to be confirmed before
+    pending.remove(&user.id);
+    if !session.previewed.is_empty() {
+        let shown = session.previewed.len().min(PREVIEW_LENGTH);
+        ctx.insert("preview", &session.previewed[..shown]);
+        ctx.insert("preview_total", &session.previewed.len());
+        pending.insert(user.id, session.previewed);
+    }
+
+    render(&tmpl, &ctx)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn confirm(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(normalizer): Extension<Arc<Normalizer>>,
+    Extension(pending): Extension<Arc<PendingImports>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(snapshotter): Extension<Option<Arc<Snapshotter>>>,
+) -> Html<String> {
+    let mut ctx = Context::new();
+    let user = match signed_in(&cookies, sessions, &mut ctx) {
+        Some(user) => user,
+        None => return render(&tmpl, &ctx),
+    };
+
+    let previewed = match pending.remove(&user.id) {
+        Some((_, previewed)) => previewed,
+        None => {
+            ctx.insert(
+                "error",
+                "There is no previewed import waiting to be confirmed.",
+            );
+            return render(&tmpl, &ctx);
+        }
+    };
+
+    // links are checked again, something could have been added in between
+    let mut session = ImportSession::new(dbconn.clone(), normalizer, user.id);
+    for imported in previewed {
+        if let Err(e) = session.add(imported).await {
+            error!("import stopped partway through: {e}");
+            ctx.insert(
+                "error",
+                "The import stopped partway through, links before the failure were saved.",
+            );
+            break;
+        }
+    }
+    queue
+        .push_saved(&session.saved, snapshotter.is_some())
+        .await;
+    webhooks::emit_saved(dbconn.as_ref(), &queue, &session.saved).await;
+    ctx.insert("report", &session.report);
+
+    render(&tmpl, &ctx)
+}
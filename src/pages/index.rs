@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use {
     anyhow::{Error, Result},
@@ -6,24 +6,69 @@ use {
         extract::{Extension, Query},
         response::Html,
     },
-    sea_orm::{entity::prelude::*, DatabaseConnection, QueryOrder},
-    serde::Deserialize,
+    sea_orm::{entity::prelude::*, DatabaseConnection, FromQueryResult, QueryOrder, QuerySelect},
+    serde::{Deserialize, Serialize},
     tera::{Context, Tera},
     tower_cookies::Cookies,
     tracing::{error, info, warn},
 };
 
 use crate::{
-    api::{links::Link, UserId},
+    api::{
+        groups::GroupId,
+        links::{self as api_links, Link, ReadingStatus, Sort},
+        members::Role,
+        UserId,
+    },
     app::{Sessions, User},
-    database::entity::links,
-    identity,
+    database::entity::{groups, link_articles, link_health, links, snapshots},
+    identity, reminders,
+    snapshots::Snapshotter,
 };
 
 #[derive(Deserialize)]
 pub struct IndexParameters {
     page: Option<usize>,
     links_per_page: Option<usize>,
+    group: Option<GroupId>,
+    tag: Option<String>,
+    broken: Option<bool>,
+    q: Option<String>,
+    status: Option<ReadingStatus>,
+    snoozed: Option<bool>,
+    sort: Option<Sort>,
+}
+
+// how long the article behind a link takes to read, the text itself isn't
+// needed for the list
+#[derive(FromQueryResult)]
+struct ReadingTime {
+    link_id: String,
+    reading_minutes: i32,
+}
+
+// a banner about a snoozed link that's due
+#[derive(Serialize)]
+struct ReminderEntry {
+    id: String,
+    link_id: String,
+    title: String,
+    due_at: DateTimeWithTimeZone,
+}
+
+// how many of the user's own links are in one reading status
+#[derive(Serialize)]
+struct StatusCount {
+    status: &'static str,
+    count: usize,
+}
+
+// what the sidebar needs to know about each of the user's groups
+#[derive(Serialize)]
+struct GroupEntry {
+    id: String,
+    name: String,
+    role: Role,
 }
 
 pub async fn index(
@@ -31,6 +76,7 @@ pub async fn index(
     cookies: Cookies,
     Extension(sessions): Extension<Arc<Sessions>>,
     Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(snapshotter): Extension<Option<Arc<Snapshotter>>>,
     Query(req): Query<IndexParameters>,
 ) -> Html<String> {
     let mut ctx = Context::new();
@@ -47,14 +93,115 @@ pub async fn index(
     };
     let user_id: UserId = user.id;
 
+    // groups the user owns or has been invited to
+    let roles = match identity::group_roles(dbconn.clone(), &user_id).await {
+        Ok(ok) => ok,
+        Err(e) => {
+            error!("fetching group memberships from the database failed: {e}");
+            ctx.insert("error", "Database did not return any groups.");
+            return Html(tmpl.render("index.html.tera", &ctx).unwrap());
+        }
+    };
+    match groups::Entity::find()
+        .order_by_asc(groups::Column::Name)
+        .filter(groups::Column::Id.is_in(roles.keys().cloned().collect::<Vec<_>>()))
+        .all(dbconn.as_ref())
+        .await
+    {
+        Ok(grps) => {
+            let entries = grps
+                .into_iter()
+                .map(|g| GroupEntry {
+                    role: roles[&g.id],
+                    id: g.id,
+                    name: g.name,
+                })
+                .collect::<Vec<_>>();
+            ctx.insert("groups", &entries);
+        }
+        Err(e) => warn!("groups for the sidebar couldn't be fetched: {e}"),
+    }
+
+    if let Some(group_id) = req.group {
+        if !roles.contains_key(&group_id.to_string()) {
+            ctx.insert("error", "That group does not exist.");
+            return Html(tmpl.render("index.html.tera", &ctx).unwrap());
+        }
+        ctx.insert("current_group", &group_id.to_string());
+    }
+    if let Some(tag) = &req.tag {
+        ctx.insert("current_tag", tag);
+    }
+    if req.broken == Some(true) {
+        ctx.insert("current_broken", &true);
+    }
+    if let Some(q) = req.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        ctx.insert("current_q", q);
+    }
+    if let Some(status) = req.status {
+        ctx.insert("current_status", status.as_str());
+    }
+    // snoozed links stay out of the backlog until they're due
+    let snoozed = req.snoozed.unwrap_or(false);
+    if snoozed {
+        ctx.insert("current_snoozed", &true);
+    }
+    let sort = req.sort.unwrap_or(Sort::Newest);
+    ctx.insert("current_sort", sort.as_str());
+    let filter = api_links::LinkFilter {
+        group: req.group,
+        tag: req.tag,
+        broken: req.broken,
+        q: req.q,
+        status: req.status,
+        snoozed: Some(snoozed),
+    };
+
+    // shown in the navbar
+    let mut counts = Vec::new();
+    for status in ReadingStatus::ALL {
+        let by_status = api_links::LinkFilter {
+            status: Some(status),
+            ..Default::default()
+        };
+        match by_status
+            .query_unchecked(&user_id)
+            .filter(links::Column::DeletedAt.is_null())
+            .count(dbconn.as_ref())
+            .await
+        {
+            Ok(count) => counts.push(StatusCount {
+                status: status.as_str(),
+                count,
+            }),
+            Err(e) => warn!("links couldn't be counted by reading status: {e}"),
+        }
+    }
+    ctx.insert("reading_counts", &counts);
+
+    match reminders::pending(dbconn.as_ref(), &user_id).await {
+        Ok(pending) => {
+            let entries = pending
+                .into_iter()
+                .map(|(notification, link)| ReminderEntry {
+                    id: notification.id,
+                    title: link.title.unwrap_or(link.url),
+                    link_id: link.id,
+                    due_at: notification.due_at,
+                })
+                .collect::<Vec<_>>();
+            ctx.insert("reminders", &entries);
+        }
+        Err(e) => warn!("reminders couldn't be fetched: {e}"),
+    }
+
     let page = req.page.unwrap_or(1);
     let links_per_page = req.links_per_page.unwrap_or(50);
-    let paginator = links::Entity::find()
-        .order_by_desc(links::Column::DateCreated)
-        .filter(links::Column::CreatedBy.eq(user_id.clone()))
+    let paginator = sort
+        .order(filter.query_unchecked(&user_id))
         .paginate(dbconn.as_ref(), links_per_page);
-    match links::Entity::find()
-        .filter(links::Column::CreatedBy.eq(user_id))
+    match filter
+        .query_unchecked(&user_id)
         .count(dbconn.as_ref())
         .await
     {
@@ -89,6 +236,70 @@ pub async fn index(
             }
         })
     }
+
+    // the rows that get a badge because their link stopped working
+    let mut health = HashMap::new();
+    match link_health::Entity::find()
+        .filter(link_health::Column::Broken.eq(true))
+        .filter(
+            link_health::Column::LinkId.is_in(
+                converted_links
+                    .iter()
+                    .map(|l| l.id.to_string())
+                    .collect::<Vec<_>>(),
+            ),
+        )
+        .all(dbconn.as_ref())
+        .await
+    {
+        Ok(found) => health.extend(found.into_iter().map(|h| (h.link_id.clone(), h))),
+        Err(e) => warn!("health of the links couldn't be fetched: {e}"),
+    }
+    ctx.insert("health", &health);
+
+    // the rows that can be read without the page around them
+    let mut articles = HashMap::new();
+    match link_articles::Entity::find()
+        .select_only()
+        .column(link_articles::Column::LinkId)
+        .column(link_articles::Column::ReadingMinutes)
+        .filter(
+            link_articles::Column::LinkId.is_in(
+                converted_links
+                    .iter()
+                    .map(|l| l.id.to_string())
+                    .collect::<Vec<_>>(),
+            ),
+        )
+        .into_model::<ReadingTime>()
+        .all(dbconn.as_ref())
+        .await
+    {
+        Ok(found) => articles.extend(found.into_iter().map(|a| (a.link_id, a.reading_minutes))),
+        Err(e) => warn!("articles of the links couldn't be fetched: {e}"),
+    }
+    ctx.insert("articles", &articles);
+
+    // the rows that link to a copy of their page
+    if snapshotter.is_some() {
+        let mut snapshotted = HashMap::new();
+        match snapshots::Entity::find()
+            .filter(
+                snapshots::Column::LinkId.is_in(
+                    converted_links
+                        .iter()
+                        .map(|l| l.id.to_string())
+                        .collect::<Vec<_>>(),
+                ),
+            )
+            .all(dbconn.as_ref())
+            .await
+        {
+            Ok(found) => snapshotted.extend(found.into_iter().map(|s| (s.link_id, true))),
+            Err(e) => warn!("snapshots of the links couldn't be fetched: {e}"),
+        }
+        ctx.insert("snapshots", &snapshotted);
+    }
     ctx.insert("links", &converted_links);
     ctx.insert("current_page", &page);
 
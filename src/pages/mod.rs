@@ -1,4 +1,15 @@
+pub mod admin;
 pub mod apikeys;
+pub mod digest;
+pub mod duplicates;
+pub mod export;
+pub mod go;
+pub mod import;
 mod index;
+pub mod reader;
+pub mod reminders;
+pub mod shared;
+pub mod snapshot;
+pub mod webhooks;
 
 pub use index::index;
@@ -0,0 +1,125 @@
+use std::sync::Arc;
+
+use {
+    axum::{
+        extract::{Extension, Path, Query},
+        http::StatusCode,
+        response::Html,
+    },
+    sea_orm::{entity::prelude::*, DatabaseConnection},
+    serde::{Deserialize, Serialize},
+    tera::{Context, Tera},
+    tower_cookies::Cookies,
+    tracing::error,
+};
+
+use crate::{
+    api::links::{self as api_links, LinkId},
+    app::Sessions,
+    database::entity::link_articles,
+    identity,
+};
+
+// the choices for each setting, the first one is what's used by default
+const SIZES: &[&str] = &["medium", "small", "large"];
+const FONTS: &[&str] = &["serif", "sans"];
+const WIDTHS: &[&str] = &["narrow", "wide"];
+const THEMES: &[&str] = &["light", "sepia", "dark"];
+
+#[derive(Deserialize)]
+pub struct ReaderParameters {
+    size: Option<String>,
+    font: Option<String>,
+    width: Option<String>,
+    theme: Option<String>,
+}
+
+// one of the selects above the article
+#[derive(Serialize)]
+struct Setting {
+    name: &'static str,
+    choices: &'static [&'static str],
+    chosen: &'static str,
+}
+
+// only known settings make it into the page, they end up as class names
+fn setting(name: &'static str, choices: &'static [&'static str], chosen: Option<&str>) -> Setting {
+    Setting {
+        name,
+        choices,
+        chosen: choices
+            .iter()
+            .find(|choice| Some(**choice) == chosen)
+            .copied()
+            .unwrap_or(choices[0]),
+    }
+}
+
+fn render(tmpl: &Tera, ctx: &Context, code: StatusCode) -> (StatusCode, Html<String>) {
+    (code, Html(tmpl.render("reader.html.tera", ctx).unwrap()))
+}
+
+// the article behind a link without the page around it, to anyone who can
+// see the link itself
+pub async fn page(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Path(link_id): Path<LinkId>,
+    Query(req): Query<ReaderParameters>,
+) -> (StatusCode, Html<String>) {
+    let mut ctx = Context::new();
+    let user = match cookies
+        .get("sess")
+        .and_then(|c| identity::user_from_session(sessions, c.value().to_string()))
+    {
+        Some(user) => user,
+        None => return render(&tmpl, &ctx, StatusCode::UNAUTHORIZED),
+    };
+    ctx.insert("user", &user);
+    ctx.insert(
+        "settings",
+        &[
+            setting("size", SIZES, req.size.as_deref()),
+            setting("font", FONTS, req.font.as_deref()),
+            setting("width", WIDTHS, req.width.as_deref()),
+            setting("theme", THEMES, req.theme.as_deref()),
+        ],
+    );
+
+    let link = match api_links::visible_link(dbconn.clone(), &user.id, &link_id).await {
+        Ok(Some(link)) => link,
+        Ok(None) => {
+            ctx.insert("error", "That link does not exist.");
+            return render(&tmpl, &ctx, StatusCode::NOT_FOUND);
+        }
+        Err(e) => {
+            error!("looking up a link to read failed: {e}");
+            ctx.insert("error", "Database was unreachable.");
+            return render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    ctx.insert("link", &link);
+
+    match link_articles::Entity::find_by_id(link_id.to_string())
+        .one(dbconn.as_ref())
+        .await
+    {
+        Ok(Some(article)) => ctx.insert("article", &article),
+        Ok(None) => {
+            ctx.insert(
+                "error",
+                "No article could be read out of this page, or it was not fetched yet.",
+            );
+            return render(&tmpl, &ctx, StatusCode::NOT_FOUND);
+        }
+        Err(e) => {
+            error!("looking up the article of a link failed: {e}");
+            ctx.insert("error", "Database was unreachable.");
+            return render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    render(&tmpl, &ctx, StatusCode::OK)
+}
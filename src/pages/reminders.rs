@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use {
+    axum::{
+        extract::{Extension, Form, Path},
+        http::StatusCode,
+        response::{Html, Redirect},
+    },
+    chrono::{Duration, NaiveDate, TimeZone, Utc},
+    sea_orm::{entity::prelude::*, DatabaseConnection},
+    serde::Deserialize,
+    tera::{Context, Tera},
+    tower_cookies::Cookies,
+    tracing::error,
+};
+
+use crate::{
+    api::links::LinkId,
+    app::{Sessions, User},
+    database::entity::links,
+    identity,
+    jobs::Queue,
+    reminders,
+};
+
+// the hour of the day a link snoozed until a date comes back, in utc
+const MORNING: u32 = 8;
+
+// one of the buttons was pressed, either a number of days, a date or clear
+#[derive(Deserialize)]
+pub struct SnoozeForm {
+    days: Option<i64>,
+    until: Option<String>,
+    clear: Option<String>,
+}
+
+fn render(tmpl: &Tera, ctx: &Context, code: StatusCode) -> (StatusCode, Html<String>) {
+    (code, Html(tmpl.render("index.html.tera", ctx).unwrap()))
+}
+
+fn signed_in(
+    tmpl: &Tera,
+    cookies: &Cookies,
+    sessions: Arc<Sessions>,
+    ctx: &mut Context,
+) -> Result<User, (StatusCode, Html<String>)> {
+    let user = cookies
+        .get("sess")
+        .and_then(|c| identity::user_from_session(sessions, c.value().to_string()))
+        .ok_or_else(|| render(tmpl, ctx, StatusCode::UNAUTHORIZED))?;
+    ctx.insert("user", &user);
+    Ok(user)
+}
+
+pub async fn snooze(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Path(link_id): Path<LinkId>,
+    Form(form): Form<SnoozeForm>,
+) -> Result<Redirect, (StatusCode, Html<String>)> {
+    let mut ctx = Context::new();
+    let user = signed_in(&tmpl, &cookies, sessions, &mut ctx)?;
+
+    let until = form
+        .until
+        .as_deref()
+        .filter(|until| !until.is_empty())
+        .map(|until| NaiveDate::parse_from_str(until, "%Y-%m-%d"));
+    let remind_at = match (form.clear, form.days, until) {
+        (Some(_), _, _) => None,
+        (None, Some(days), _) if (1..=365 * 5).contains(&days) => {
+            Some((Utc::now() + Duration::days(days)).into())
+        }
+        (None, None, Some(Ok(date))) => {
+            Some(Utc.from_utc_datetime(&date.and_hms(MORNING, 0, 0)).into())
+        }
+        _ => {
+            ctx.insert("error", "Choose when the link should come back.");
+            return Err(render(&tmpl, &ctx, StatusCode::UNPROCESSABLE_ENTITY));
+        }
+    };
+
+    // only the owner's backlog is snoozed
+    match links::Entity::find_by_id(link_id.to_string())
+        .filter(links::Column::CreatedBy.eq(user.id.clone()))
+        .filter(links::Column::DeletedAt.is_null())
+        .one(dbconn.as_ref())
+        .await
+    {
+        Ok(Some(_)) => (),
+        Ok(None) => {
+            ctx.insert("error", "That link does not exist.");
+            return Err(render(&tmpl, &ctx, StatusCode::NOT_FOUND));
+        }
+        Err(e) => {
+            error!("looking up a link to snooze failed: {e}");
+            ctx.insert("error", "Database was unreachable.");
+            return Err(render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    }
+    if let Err(e) = reminders::snooze(dbconn.as_ref(), &queue, &link_id, remind_at).await {
+        error!("tried setting the reminder of a link: {e}");
+        ctx.insert("error", "Database was unreachable.");
+        return Err(render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    Ok(Redirect::to("/".parse().unwrap()))
+}
+
+pub async fn dismiss(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Path(id): Path<String>,
+) -> Result<Redirect, (StatusCode, Html<String>)> {
+    let mut ctx = Context::new();
+    let user = signed_in(&tmpl, &cookies, sessions, &mut ctx)?;
+
+    match reminders::dismiss(dbconn.as_ref(), &user.id, &id).await {
+        Ok(_) => Ok(Redirect::to("/".parse().unwrap())),
+        Err(e) => {
+            error!("tried dismissing a notification: {e}");
+            ctx.insert("error", "Database was unreachable.");
+            Err(render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
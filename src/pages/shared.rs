@@ -0,0 +1,190 @@
+use std::{collections::HashMap, sync::Arc};
+
+use {
+    axum::{
+        extract::{Extension, Path, Query},
+        http::StatusCode,
+        response::Html,
+    },
+    sea_orm::{entity::prelude::*, Condition, DatabaseConnection, QueryOrder},
+    serde::{Deserialize, Serialize},
+    tera::{Context, Tera},
+    tracing::{error, warn},
+};
+
+use crate::{
+    api::{
+        groups::Group,
+        links::{self as api_links, Link},
+        shares::Share,
+        UserId,
+    },
+    database::entity::{group_shares, groups, links, notes},
+};
+
+// anyone holding the token can ask for pages, so they don't get to pick huge ones
+const MAX_LINKS_PER_PAGE: usize = 100;
+
+#[derive(Deserialize)]
+pub struct SharedParameters {
+    page: Option<usize>,
+    links_per_page: Option<usize>,
+}
+
+// a link along with the notes that are allowed to be shown publicly
+#[derive(Serialize)]
+struct SharedLink {
+    #[serde(flatten)]
+    link: Link,
+    notes: Vec<String>,
+}
+
+fn render(tmpl: &Tera, ctx: &Context, code: StatusCode) -> (StatusCode, Html<String>) {
+    (code, Html(tmpl.render("shared.html.tera", ctx).unwrap()))
+}
+
+pub async fn page(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Path(token): Path<String>,
+    Query(req): Query<SharedParameters>,
+) -> (StatusCode, Html<String>) {
+    let mut ctx = Context::new();
+
+    // revoked, expired and unknown tokens all look the same from the outside
+    let share = match group_shares::Entity::find()
+        .filter(group_shares::Column::Token.eq(token))
+        .one(dbconn.as_ref())
+        .await
+    {
+        Ok(Some(der)) => match Share::from_inactive_der(der) {
+            Ok(share) if share.is_active() => share,
+            Ok(_) => {
+                ctx.insert("error", "This share does not exist or has expired.");
+                return render(&tmpl, &ctx, StatusCode::NOT_FOUND);
+            }
+            Err(e) => {
+                error!("der share couldn't be casted into rust repr share: {e}");
+                ctx.insert("error", "This share could not be displayed.");
+                return render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        },
+        Ok(None) => {
+            ctx.insert("error", "This share does not exist or has expired.");
+            return render(&tmpl, &ctx, StatusCode::NOT_FOUND);
+        }
+        Err(e) => {
+            error!("fetching a group share from the database failed: {e}");
+            ctx.insert("error", "Database did not return the share.");
+            return render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let group = match groups::Entity::find_by_id(share.group_id.to_string())
+        .filter(groups::Column::DeletedAt.is_null())
+        .one(dbconn.as_ref())
+        .await
+    {
+        Ok(Some(der)) => match Group::from_inactive_der(der) {
+            Ok(mut group) => {
+                group.created_by.clear();
+                group
+            }
+            Err(e) => {
+                error!("der group couldn't be casted into rust repr group: {e}");
+                ctx.insert("error", "This share could not be displayed.");
+                return render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        },
+        Ok(None) => {
+            ctx.insert("error", "This share does not exist or has expired.");
+            return render(&tmpl, &ctx, StatusCode::NOT_FOUND);
+        }
+        Err(e) => {
+            error!("fetching a group from the database failed: {e}");
+            ctx.insert("error", "Database did not return the group.");
+            return render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    ctx.insert("group", &group);
+
+    // addresses are put into links on the page, ones that aren't web pages
+    // could run scripts for whoever clicks them
+    let mut query = api_links::visible_links(UserId::new(), Some(share.group_id))
+        .filter(links::Column::DeletedAt.is_null())
+        .filter(
+            Condition::any()
+                .add(links::Column::Url.starts_with("http://"))
+                .add(links::Column::Url.starts_with("https://")),
+        );
+    if !share.include_sensitive {
+        query = query.filter(links::Column::Sensitive.eq(false));
+    }
+
+    let page = req.page.unwrap_or(1).max(1);
+    let links_per_page = req
+        .links_per_page
+        .unwrap_or(50)
+        .clamp(1, MAX_LINKS_PER_PAGE);
+    let paginator = query
+        .clone()
+        .order_by_desc(links::Column::DateCreated)
+        .paginate(dbconn.as_ref(), links_per_page);
+    match query.count(dbconn.as_ref()).await {
+        Ok(total_links) => ctx.insert("pages", &total_links.div_ceil(links_per_page)),
+        Err(e) => warn!("amount of pages couldn't be counted: {e}"),
+    }
+
+    let links = match paginator.fetch_page(page - 1).await {
+        Ok(ok) => ok,
+        Err(e) => {
+            error!("fetching a page of links from the database failed: {e}");
+            ctx.insert("error", "Database did not return any links.");
+            return render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    // private notes never leave the group
+    let mut public_notes: HashMap<String, Vec<String>> = HashMap::new();
+    match notes::Entity::find()
+        .order_by_asc(notes::Column::DateCreated)
+        .filter(notes::Column::LinkId.is_in(links.iter().map(|l| l.id.clone())))
+        .filter(notes::Column::Private.eq(false))
+        .filter(notes::Column::DeletedAt.is_null())
+        .all(dbconn.as_ref())
+        .await
+    {
+        Ok(found) => {
+            for note in found {
+                if let Some(body) = note.body {
+                    public_notes.entry(note.link_id).or_default().push(body);
+                }
+            }
+        }
+        Err(e) => warn!("notes for a shared group couldn't be fetched: {e}"),
+    }
+
+    // convert der's to a rust struct
+    let mut converted_links = Vec::new();
+    for link in links {
+        let notes = public_notes.remove(&link.id).unwrap_or_default();
+        converted_links.push(match Link::from_inactive_der(link) {
+            Ok(mut ok) => {
+                ok.created_by.clear();
+                SharedLink { link: ok, notes }
+            }
+            Err(e) => {
+                error!("der link couldn't be casted into rust repr link: {e}");
+                ctx.insert(
+                    "error",
+                    "Links retrieved from database were corrupted and could not be displayed.",
+                );
+                return render(&tmpl, &ctx, StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        })
+    }
+    ctx.insert("links", &converted_links);
+    ctx.insert("current_page", &page);
+
+    render(&tmpl, &ctx, StatusCode::OK)
+}
@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use {
+    axum::{
+        extract::{Extension, Path, Query},
+        http::{header, HeaderMap, HeaderValue, StatusCode},
+        response::{IntoResponse, Json},
+    },
+    sea_orm::{entity::prelude::*, DatabaseConnection, QueryOrder},
+    serde::Deserialize,
+    tower_cookies::Cookies,
+    tracing::error,
+};
+
+use crate::{
+    api::{
+        archives::{self, Scope},
+        error::{resp_err, ApiError},
+        groups::GroupId,
+        links::{self as api_links, LinkId},
+    },
+    app::Sessions,
+    database::entity::snapshots,
+    identity,
+    snapshots::{Snapshotter, CONTENT_SECURITY_POLICY},
+};
+
+#[derive(Deserialize)]
+pub struct SnapshotParameters {
+    // a particular snapshot, the newest one is shown otherwise
+    at: Option<String>,
+}
+
+// shows the copy of the page as it was when the snapshot was taken, to anyone
+// who can see the link itself
+pub async fn view(
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(snapshotter): Extension<Option<Arc<Snapshotter>>>,
+    Path(link_id): Path<LinkId>,
+    Query(req): Query<SnapshotParameters>,
+) -> Result<(StatusCode, HeaderMap, Vec<u8>), (StatusCode, Json<ApiError<'static>>)> {
+    let user = cookies
+        .get("sess")
+        .and_then(|c| identity::user_from_session(sessions, c.value().to_string()))
+        .ok_or_else(|| resp_err(StatusCode::UNAUTHORIZED, "not signed in"))?;
+    let snapshotter = snapshotter.ok_or_else(|| {
+        resp_err(
+            StatusCode::NOT_IMPLEMENTED,
+            "snapshots are not enabled on this server",
+        )
+    })?;
+    let db_err = |e: DbErr| {
+        error!("looking up a snapshot in the database failed: {e}");
+        resp_err(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "database was unreachable",
+        )
+    };
+
+    let visible = api_links::visible_link(dbconn.clone(), &user.id, &link_id)
+        .await
+        .map_err(db_err)?;
+    if visible.is_none() {
+        return Err(resp_err(StatusCode::NOT_FOUND, "link does not exist"));
+    }
+
+    let mut query = snapshots::Entity::find()
+        .filter(snapshots::Column::LinkId.eq(link_id.to_string()))
+        .order_by_desc(snapshots::Column::DateCreated);
+    if let Some(at) = req.at {
+        query = query.filter(snapshots::Column::Id.eq(at));
+    }
+    let snapshot = query
+        .one(dbconn.as_ref())
+        .await
+        .map_err(db_err)?
+        .ok_or_else(|| resp_err(StatusCode::NOT_FOUND, "link has no snapshot"))?;
+
+    let body = match snapshotter.load(&snapshot).await {
+        Ok(Some(body)) => body,
+        Ok(None) => return Err(resp_err(StatusCode::NOT_FOUND, "snapshot was deleted")),
+        Err(e) => {
+            error!(
+                "snapshot {} couldn't be loaded from storage: {e}",
+                snapshot.id
+            );
+            return Err(resp_err(
+                StatusCode::BAD_GATEWAY,
+                "snapshot storage was unreachable",
+            ));
+        }
+    };
+
+    // the page was written by someone else, it gets to show itself and
+    // nothing more
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    headers.insert(
+        header::CONTENT_SECURITY_POLICY,
+        HeaderValue::from_static(CONTENT_SECURITY_POLICY),
+    );
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    headers.insert(
+        header::REFERRER_POLICY,
+        HeaderValue::from_static("no-referrer"),
+    );
+
+    Ok((StatusCode::OK, headers, body))
+}
+
+// the same archives the api offers, for downloading them from the browser
+async fn download(
+    cookies: Cookies,
+    sessions: Arc<Sessions>,
+    dbconn: Arc<DatabaseConnection>,
+    snapshotter: Option<Arc<Snapshotter>>,
+    scope: Scope,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    let user = cookies
+        .get("sess")
+        .and_then(|c| identity::user_from_session(sessions, c.value().to_string()))
+        .ok_or_else(|| resp_err(StatusCode::UNAUTHORIZED, "not signed in"))?;
+
+    archives::warc(dbconn, snapshotter, user.id, scope).await
+}
+
+pub async fn link_warc(
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(snapshotter): Extension<Option<Arc<Snapshotter>>>,
+    Path(link_id): Path<LinkId>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    download(cookies, sessions, dbconn, snapshotter, Scope::Link(link_id)).await
+}
+
+pub async fn group_warc(
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(snapshotter): Extension<Option<Arc<Snapshotter>>>,
+    Path(group_id): Path<GroupId>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ApiError<'static>>)> {
+    download(
+        cookies,
+        sessions,
+        dbconn,
+        snapshotter,
+        Scope::Group(group_id),
+    )
+    .await
+}
@@ -0,0 +1,332 @@
+use std::sync::Arc;
+
+use {
+    axum::{
+        extract::{Extension, Form, Path},
+        http::StatusCode,
+        response::{Html, Redirect},
+    },
+    chrono::Utc,
+    rand::{distributions::Alphanumeric, Rng},
+    reqwest::Url,
+    sea_orm::{
+        entity::{prelude::*, Set},
+        DatabaseConnection, QueryOrder, QuerySelect,
+    },
+    serde::Serialize,
+    tera::{Context, Tera},
+    tower_cookies::Cookies,
+    tracing::error,
+    ulid::Ulid,
+};
+
+use crate::{
+    app::{Sessions, User},
+    database::entity::{webhook_deliveries, webhooks},
+    identity,
+    jobs::{Job, Queue},
+    net::Guard,
+    webhooks::{self as hooks, DeliveryId, Event},
+};
+
+const DELIVERIES: u64 = 50;
+// generated when the user doesn't bring their own
+const SECRET_LENGTH: usize = 32;
+const MAX_SECRET_LENGTH: usize = 128;
+
+// a subscription as it's listed, along with the events it asked for
+#[derive(Serialize)]
+struct Subscription {
+    id: String,
+    url: String,
+    secret: String,
+    events: Vec<&'static str>,
+    date_created: DateTimeWithTimeZone,
+}
+
+// a line of the delivery log
+#[derive(Serialize)]
+struct Delivery {
+    id: String,
+    url: String,
+    event: String,
+    attempts: i32,
+    status_code: Option<i16>,
+    last_error: Option<String>,
+    date_created: DateTimeWithTimeZone,
+    delivered_at: Option<DateTimeWithTimeZone>,
+}
+
+fn render(tmpl: &Tera, ctx: &Context, code: StatusCode) -> (StatusCode, Html<String>) {
+    (code, Html(tmpl.render("webhooks.html.tera", ctx).unwrap()))
+}
+
+fn signed_in(
+    tmpl: &Tera,
+    cookies: &Cookies,
+    sessions: Arc<Sessions>,
+    ctx: &mut Context,
+) -> Result<User, (StatusCode, Html<String>)> {
+    let user = cookies
+        .get("sess")
+        .and_then(|c| identity::user_from_session(sessions, c.value().to_string()))
+        .ok_or_else(|| render(tmpl, ctx, StatusCode::UNAUTHORIZED))?;
+    ctx.insert("user", &user);
+    Ok(user)
+}
+
+fn unreachable(tmpl: &Tera, ctx: &mut Context) -> (StatusCode, Html<String>) {
+    ctx.insert("error", "Database was unreachable.");
+    render(tmpl, ctx, StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+// the subscriptions of the user, and the latest deliveries to them
+pub async fn page(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+) -> Result<Html<String>, (StatusCode, Html<String>)> {
+    let mut ctx = Context::new();
+    let user = signed_in(&tmpl, &cookies, sessions, &mut ctx)?;
+
+    let subscriptions = match webhooks::Entity::find()
+        .filter(webhooks::Column::CreatedBy.eq(user.id.clone()))
+        .filter(webhooks::Column::DeletedAt.is_null())
+        .order_by_desc(webhooks::Column::DateCreated)
+        .all(dbconn.as_ref())
+        .await
+    {
+        Ok(subscriptions) => subscriptions,
+        Err(e) => {
+            error!("tried looking up webhooks: {e}");
+            return Err(unreachable(&tmpl, &mut ctx));
+        }
+    };
+    let deliveries = match webhook_deliveries::Entity::find()
+        .filter(
+            webhook_deliveries::Column::WebhookId.is_in(
+                subscriptions
+                    .iter()
+                    .map(|s| s.id.clone())
+                    .collect::<Vec<_>>(),
+            ),
+        )
+        .order_by_desc(webhook_deliveries::Column::DateCreated)
+        .limit(DELIVERIES)
+        .all(dbconn.as_ref())
+        .await
+    {
+        Ok(deliveries) => deliveries,
+        Err(e) => {
+            error!("tried looking up webhook deliveries: {e}");
+            return Err(unreachable(&tmpl, &mut ctx));
+        }
+    };
+
+    let deliveries: Vec<Delivery> = deliveries
+        .into_iter()
+        .map(|d| Delivery {
+            url: subscriptions
+                .iter()
+                .find(|s| s.id == d.webhook_id)
+                .map(|s| s.url.clone())
+                .unwrap_or_default(),
+            id: d.id,
+            event: d.event,
+            attempts: d.attempts,
+            status_code: d.status_code,
+            last_error: d.last_error,
+            date_created: d.date_created,
+            delivered_at: d.delivered_at,
+        })
+        .collect();
+    let subscriptions: Vec<Subscription> = subscriptions
+        .into_iter()
+        .map(|s| Subscription {
+            events: hooks::events(&s).iter().map(|e| e.as_str()).collect(),
+            id: s.id,
+            url: s.url,
+            secret: s.secret,
+            date_created: s.date_created,
+        })
+        .collect();
+
+    ctx.insert("subscriptions", &subscriptions);
+    ctx.insert("deliveries", &deliveries);
+    ctx.insert(
+        "events",
+        &Event::ALL.iter().map(|e| e.as_str()).collect::<Vec<_>>(),
+    );
+
+    Ok(Html(tmpl.render("webhooks.html.tera", &ctx).unwrap()))
+}
+
+// the form sends every ticked event as a field of it's own
+pub async fn create(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(guard): Extension<Arc<Guard>>,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<Redirect, (StatusCode, Html<String>)> {
+    let mut ctx = Context::new();
+    let user = signed_in(&tmpl, &cookies, sessions, &mut ctx)?;
+
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.trim())
+            .unwrap_or_default()
+    };
+    let url = match field("url").parse::<Url>() {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => url,
+        _ => {
+            ctx.insert("error", "Webhooks need an http or https address.");
+            return Err(render(&tmpl, &ctx, StatusCode::UNPROCESSABLE_ENTITY));
+        }
+    };
+    // the reply to a refused delivery is shown on this page, so the receiver
+    // can't be something only this server can reach
+    if guard.check_resolved(&url).await.is_err() {
+        ctx.insert(
+            "error",
+            "Webhooks can't be sent to addresses on the inside of the network.",
+        );
+        return Err(render(&tmpl, &ctx, StatusCode::UNPROCESSABLE_ENTITY));
+    }
+    let mut events = Vec::new();
+    for (_, value) in fields.iter().filter(|(key, _)| key == "events") {
+        match value.parse::<Event>() {
+            Ok(event) if !events.contains(&event) => events.push(event),
+            Ok(_) => (),
+            Err(_) => {
+                ctx.insert("error", "One of the events is not known.");
+                return Err(render(&tmpl, &ctx, StatusCode::UNPROCESSABLE_ENTITY));
+            }
+        }
+    }
+    if events.is_empty() {
+        ctx.insert("error", "Choose at least one event.");
+        return Err(render(&tmpl, &ctx, StatusCode::UNPROCESSABLE_ENTITY));
+    }
+    let secret = match field("secret") {
+        "" => rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(SECRET_LENGTH)
+            .map(char::from)
+            .collect(),
+        secret if secret.len() <= MAX_SECRET_LENGTH => secret.to_string(),
+        _ => {
+            ctx.insert("error", "The secret is too long.");
+            return Err(render(&tmpl, &ctx, StatusCode::UNPROCESSABLE_ENTITY));
+        }
+    };
+
+    if let Err(e) = webhooks::Entity::insert(webhooks::ActiveModel {
+        id: Set(Ulid::new().to_string()),
+        created_by: Set(user.id),
+        url: Set(url.to_string()),
+        secret: Set(secret),
+        events: Set(events
+            .iter()
+            .map(|e| e.as_str())
+            .collect::<Vec<_>>()
+            .join(",")),
+        date_created: Set(Utc::now().into()),
+        deleted_at: Set(None),
+    })
+    .exec(dbconn.as_ref())
+    .await
+    {
+        error!("tried saving a webhook: {e}");
+        return Err(unreachable(&tmpl, &mut ctx));
+    }
+
+    Ok(Redirect::to("/webhooks".parse().unwrap()))
+}
+
+// deliveries that are still waiting are dropped along with it
+pub async fn delete(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Path(webhook_id): Path<Ulid>,
+) -> Result<Redirect, (StatusCode, Html<String>)> {
+    let mut ctx = Context::new();
+    let user = signed_in(&tmpl, &cookies, sessions, &mut ctx)?;
+
+    let subscription = match webhooks::Entity::find_by_id(webhook_id.to_string())
+        .filter(webhooks::Column::CreatedBy.eq(user.id))
+        .filter(webhooks::Column::DeletedAt.is_null())
+        .one(dbconn.as_ref())
+        .await
+    {
+        Ok(Some(subscription)) => subscription,
+        Ok(None) => {
+            ctx.insert("error", "That webhook does not exist.");
+            return Err(render(&tmpl, &ctx, StatusCode::NOT_FOUND));
+        }
+        Err(e) => {
+            error!("tried looking up a webhook: {e}");
+            return Err(unreachable(&tmpl, &mut ctx));
+        }
+    };
+
+    let mut der: webhooks::ActiveModel = subscription.into();
+    der.deleted_at = Set(Some(Utc::now().into()));
+    if let Err(e) = der.update(dbconn.as_ref()).await {
+        error!("tried deleting a webhook: {e}");
+        return Err(unreachable(&tmpl, &mut ctx));
+    }
+
+    Ok(Redirect::to("/webhooks".parse().unwrap()))
+}
+
+// sends a delivery again, like one that ran out of attempts while the
+// receiver was down
+pub async fn retry(
+    Extension(tmpl): Extension<Arc<Tera>>,
+    cookies: Cookies,
+    Extension(sessions): Extension<Arc<Sessions>>,
+    Extension(dbconn): Extension<Arc<DatabaseConnection>>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Path(delivery_id): Path<DeliveryId>,
+) -> Result<Redirect, (StatusCode, Html<String>)> {
+    let mut ctx = Context::new();
+    let user = signed_in(&tmpl, &cookies, sessions, &mut ctx)?;
+
+    let owned = match webhook_deliveries::Entity::find_by_id(delivery_id.to_string())
+        .filter(webhook_deliveries::Column::DeliveredAt.is_null())
+        .find_also_related(webhooks::Entity)
+        .one(dbconn.as_ref())
+        .await
+    {
+        Ok(found) => matches!(
+            found,
+            Some((_, Some(webhooks::Model { created_by, deleted_at: None, .. })))
+                if created_by == user.id
+        ),
+        Err(e) => {
+            error!("tried looking up a webhook delivery: {e}");
+            return Err(unreachable(&tmpl, &mut ctx));
+        }
+    };
+    if !owned {
+        ctx.insert(
+            "error",
+            "That delivery does not exist or was delivered already.",
+        );
+        return Err(render(&tmpl, &ctx, StatusCode::NOT_FOUND));
+    }
+
+    if let Err(e) = queue.push_unique(Job::DeliverWebhook { delivery_id }).await {
+        error!("tried queueing a webhook delivery: {e}");
+        return Err(unreachable(&tmpl, &mut ctx));
+    }
+
+    Ok(Redirect::to("/webhooks".parse().unwrap()))
+}
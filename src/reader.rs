@@ -0,0 +1,462 @@
+//! Reads the article out of a page and leaves the navigation, ads and popups
+//! around it behind, much like the reader views of browsers do. What's left is
+//! stored with the link so it can be read distraction free, offline, and
+//! searched by what it says.
+
+use std::{cmp::Ordering, collections::HashMap, fmt::Write};
+
+use {
+    reqwest::Url,
+    scraper::{node::Element, ElementRef, Html, Node, Selector},
+};
+
+use crate::interchange::escape_markup;
+
+// how many words an average reader gets through a minute
+const WORDS_PER_MINUTE: usize = 230;
+// anything shorter is most likely a teaser, a login wall or an error page
+const MIN_TEXT: usize = 250;
+// paragraphs shorter than this don't say anything about where the article is
+const MIN_PARAGRAPH: usize = 25;
+
+// never part of an article, along with everything inside of them
+const DROPPED: &[&str] = &[
+    "aside", "audio", "button", "canvas", "dialog", "embed", "footer", "form", "frame", "head",
+    "header", "iframe", "img", "input", "link", "map", "menu", "meta", "nav", "noscript", "object",
+    "picture", "script", "select", "source", "style", "svg", "template", "textarea", "title",
+    "video",
+];
+
+// kept as they are, though without any of their attributes
+const ALLOWED: &[&str] = &[
+    "a",
+    "abbr",
+    "b",
+    "blockquote",
+    "br",
+    "caption",
+    "cite",
+    "code",
+    "dd",
+    "del",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "s",
+    "samp",
+    "small",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "tr",
+    "ul",
+    "var",
+];
+
+// everything else separates the words around it in the plain text
+const INLINE: &[&str] = &[
+    "a", "abbr", "b", "cite", "code", "del", "em", "font", "i", "ins", "kbd", "mark", "q", "s",
+    "samp", "small", "span", "strong", "sub", "sup", "time", "u", "var",
+];
+
+// words in classes and ids that give away what an element is for
+const POSITIVE: &[&str] = &[
+    "article", "body", "content", "entry", "main", "page", "post", "story", "text",
+];
+const NEGATIVE: &[&str] = &[
+    "ad",
+    "ads",
+    "advert",
+    "advertisement",
+    "banner",
+    "breadcrumb",
+    "breadcrumbs",
+    "comment",
+    "comments",
+    "cookie",
+    "cookies",
+    "footer",
+    "header",
+    "masthead",
+    "menu",
+    "modal",
+    "nav",
+    "navigation",
+    "newsletter",
+    "outbrain",
+    "overlay",
+    "paywall",
+    "popup",
+    "promo",
+    "related",
+    "share",
+    "sharing",
+    "sidebar",
+    "social",
+    "sponsor",
+    "sponsored",
+    "subscribe",
+    "taboola",
+    "widget",
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct Article {
+    pub byline: Option<String>,
+    // sanitized markup, only the structure of the text and it's links are left
+    pub content: String,
+    pub text: String,
+    pub word_count: usize,
+    pub reading_minutes: usize,
+}
+
+// what was written out so far, as markup and as plain text
+#[derive(Default)]
+struct Output {
+    html: String,
+    text: String,
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn words(element: &Element) -> Vec<String> {
+    let mut words = Vec::new();
+    for attr in [element.attr("class"), element.attr("id")].iter().flatten() {
+        words.extend(
+            attr.split(|c: char| !c.is_ascii_alphanumeric())
+                .filter(|w| !w.is_empty())
+                .map(|w| w.to_ascii_lowercase()),
+        );
+    }
+    words
+}
+
+fn class_weight(element: &Element) -> f64 {
+    let words = words(element);
+    let mut weight = 0.0;
+    if words.iter().any(|w| NEGATIVE.contains(&w.as_str())) {
+        weight -= 25.0;
+    }
+    if words.iter().any(|w| POSITIVE.contains(&w.as_str())) {
+        weight += 25.0;
+    }
+    weight
+}
+
+fn tag_weight(name: &str) -> f64 {
+    match name {
+        "article" => 10.0,
+        "div" | "main" | "section" => 5.0,
+        "blockquote" | "pre" | "td" => 3.0,
+        "address" | "dd" | "dl" | "dt" | "form" | "li" | "ol" | "ul" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    }
+}
+
+// elements that are hidden or obviously not part of the article, a class
+// that sounds like both is given the benefit of the doubt
+fn skipped(element: &Element) -> bool {
+    let name = element.name();
+    if DROPPED.contains(&name) {
+        return true;
+    }
+    if element.attr("hidden").is_some() || element.attr("aria-hidden") == Some("true") {
+        return true;
+    }
+    if let Some(style) = element.attr("style") {
+        let style: String = style.chars().filter(|c| !c.is_whitespace()).collect();
+        if style.contains("display:none") || style.contains("visibility:hidden") {
+            return true;
+        }
+    }
+    if matches!(name, "html" | "body" | "article" | "main") {
+        return false;
+    }
+    let words = words(element);
+    words.iter().any(|w| NEGATIVE.contains(&w.as_str()))
+        && !words.iter().any(|w| POSITIVE.contains(&w.as_str()))
+}
+
+// how much of the text is made up of links, menus and link lists are mostly
+// links while articles mostly aren't
+fn link_density(element: ElementRef) -> f64 {
+    let length = element.text().map(|t| t.trim().len()).sum::<usize>();
+    if length == 0 {
+        return 0.0;
+    }
+    let links = Selector::parse("a").unwrap();
+    let linked = element
+        .select(&links)
+        .flat_map(|a| a.text())
+        .map(|t| t.trim().len())
+        .sum::<usize>();
+    linked as f64 / length as f64
+}
+
+fn emit_children(element: ElementRef, base: &Url, out: &mut Output) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => {
+                out.html.push_str(&escape_markup(text));
+                out.text.push_str(text);
+            }
+            Node::Element(_) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    emit(child, base, out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// writes the element out when it's allowed, unwraps it when only it's text
+// is of use, and leaves it out otherwise
+fn emit(element: ElementRef, base: &Url, out: &mut Output) {
+    let value = element.value();
+    if skipped(value) {
+        return;
+    }
+    let name = value.name();
+    let block = !INLINE.contains(&name);
+    if block {
+        out.text.push('\n');
+    }
+
+    // the page's own title is shown above the article already
+    let tag = match name {
+        "h1" => Some("h2"),
+        name if ALLOWED.contains(&name) => Some(name),
+        _ => None,
+    };
+    let opened = match tag {
+        Some("br") | Some("hr") => {
+            let _ = write!(out.html, "<{name}>");
+            out.text.push('\n');
+            return;
+        }
+        // links only survive when they lead to another web page
+        Some("a") => match value
+            .attr("href")
+            .and_then(|href| base.join(href.trim()).ok())
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+        {
+            Some(href) => {
+                let _ = write!(
+                    out.html,
+                    "<a href=\"{}\" rel=\"noopener noreferrer nofollow\">",
+                    escape_markup(href.as_str())
+                );
+                Some("a")
+            }
+            None => None,
+        },
+        Some(tag) => {
+            let _ = write!(out.html, "<{tag}>");
+            Some(tag)
+        }
+        None => None,
+    };
+
+    emit_children(element, base, out);
+
+    if let Some(tag) = opened {
+        let _ = write!(out.html, "</{tag}>");
+    }
+    if block {
+        out.text.push('\n');
+    }
+}
+
+// whoever the page says wrote it, when it says so in the text
+fn byline(body: ElementRef) -> Option<String> {
+    let selector = Selector::parse("[rel~=author], [itemprop~=author], .byline, .author").unwrap();
+    body.select(&selector)
+        .map(|author| collapse(&author.text().collect::<String>()))
+        .find(|author| !author.is_empty() && author.len() < 100)
+}
+
+// finds the element with the most paragraphs of real text in it, the way
+// readability does, and cleans it up for reading
+pub fn extract(document: &Html, base: &Url) -> Option<Article> {
+    let body = document.select(&Selector::parse("body").unwrap()).next()?;
+
+    // every paragraph counts towards it's parent and, by less, the elements
+    // above that
+    let paragraphs = Selector::parse("p, pre, td, blockquote").unwrap();
+    let mut scores = HashMap::new();
+    for paragraph in body.select(&paragraphs) {
+        if skipped(paragraph.value())
+            || paragraph
+                .ancestors()
+                .filter_map(ElementRef::wrap)
+                .any(|ancestor| skipped(ancestor.value()))
+        {
+            continue;
+        }
+        let text = collapse(&paragraph.text().collect::<String>());
+        if text.chars().count() < MIN_PARAGRAPH {
+            continue;
+        }
+        let score =
+            1.0 + text.matches(',').count() as f64 + (text.chars().count() as f64 / 100.0).min(3.0);
+        for (depth, ancestor) in paragraph
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .take(3)
+            .enumerate()
+        {
+            let value = ancestor.value();
+            let entry = scores
+                .entry(ancestor.id())
+                .or_insert_with(|| tag_weight(value.name()) + class_weight(value));
+            *entry += score / (depth + 1) as f64;
+        }
+    }
+    let scores: HashMap<_, f64> = scores
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let element = ElementRef::wrap(document.tree.get(id)?)?;
+            Some((id, score * (1.0 - link_density(element))))
+        })
+        .collect();
+    let (best, top) = scores
+        .iter()
+        .max_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(Ordering::Equal))
+        .and_then(|(id, score)| Some((ElementRef::wrap(document.tree.get(*id)?)?, *score)))?;
+
+    // articles are often split up into several sections next to each other,
+    // those that score well enough or read like a paragraph are kept too
+    let mut out = Output::default();
+    let threshold = (top * 0.2).max(10.0);
+    match best.parent().and_then(ElementRef::wrap) {
+        Some(parent) => {
+            for sibling in parent.children().filter_map(ElementRef::wrap) {
+                if sibling.id() == best.id() {
+                    emit_children(best, base, &mut out);
+                    continue;
+                }
+                let keep = scores
+                    .get(&sibling.id())
+                    .map(|score| *score >= threshold)
+                    .unwrap_or(false)
+                    || (sibling.value().name() == "p" && {
+                        let text = collapse(&sibling.text().collect::<String>());
+                        text.len() > 80 && link_density(sibling) < 0.25
+                    });
+                if keep {
+                    emit(sibling, base, &mut out);
+                }
+            }
+        }
+        None => emit_children(best, base, &mut out),
+    }
+
+    let text = out
+        .text
+        .lines()
+        .map(collapse)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    if text.chars().count() < MIN_TEXT {
+        return None;
+    }
+    let word_count = text.split_whitespace().count();
+
+    Some(Article {
+        byline: byline(body),
+        content: out.html.trim().to_string(),
+        text,
+        word_count,
+        reading_minutes: word_count.div_ceil(WORDS_PER_MINUTE).max(1),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAGRAPH: &str = "The river rose through the night, and by morning the lower streets \
+        were under water, the shops along the quay closed, and the ferry stopped running.";
+
+    fn extract_from(body: &str) -> Option<Article> {
+        let html = format!("<html><head><title>Page</title></head><body>{body}</body></html>");
+        let base = "https://example.com/news/flood".parse().unwrap();
+        extract(&Html::parse_document(&html), &base)
+    }
+
+    #[test]
+    fn keeps_the_article_and_leaves_the_rest() {
+        let article = extract_from(&format!(
+            r#"<nav><a href="/">Home</a> <a href="/news">News</a></nav>
+            <div class="sidebar ads"><p>{PARAGRAPH} Buy now, limited offer, today only.</p></div>
+            <article class="post" onclick="track()">
+                <h1>Flood</h1>
+                <span class="byline">Jane Doe</span>
+                <p>{PARAGRAPH}</p>
+                <p style="display: none">Hidden paragraph that should not be read at all.</p>
+                <p>{PARAGRAPH} See <a href="/news/map">the map</a> and
+                    <a href="javascript:alert(1)">this</a>.</p>
+                <script>document.write("tracking")</script>
+                <p>{PARAGRAPH}</p>
+            </article>
+            <footer><p>{PARAGRAPH} Copyright, all rights reserved.</p></footer>"#
+        ))
+        .expect("the article was found");
+
+        assert_eq!(article.byline.as_deref(), Some("Jane Doe"));
+        assert_eq!(article.text.matches("The river rose").count(), 3);
+        for left_out in ["Home", "Buy now", "Hidden", "tracking", "Copyright"] {
+            assert!(
+                !article.text.contains(left_out),
+                "{} is in the text",
+                left_out
+            );
+        }
+        // headings are demoted below the page's title, attributes are dropped
+        // and links only kept when they lead to another page
+        assert!(article.content.contains("<h2>Flood</h2>"));
+        assert!(article.content.contains(
+            r#"<a href="https://example.com/news/map" rel="noopener noreferrer nofollow">the map</a>"#
+        ));
+        assert!(!article.content.contains("javascript"));
+        assert!(!article.content.contains("onclick"));
+        assert!(!article.content.contains("class="));
+        assert_eq!(article.word_count, article.text.split_whitespace().count());
+        assert_eq!(article.reading_minutes, 1);
+    }
+
+    #[test]
+    fn short_pages_have_no_article() {
+        assert!(extract_from("<p>Sign in to continue reading this story.</p>").is_none());
+        assert!(extract_from(&format!("<nav><p>{PARAGRAPH}</p></nav>")).is_none());
+    }
+}
@@ -0,0 +1,250 @@
+//! Links can be snoozed until they matter again, they're left out of the
+//! backlog until then and brought back up with a reminder in the application
+//! and wherever else reminders are configured to go.
+
+use std::{sync::Arc, time::Duration};
+
+use {
+    anyhow::Result,
+    chrono::Utc,
+    reqwest::{Client, Url},
+    sea_orm::{
+        entity::{prelude::*, Set},
+        sea_query::Expr,
+        DatabaseConnection, QueryOrder,
+    },
+    serde_json::json,
+    tera::Context,
+    tracing::info,
+    ulid::Ulid,
+};
+
+use crate::{
+    api::{
+        links::{Link, LinkId},
+        UserId,
+    },
+    config::Configuration,
+    database::entity::{links, notifications, users},
+    jobs::{Job, Queue},
+    mail::Outbox,
+};
+
+// what notifications about a due link are filed under
+pub const KIND: &str = "reminder";
+const TIMEOUT: u64 = 15;
+
+pub struct Reminders {
+    client: Client,
+    cfg: Arc<Configuration>,
+    dbconn: Arc<DatabaseConnection>,
+    outbox: Arc<Outbox>,
+    webhook: Option<Url>,
+}
+
+impl Reminders {
+    pub fn new(
+        cfg: Arc<Configuration>,
+        dbconn: Arc<DatabaseConnection>,
+        outbox: Arc<Outbox>,
+    ) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(TIMEOUT))
+            .user_agent(concat!("link_backlog/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+
+        let webhook = cfg.reminders.as_ref().and_then(|c| c.webhook.clone());
+        Ok(Self {
+            client,
+            cfg,
+            dbconn,
+            outbox,
+            webhook,
+        })
+    }
+
+    // the owner hears about it by email too, when they have a verified address
+    async fn email(
+        &self,
+        queue: &Queue,
+        link: &links::Model,
+        due: DateTimeWithTimeZone,
+    ) -> Result<()> {
+        let to = match users::Entity::find_by_id(link.created_by.clone())
+            .one(self.dbconn.as_ref())
+            .await?
+        {
+            Some(users::Model {
+                email: Some(email),
+                email_verified: true,
+                ..
+            }) => email,
+            _ => return Ok(()),
+        };
+
+        let mut ctx = Context::new();
+        ctx.insert("title", link.title.as_ref().unwrap_or(&link.url));
+        ctx.insert(
+            "url",
+            &self
+                .cfg
+                .public_url(&format!("go/{}", link.id))
+                .map(|url| url.to_string())
+                .unwrap_or_else(|| link.url.clone()),
+        );
+        ctx.insert("due_at", &due);
+        let subject = format!("Reminder: {}", link.title.as_ref().unwrap_or(&link.url));
+        self.outbox.send(queue, &to, &subject, KIND, &ctx).await?;
+
+        Ok(())
+    }
+
+    // lets the owner know the link is due, a failed delivery is tried again
+    // without showing the banner or sending the email twice
+    pub async fn remind(&self, queue: &Queue, link_id: &LinkId) -> Result<()> {
+        let link = match links::Entity::find_by_id(link_id.to_string())
+            .filter(links::Column::DeletedAt.is_null())
+            .one(self.dbconn.as_ref())
+            .await?
+        {
+            Some(link) => link,
+            None => return Ok(()),
+        };
+        // the reminder was moved or taken away since the job was queued
+        let due = match link.remind_at {
+            Some(due) if due <= Utc::now() => due,
+            _ => return Ok(()),
+        };
+        if matches!(link.reminded_at, Some(reminded) if reminded >= due) {
+            return Ok(());
+        }
+
+        let (notification_id, emailed) = match notifications::Entity::find()
+            .filter(notifications::Column::LinkId.eq(link.id.clone()))
+            .filter(notifications::Column::Kind.eq(KIND))
+            .filter(notifications::Column::DueAt.eq(due))
+            .one(self.dbconn.as_ref())
+            .await?
+        {
+            Some(notification) => (notification.id, notification.emailed_at.is_some()),
+            None => {
+                let id = Ulid::new().to_string();
+                notifications::Entity::insert(notifications::ActiveModel {
+                    id: Set(id.clone()),
+                    user_id: Set(link.created_by.clone()),
+                    link_id: Set(link.id.clone()),
+                    kind: Set(KIND.to_string()),
+                    due_at: Set(due),
+                    date_created: Set(Utc::now().into()),
+                    dismissed_at: Set(None),
+                    emailed_at: Set(None),
+                })
+                .exec(self.dbconn.as_ref())
+                .await?;
+                (id, false)
+            }
+        };
+        // the banner can be up already while the email still failed to queue
+        if !emailed {
+            self.email(queue, &link, due).await?;
+            notifications::Entity::update_many()
+                .col_expr(
+                    notifications::Column::EmailedAt,
+                    Expr::value(Some(DateTimeWithTimeZone::from(Utc::now()))),
+                )
+                .filter(notifications::Column::Id.eq(notification_id))
+                .exec(self.dbconn.as_ref())
+                .await?;
+        }
+
+        if let Some(webhook) = &self.webhook {
+            let payload = json!({
+                "event": KIND,
+                "due_at": due,
+                "link": Link::from_inactive_der(link.clone())?,
+            });
+            self.client
+                .post(webhook.clone())
+                .json(&payload)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+
+        links::Entity::update_many()
+            .col_expr(
+                links::Column::RemindedAt,
+                Expr::value(Some(DateTimeWithTimeZone::from(Utc::now()))),
+            )
+            .filter(links::Column::Id.eq(link.id.clone()))
+            .exec(self.dbconn.as_ref())
+            .await?;
+        info!("reminded {} of link {}", link.created_by, link.id);
+
+        Ok(())
+    }
+}
+
+// snoozes the link until the reminder is due, or wakes it up again when
+// there's no reminder
+pub async fn snooze(
+    dbconn: &DatabaseConnection,
+    queue: &Queue,
+    link_id: &LinkId,
+    remind_at: Option<DateTimeWithTimeZone>,
+) -> Result<()> {
+    links::Entity::update_many()
+        .col_expr(links::Column::RemindAt, Expr::value(remind_at))
+        .col_expr(
+            links::Column::RemindedAt,
+            Expr::value(Option::<DateTimeWithTimeZone>::None),
+        )
+        .filter(links::Column::Id.eq(link_id.to_string()))
+        .exec(dbconn)
+        .await?;
+    if let Some(remind_at) = remind_at {
+        queue
+            .push_at(Job::Remind { link_id: *link_id }, remind_at)
+            .await?;
+    }
+
+    Ok(())
+}
+
+// the banners the user hasn't closed yet, along with their links
+pub async fn pending(
+    dbconn: &DatabaseConnection,
+    user_id: &UserId,
+) -> Result<Vec<(notifications::Model, links::Model)>, DbErr> {
+    Ok(notifications::Entity::find()
+        .filter(notifications::Column::UserId.eq(user_id.clone()))
+        .filter(notifications::Column::DismissedAt.is_null())
+        .order_by_asc(notifications::Column::DueAt)
+        .find_also_related(links::Entity)
+        .all(dbconn)
+        .await?
+        .into_iter()
+        .filter_map(|(notification, link)| Some((notification, link?)))
+        .filter(|(_, link)| link.deleted_at.is_none())
+        .collect())
+}
+
+// closes a banner, only the user it was for can
+pub async fn dismiss(
+    dbconn: &DatabaseConnection,
+    user_id: &UserId,
+    id: &str,
+) -> Result<bool, DbErr> {
+    let result = notifications::Entity::update_many()
+        .col_expr(
+            notifications::Column::DismissedAt,
+            Expr::value(Some(DateTimeWithTimeZone::from(Utc::now()))),
+        )
+        .filter(notifications::Column::Id.eq(id))
+        .filter(notifications::Column::UserId.eq(user_id.clone()))
+        .filter(notifications::Column::DismissedAt.is_null())
+        .exec(dbconn)
+        .await?;
+
+    Ok(result.rows_affected > 0)
+}
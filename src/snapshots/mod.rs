@@ -0,0 +1,519 @@
+//! Copies of the pages links point to, with their stylesheets and images
+//! inlined, so there's still something to read once the page is gone.
+
+mod s3;
+pub mod storage;
+mod warc;
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use {
+    anyhow::{anyhow, Result},
+    chrono::Utc,
+    reqwest::{
+        header::{self, HeaderMap, HeaderValue},
+        redirect, Client, Url,
+    },
+    scraper::{Html, Node, Selector},
+    sea_orm::{
+        entity::{prelude::*, Set},
+        DatabaseConnection, QueryOrder, QuerySelect,
+    },
+    tracing::debug,
+    ulid::Ulid,
+};
+
+use crate::{
+    api::links::LinkId,
+    config,
+    database::entity::{links, snapshots},
+    net::{self, Guard},
+};
+
+use self::{
+    s3::S3,
+    storage::{Filesystem, Storage},
+    warc::{Exchange, Warc},
+};
+
+// used when the configuration doesn't say otherwise
+const MAX_SIZE: usize = 10 * 1024 * 1024;
+const KEEP: usize = 3;
+const TIMEOUT: u64 = 30;
+const REDIRECTS: usize = 5;
+const USER_AGENT: &str = concat!("link_backlog/", env!("CARGO_PKG_VERSION"));
+// how often snapshots that are too old are looked for
+pub const PRUNE_INTERVAL: i64 = 24 * 60 * 60;
+
+// snapshots are served with this, nothing in them may run or reach out to the
+// page they were taken from
+pub const CONTENT_SECURITY_POLICY: &str = "default-src 'none'; img-src data:; \
+    style-src 'unsafe-inline' data:; font-src data:; media-src data:; base-uri 'none'; \
+    form-action 'none'; frame-ancestors 'self'; sandbox";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Stylesheet,
+    Other,
+}
+
+// the elements that refer to something worth inlining, and where they do so
+const REFERENCES: [(&str, &str, Kind); 5] = [
+    (
+        "link[rel~=\"stylesheet\" i][href]",
+        "href",
+        Kind::Stylesheet,
+    ),
+    ("link[rel~=\"icon\" i][href]", "href", Kind::Other),
+    ("img[src]", "src", Kind::Other),
+    ("input[type=\"image\" i][src]", "src", Kind::Other),
+    ("video[poster]", "poster", Kind::Other),
+];
+
+// elements that would run something, or send the browser elsewhere
+const REMOVED: &str = "script, base, meta[http-equiv], iframe, frame, object, embed";
+
+// calls the closure with every reference in a url() of the stylesheet and puts
+// whatever it returns in it's place, references it returns nothing for stay
+fn map_css_urls(css: &str, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("url(") {
+        let (before, after) = rest.split_at(start + 4);
+        out.push_str(before);
+        let end = match after.find(')') {
+            Some(end) => end,
+            None => {
+                rest = after;
+                break;
+            }
+        };
+        let raw = after[..end].trim().trim_matches(|c| c == '"' || c == '\'');
+        match f(raw) {
+            Some(replaced) => {
+                out.push('"');
+                out.push_str(&replaced);
+                out.push('"');
+            }
+            None => out.push_str(&after[..end]),
+        }
+        rest = &after[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn css_references(css: &str, base: &Url) -> Vec<Url> {
+    let mut found = Vec::new();
+    map_css_urls(css, |raw| {
+        if !raw.starts_with("data:") {
+            found.extend(base.join(raw).ok());
+        }
+        None
+    });
+    found
+}
+
+fn rewrite_css(css: &str, base: &Url, inlined: &HashMap<Url, String>) -> String {
+    map_css_urls(css, |raw| inlined.get(&base.join(raw).ok()?).cloned())
+}
+
+fn data_url(content_type: &str, body: &[u8]) -> String {
+    format!("data:{content_type};base64,{}", base64::encode(body))
+}
+
+// relative references are resolved against the page's base element if it has
+// one, otherwise against where the page ended up after redirects
+fn document_base(document: &Html, page: &Url) -> Url {
+    let selector = Selector::parse("base[href]").unwrap();
+    document
+        .select(&selector)
+        .next()
+        .and_then(|base| page.join(base.value().attr("href")?).ok())
+        .unwrap_or_else(|| page.clone())
+}
+
+// everything the page refers to that should end up inside the snapshot
+fn references(html: &str, page: &Url) -> Vec<(Url, Kind)> {
+    let document = Html::parse_document(html);
+    let base = document_base(&document, page);
+
+    let mut found: Vec<(Url, Kind)> = Vec::new();
+    for (selector, attr, kind) in REFERENCES {
+        let selector = Selector::parse(selector).unwrap();
+        for element in document.select(&selector) {
+            if let Some(url) = element.value().attr(attr).and_then(|r| base.join(r).ok()) {
+                found.push((url, kind));
+            }
+        }
+    }
+    let selector = Selector::parse("style").unwrap();
+    for style in document.select(&selector) {
+        for url in css_references(&style.text().collect::<String>(), &base) {
+            found.push((url, Kind::Other));
+        }
+    }
+    let selector = Selector::parse("[style]").unwrap();
+    for element in document.select(&selector) {
+        for url in css_references(element.value().attr("style").unwrap_or_default(), &base) {
+            found.push((url, Kind::Other));
+        }
+    }
+
+    let mut seen = Vec::new();
+    found.retain(|(url, _)| {
+        let new = !seen.contains(url) && matches!(url.scheme(), "http" | "https");
+        seen.push(url.clone());
+        new
+    });
+    found
+}
+
+// puts what was downloaded in place of the references to it, and takes out
+// whatever could run or load something when the snapshot is viewed
+fn rewrite(html: &str, page: &Url, inlined: &HashMap<Url, String>) -> String {
+    let mut document = Html::parse_document(html);
+    let base = document_base(&document, page);
+
+    let selector = Selector::parse(REMOVED).unwrap();
+    let removed: Vec<_> = document.select(&selector).map(|e| e.id()).collect();
+
+    let mut attrs = Vec::new();
+    for (selector, attr, _) in REFERENCES {
+        let selector = Selector::parse(selector).unwrap();
+        for element in document.select(&selector) {
+            if let Some(data) = element
+                .value()
+                .attr(attr)
+                .and_then(|r| base.join(r).ok())
+                .and_then(|url| inlined.get(&url))
+            {
+                attrs.push((element.id(), attr, data.clone()));
+            }
+        }
+    }
+    let selector = Selector::parse("[style]").unwrap();
+    for element in document.select(&selector) {
+        let style = element.value().attr("style").unwrap_or_default();
+        attrs.push((element.id(), "style", rewrite_css(style, &base, inlined)));
+    }
+    // links keep working, they lead to the live page
+    let selector = Selector::parse("a[href]").unwrap();
+    for element in document.select(&selector) {
+        if let Some(url) = element.value().attr("href").and_then(|r| base.join(r).ok()) {
+            attrs.push((element.id(), "href", url.to_string()));
+        }
+    }
+
+    let mut texts = Vec::new();
+    let selector = Selector::parse("style").unwrap();
+    for style in document.select(&selector) {
+        for child in style.children() {
+            if let Node::Text(text) = child.value() {
+                texts.push((child.id(), rewrite_css(&text.text, &base, inlined)));
+            }
+        }
+    }
+
+    for id in removed {
+        if let Some(mut node) = document.tree.get_mut(id) {
+            node.detach();
+        }
+    }
+    for (id, attr, value) in attrs {
+        if let Some(Node::Element(element)) = document.tree.get_mut(id).as_mut().map(|n| n.value())
+        {
+            if let Some((_, old)) = element
+                .attrs
+                .iter_mut()
+                .find(|(name, _)| &*name.local == attr)
+            {
+                *old = value.into();
+            }
+        }
+    }
+    for (id, value) in texts {
+        if let Some(Node::Text(text)) = document.tree.get_mut(id).as_mut().map(|n| n.value()) {
+            text.text = value.into();
+        }
+    }
+    // event handlers can't run anyways, alternative sources and integrity
+    // checks would point past what was inlined
+    let ids: Vec<_> = document.tree.nodes().map(|n| n.id()).collect();
+    for id in ids {
+        if let Some(Node::Element(element)) = document.tree.get_mut(id).as_mut().map(|n| n.value())
+        {
+            element.attrs.retain(|name, _| {
+                let name: &str = &name.local;
+                !name.starts_with("on") && !matches!(name, "srcset" | "integrity")
+            });
+        }
+    }
+
+    format!("<!DOCTYPE html>\n{}", document.root_element().html())
+}
+
+pub struct Snapshotter {
+    client: net::Client,
+    storage: Box<dyn Storage>,
+    max_size: usize,
+    keep: usize,
+    max_age: Option<i64>,
+    dbconn: Arc<DatabaseConnection>,
+}
+
+impl Snapshotter {
+    pub fn new(
+        cfg: &config::Snapshots,
+        guard: &Arc<Guard>,
+        dbconn: Arc<DatabaseConnection>,
+    ) -> Result<Self> {
+        // redirects are followed by hand, every hop is checked the same way
+        let client = guard.client(Duration::from_secs(TIMEOUT), 0)?;
+        let storage: Box<dyn Storage> = match &cfg.storage {
+            config::Storage::Filesystem { path } => Box::new(Filesystem::new(path.clone())),
+            config::Storage::S3 {
+                endpoint,
+                bucket,
+                region,
+                access_key,
+                secret_key,
+            } => Box::new(S3::new(
+                // the bucket is the operator's own, and may well be internal
+                Client::builder()
+                    .timeout(Duration::from_secs(TIMEOUT))
+                    .redirect(redirect::Policy::none())
+                    .build()?,
+                endpoint.clone(),
+                bucket.clone(),
+                region.clone(),
+                access_key.clone(),
+                secret_key.clone(),
+            )),
+        };
+
+        Ok(Self {
+            client,
+            storage,
+            max_size: cfg.max_size.unwrap_or(MAX_SIZE),
+            keep: cfg.keep.unwrap_or(KEEP).max(1),
+            max_age: cfg.max_age,
+            dbconn,
+        })
+    }
+
+    // downloads at most limit bytes, nothing is returned for anything larger,
+    // redirects are followed here instead of by the client so every hop ends
+    // up in the archive
+    async fn download(
+        &self,
+        url: &Url,
+        limit: usize,
+        warc: &mut Warc,
+    ) -> Result<Option<(Url, String, Vec<u8>)>> {
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(header::USER_AGENT, HeaderValue::from_static(USER_AGENT));
+        request_headers.insert(header::ACCEPT, HeaderValue::from_static("*/*"));
+
+        let mut url = url.clone();
+        for _ in 0..=REDIRECTS {
+            let date = Utc::now();
+            let mut resp = self
+                .client
+                .get(url.clone())?
+                .headers(request_headers.clone())
+                .send()
+                .await?;
+            let status = resp.status();
+            let version = resp.version();
+            let response_headers = resp.headers().clone();
+
+            let mut body = Vec::new();
+            while let Some(chunk) = resp.chunk().await? {
+                body.extend_from_slice(&chunk);
+                if body.len() > limit {
+                    return Ok(None);
+                }
+            }
+            warc.exchange(Exchange {
+                url: &url,
+                date,
+                request_headers: &request_headers,
+                version,
+                status,
+                response_headers: &response_headers,
+                body: &body,
+            });
+
+            let location = response_headers
+                .get(header::LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .and_then(|l| url.join(l).ok());
+            match location {
+                Some(location) if status.is_redirection() => url = location,
+                _ if !status.is_success() => return Err(anyhow!("{url} answered with {status}")),
+                _ => {
+                    let content_type = response_headers
+                        .get(header::CONTENT_TYPE)
+                        .and_then(|t| t.to_str().ok())
+                        .unwrap_or("application/octet-stream")
+                        .to_string();
+                    return Ok(Some((url, content_type, body)));
+                }
+            }
+        }
+
+        Err(anyhow!("{url} redirected too often"))
+    }
+
+    // turns a resource into a data url, as long as it still fits
+    async fn inline(&self, url: &Url, budget: &mut usize, warc: &mut Warc) -> Option<String> {
+        // base64 takes up a third more than the original
+        match self.download(url, *budget / 4 * 3, warc).await {
+            Ok(Some((_, content_type, body))) => {
+                let data = data_url(&content_type, &body);
+                *budget = budget.checked_sub(data.len())?;
+                Some(data)
+            }
+            Ok(None) => {
+                debug!("{url} is too large to fit into the snapshot");
+                None
+            }
+            Err(e) => {
+                debug!("{url} couldn't be inlined into a snapshot: {e}");
+                None
+            }
+        }
+    }
+
+    // takes a snapshot of the page the link points to, stylesheets are
+    // inlined first since pages tend to be unreadable without them, images
+    // follow as long as there's room left
+    pub async fn take(&self, link_id: &LinkId) -> Result<()> {
+        // links that were deleted since being queued aren't worth keeping
+        let link = match links::Entity::find_by_id(link_id.to_string())
+            .filter(links::Column::DeletedAt.is_null())
+            .one(self.dbconn.as_ref())
+            .await?
+        {
+            Some(link) => link,
+            None => return Ok(()),
+        };
+        let url: Url = link.url.parse()?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!("only web pages can be snapshotted"));
+        }
+
+        let id = Ulid::new();
+        let mut warc = Warc::new(&format!("{id}.warc.gz"));
+        let (page, _, body) = self
+            .download(&url, self.max_size, &mut warc)
+            .await?
+            .ok_or_else(|| anyhow!("page is larger than snapshots may be"))?;
+        let html = String::from_utf8_lossy(&body).into_owned();
+        let mut budget = self.max_size.saturating_sub(html.len());
+
+        let mut inlined = HashMap::new();
+        for (url, kind) in references(&html, &page) {
+            if kind == Kind::Other {
+                if let Some(data) = self.inline(&url, &mut budget, &mut warc).await {
+                    inlined.insert(url, data);
+                }
+                continue;
+            }
+
+            let (css_url, css) = match self.download(&url, budget, &mut warc).await {
+                Ok(Some((css_url, _, body))) => {
+                    (css_url, String::from_utf8_lossy(&body).into_owned())
+                }
+                Ok(None) => continue,
+                Err(e) => {
+                    debug!("stylesheet {url} couldn't be inlined into a snapshot: {e}");
+                    continue;
+                }
+            };
+            // fonts and images in stylesheets are relative to the stylesheet
+            let mut assets = HashMap::new();
+            for asset in css_references(&css, &css_url) {
+                if let Some(data) = self.inline(&asset, &mut budget, &mut warc).await {
+                    assets.insert(asset, data);
+                }
+            }
+            let data = data_url("text/css", rewrite_css(&css, &css_url, &assets).as_bytes());
+            if let Some(left) = budget.checked_sub(data.len()) {
+                budget = left;
+                inlined.insert(url, data);
+            }
+        }
+        let snapshot = rewrite(&html, &page, &inlined).into_bytes();
+
+        let warc = warc.finish();
+
+        let storage_key = format!("{}/{id}.html", link.id);
+        let warc_key = format!("{}/{id}.warc.gz", link.id);
+        let size = snapshot.len() as i64;
+        let warc_size = warc.len() as i64;
+        self.storage.put(&storage_key, snapshot).await?;
+        self.storage.put(&warc_key, warc).await?;
+        snapshots::Entity::insert(snapshots::ActiveModel {
+            id: Set(id.to_string()),
+            link_id: Set(link.id.clone()),
+            storage_key: Set(storage_key),
+            size: Set(size),
+            date_created: Set(Utc::now().into()),
+            warc_key: Set(Some(warc_key)),
+            warc_size: Set(Some(warc_size)),
+        })
+        .exec(self.dbconn.as_ref())
+        .await?;
+
+        // only the newest few of every link are kept around
+        let outdated = snapshots::Entity::find()
+            .filter(snapshots::Column::LinkId.eq(link.id))
+            .order_by_desc(snapshots::Column::DateCreated)
+            .offset(self.keep as u64)
+            .all(self.dbconn.as_ref())
+            .await?;
+        self.remove(outdated).await
+    }
+
+    pub async fn load(&self, snapshot: &snapshots::Model) -> Result<Option<Vec<u8>>> {
+        self.storage.get(&snapshot.storage_key).await
+    }
+
+    // snapshots taken before archives were written don't have one
+    pub async fn load_warc(&self, snapshot: &snapshots::Model) -> Result<Option<Vec<u8>>> {
+        match &snapshot.warc_key {
+            Some(key) => self.storage.get(key).await,
+            None => Ok(None),
+        }
+    }
+
+    // the stored copies go first, a snapshot without them would be useless
+    async fn remove(&self, snapshots: Vec<snapshots::Model>) -> Result<()> {
+        for snapshot in snapshots {
+            self.storage.delete(&snapshot.storage_key).await?;
+            if let Some(key) = &snapshot.warc_key {
+                self.storage.delete(key).await?;
+            }
+            snapshots::Entity::delete_many()
+                .filter(snapshots::Column::Id.eq(snapshot.id))
+                .exec(self.dbconn.as_ref())
+                .await?;
+        }
+        Ok(())
+    }
+
+    // deletes the snapshots that are older than they may be
+    pub async fn prune(&self) -> Result<()> {
+        let max_age = match self.max_age {
+            Some(max_age) => max_age,
+            None => return Ok(()),
+        };
+        let expired = snapshots::Entity::find()
+            .filter(snapshots::Column::DateCreated.lt(Utc::now() - chrono::Duration::days(max_age)))
+            .all(self.dbconn.as_ref())
+            .await?;
+        self.remove(expired).await
+    }
+}
@@ -0,0 +1,146 @@
+//! A small client for the handful of s3 calls snapshots need, requests are
+//! signed with aws signature version 4 which minio and the other s3
+//! compatible stores understand as well.
+
+use std::fmt::Write;
+
+use {
+    anyhow::{anyhow, Result},
+    async_trait::async_trait,
+    chrono::Utc,
+    hmac::{Hmac, Mac},
+    reqwest::{Client, Method, StatusCode, Url},
+    sha2::{Digest, Sha256},
+};
+
+use super::storage::Storage;
+
+const DEFAULT_REGION: &str = "us-east-1";
+
+pub struct S3 {
+    client: Client,
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac takes keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+// percent encodes everything but the characters s3 leaves alone in paths
+fn encode_key(key: &str) -> String {
+    let mut encoded = String::with_capacity(key.len());
+    for b in key.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(b as char)
+            }
+            _ => {
+                let _ = write!(encoded, "%{b:02X}");
+            }
+        }
+    }
+    encoded
+}
+
+impl S3 {
+    pub fn new(
+        client: Client,
+        endpoint: Url,
+        bucket: String,
+        region: Option<String>,
+        access_key: String,
+        secret_key: String,
+    ) -> Self {
+        Self {
+            client,
+            endpoint,
+            bucket,
+            region: region.unwrap_or_else(|| String::from(DEFAULT_REGION)),
+            access_key,
+            secret_key,
+        }
+    }
+
+    fn url(&self, key: &str) -> Result<Url> {
+        let endpoint = self.endpoint.as_str().trim_end_matches('/');
+        Ok(format!("{endpoint}/{}/{}", self.bucket, encode_key(key)).parse()?)
+    }
+
+    async fn send(&self, method: Method, key: &str, body: Vec<u8>) -> Result<reqwest::Response> {
+        let url = self.url(key)?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.to_string(),
+            _ => return Err(anyhow!("s3 endpoint has no host")),
+        };
+        let now = Utc::now();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(&body));
+
+        let canonical_request = format!(
+            "{method}\n{}\n\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{timestamp}\n\nhost;x-amz-content-sha256;x-amz-date\n{payload_hash}",
+            url.path(),
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical_request.as_bytes())),
+        );
+        let key = [date.as_str(), &self.region, "s3", "aws4_request"]
+            .iter()
+            .fold(
+                format!("AWS4{}", self.secret_key).into_bytes(),
+                |key, part| hmac(&key, part),
+            );
+        let signature = hex::encode(hmac(&key, &string_to_sign));
+
+        Ok(self
+            .client
+            .request(method, url)
+            .header("x-amz-date", timestamp)
+            .header("x-amz-content-sha256", payload_hash)
+            .header(
+                "authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={signature}",
+                    self.access_key,
+                ),
+            )
+            .body(body)
+            .send()
+            .await?)
+    }
+}
+
+#[async_trait]
+impl Storage for S3 {
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<()> {
+        self.send(Method::PUT, key, body)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let resp = self.send(Method::GET, key, Vec::new()).await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(resp.error_for_status()?.bytes().await?.to_vec()))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let resp = self.send(Method::DELETE, key, Vec::new()).await?;
+        if resp.status() != StatusCode::NOT_FOUND {
+            resp.error_for_status()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use {
+    anyhow::{anyhow, Result},
+    async_trait::async_trait,
+    tokio::fs,
+};
+
+// somewhere to keep snapshots, keys look like relative paths
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<()>;
+    // nothing is returned when the key was never stored or already deleted
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    // deleting a key that doesn't exist isn't an error
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+pub struct Filesystem {
+    root: PathBuf,
+}
+
+impl Filesystem {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    // keys are made up by the application, but it never hurts to make sure
+    // they can't point outside the directory
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        if key
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..")
+        {
+            return Err(anyhow!("storage key {key} is not a valid path"));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl Storage for Filesystem {
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        // written next to it first, so a half written file is never read
+        let partial = path.with_extension("partial");
+        fs::write(&partial, body).await?;
+        fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.path(key)?).await {
+            Ok(body) => Ok(Some(body)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
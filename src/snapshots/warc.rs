@@ -0,0 +1,150 @@
+//! Writes what was downloaded for a snapshot as a WARC file, the format web
+//! archives use, so it can be replayed with tools like pywb or
+//! ReplayWeb.page. Every record is compressed on it's own, which is what
+//! those tools expect of a .warc.gz file.
+
+use std::{fmt::Write as _, io::Write};
+
+use {
+    chrono::{DateTime, Utc},
+    flate2::{write::GzEncoder, Compression},
+    reqwest::{
+        header::{self, HeaderMap},
+        StatusCode, Url, Version,
+    },
+    uuid::Uuid,
+};
+
+// a request that was sent and the response it got
+pub struct Exchange<'a> {
+    pub url: &'a Url,
+    pub date: DateTime<Utc>,
+    pub request_headers: &'a HeaderMap,
+    pub version: Version,
+    pub status: StatusCode,
+    pub response_headers: &'a HeaderMap,
+    pub body: &'a [u8],
+}
+
+pub struct Warc {
+    out: Vec<u8>,
+}
+
+fn record_id() -> String {
+    format!("<urn:uuid:{}>", Uuid::new_v4())
+}
+
+fn write_headers(block: &mut String, headers: &HeaderMap) {
+    for (name, value) in headers {
+        if let Ok(value) = value.to_str() {
+            let _ = write!(block, "{name}: {value}\r\n");
+        }
+    }
+}
+
+impl Warc {
+    // starts the file with a record describing where it came from
+    pub fn new(filename: &str) -> Self {
+        let mut warc = Self { out: Vec::new() };
+        let fields = format!(
+            "software: link_backlog/{}\r\nformat: WARC File Format 1.1\r\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        warc.record(
+            &[
+                ("WARC-Type", "warcinfo"),
+                ("WARC-Record-ID", &record_id()),
+                (
+                    "WARC-Date",
+                    &Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                ),
+                ("WARC-Filename", filename),
+                ("Content-Type", "application/warc-fields"),
+            ],
+            fields.as_bytes(),
+        );
+        warc
+    }
+
+    fn record(&mut self, fields: &[(&str, &str)], block: &[u8]) {
+        let mut head = String::from("WARC/1.1\r\n");
+        for (name, value) in fields {
+            let _ = write!(head, "{name}: {value}\r\n");
+        }
+        let _ = write!(head, "Content-Length: {}\r\n\r\n", block.len());
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        // writing into memory doesn't fail
+        let _ = encoder.write_all(head.as_bytes());
+        let _ = encoder.write_all(block);
+        let _ = encoder.write_all(b"\r\n\r\n");
+        if let Ok(compressed) = encoder.finish() {
+            self.out.extend(compressed);
+        }
+    }
+
+    // adds the request as it was sent and the response as it came back, the
+    // body is stored decompressed and without chunking, so the headers that
+    // described those are left out
+    pub fn exchange(&mut self, exchange: Exchange) {
+        let date = exchange.date.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+        let response_id = record_id();
+
+        let mut path = exchange.url.path().to_string();
+        if let Some(query) = exchange.url.query() {
+            path = format!("{path}?{query}");
+        }
+        let host = match (exchange.url.host_str(), exchange.url.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (host, _) => host.unwrap_or_default().to_string(),
+        };
+        let mut request = format!("GET {path} HTTP/1.1\r\nHost: {host}\r\n");
+        write_headers(&mut request, exchange.request_headers);
+        request.push_str("\r\n");
+
+        let mut response_headers = exchange.response_headers.clone();
+        for name in [
+            header::TRANSFER_ENCODING,
+            header::CONTENT_ENCODING,
+            header::CONTENT_LENGTH,
+        ] {
+            response_headers.remove(name);
+        }
+        let mut response = format!(
+            "{:?} {} {}\r\n",
+            exchange.version,
+            exchange.status.as_u16(),
+            exchange.status.canonical_reason().unwrap_or_default()
+        );
+        write_headers(&mut response, &response_headers);
+        let _ = write!(response, "Content-Length: {}\r\n\r\n", exchange.body.len());
+        let mut response = response.into_bytes();
+        response.extend_from_slice(exchange.body);
+
+        self.record(
+            &[
+                ("WARC-Type", "response"),
+                ("WARC-Record-ID", &response_id),
+                ("WARC-Date", &date),
+                ("WARC-Target-URI", exchange.url.as_str()),
+                ("Content-Type", "application/http; msgtype=response"),
+            ],
+            &response,
+        );
+        self.record(
+            &[
+                ("WARC-Type", "request"),
+                ("WARC-Record-ID", &record_id()),
+                ("WARC-Date", &date),
+                ("WARC-Target-URI", exchange.url.as_str()),
+                ("WARC-Concurrent-To", &response_id),
+                ("Content-Type", "application/http; msgtype=request"),
+            ],
+            request.as_bytes(),
+        );
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.out
+    }
+}
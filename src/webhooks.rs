@@ -0,0 +1,339 @@
+//! Webhooks let other tools know when something happens to a user's links.
+//! Every event is kept as a delivery for each subscription that asked for it
+//! and handed to a job, which signs it and tries again later when the
+//! receiver doesn't take it.
+
+use std::{fmt, str::FromStr, sync::Arc, time::Duration};
+
+use {
+    anyhow::{anyhow, Error, Result},
+    chrono::Utc,
+    hmac::{Hmac, Mac},
+    reqwest::{StatusCode, Url},
+    sea_orm::{
+        entity::{prelude::*, Set},
+        DatabaseConnection, QuerySelect, QueryTrait,
+    },
+    serde::Serialize,
+    sha2::Sha256,
+    tracing::{info, warn},
+    ulid::Ulid,
+};
+
+use crate::{
+    api::links::{Link, LinkId},
+    database::entity::{links, tagged_links, tags, webhook_deliveries, webhooks},
+    jobs::{Job, Queue},
+    net::{Client, Guard},
+};
+
+pub type DeliveryId = Ulid;
+
+const TIMEOUT: u64 = 15;
+// the bytes of a receiver's reply kept with the delivery when it's refused
+const REPLY_LIMIT: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Saved,
+    Tagged,
+    Archived,
+    Deleted,
+}
+
+impl Event {
+    pub const ALL: [Event; 4] = [Event::Saved, Event::Tagged, Event::Archived, Event::Deleted];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Event::Saved => "link.saved",
+            Event::Tagged => "link.tagged",
+            Event::Archived => "link.archived",
+            Event::Deleted => "link.deleted",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Event {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "link.saved" => Ok(Event::Saved),
+            "link.tagged" => Ok(Event::Tagged),
+            "link.archived" => Ok(Event::Archived),
+            "link.deleted" => Ok(Event::Deleted),
+            _ => Err(anyhow!("unknown webhook event: {s}")),
+        }
+    }
+}
+
+impl Serialize for Event {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+// what the receiver gets as the body of the request
+#[derive(Serialize)]
+struct Payload<'a> {
+    id: DeliveryId,
+    event: Event,
+    date_created: DateTimeWithTimeZone,
+    link: &'a Link,
+    // the tags that were just put on the link
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+}
+
+// the events a subscription asked for are kept comma separated
+pub fn events(subscription: &webhooks::Model) -> Vec<Event> {
+    subscription
+        .events
+        .split(',')
+        .filter_map(|e| e.trim().parse().ok())
+        .collect()
+}
+
+// proves to the receiver that the request came from here and wasn't changed
+// on the way, the timestamp is signed along with the body so an old request
+// can't be sent again later
+pub fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any length");
+    mac.update(format!("{timestamp}.{body}").as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// queues a delivery of the event for every subscription of the link's owner
+// that asked for it
+pub async fn emit(
+    dbconn: &DatabaseConnection,
+    queue: &Queue,
+    event: Event,
+    link: &Link,
+    tags: &[String],
+) -> Result<()> {
+    let subscriptions = webhooks::Entity::find()
+        .filter(webhooks::Column::CreatedBy.eq(link.created_by.clone()))
+        .filter(webhooks::Column::DeletedAt.is_null())
+        .all(dbconn)
+        .await?;
+
+    for subscription in subscriptions {
+        if !events(&subscription).contains(&event) {
+            continue;
+        }
+
+        let id = Ulid::new();
+        let now: DateTimeWithTimeZone = Utc::now().into();
+        let payload = serde_json::to_string(&Payload {
+            id,
+            event,
+            date_created: now,
+            link,
+            tags,
+        })?;
+        webhook_deliveries::Entity::insert(webhook_deliveries::ActiveModel {
+            id: Set(id.to_string()),
+            webhook_id: Set(subscription.id),
+            event: Set(event.to_string()),
+            payload: Set(payload),
+            attempts: Set(0),
+            status_code: Set(None),
+            last_error: Set(None),
+            date_created: Set(now),
+            delivered_at: Set(None),
+        })
+        .exec(dbconn)
+        .await?;
+        queue.push(Job::DeliverWebhook { delivery_id: id }).await?;
+    }
+
+    Ok(())
+}
+
+// the saved event for links that came in some other way than being submitted
+// one at a time, like imports, restores and emails, along with the tags they
+// were filed under, a link is still saved so failing to queue it is only logged
+pub async fn emit_saved(dbconn: &DatabaseConnection, queue: &Queue, link_ids: &[LinkId]) {
+    for link_id in link_ids {
+        if let Err(e) = emit_one_saved(dbconn, queue, link_id).await {
+            warn!("webhooks for saved link {link_id} couldn't be queued: {e}");
+        }
+    }
+}
+
+async fn emit_one_saved(
+    dbconn: &DatabaseConnection,
+    queue: &Queue,
+    link_id: &LinkId,
+) -> Result<()> {
+    let link = match links::Entity::find_by_id(link_id.to_string())
+        .filter(links::Column::DeletedAt.is_null())
+        .one(dbconn)
+        .await?
+    {
+        Some(link) => Link::from_inactive_der(link)?,
+        None => return Ok(()),
+    };
+    let tags: Vec<String> = tags::Entity::find()
+        .filter(
+            tags::Column::Id.in_subquery(
+                tagged_links::Entity::find()
+                    .select_only()
+                    .column(tagged_links::Column::TagId)
+                    .filter(tagged_links::Column::LinkId.eq(link_id.to_string()))
+                    .filter(tagged_links::Column::DeletedAt.is_null())
+                    .into_query(),
+            ),
+        )
+        .filter(tags::Column::DeletedAt.is_null())
+        .all(dbconn)
+        .await?
+        .into_iter()
+        .map(|tag| tag.name)
+        .collect();
+
+    emit(dbconn, queue, Event::Saved, &link, &tags).await
+}
+
+pub struct Webhooks {
+    client: Client,
+    dbconn: Arc<DatabaseConnection>,
+}
+
+impl Webhooks {
+    pub fn new(guard: &Arc<Guard>, dbconn: Arc<DatabaseConnection>) -> Result<Self> {
+        // a redirect would turn the request into a get and lose the body
+        let client = guard.client(Duration::from_secs(TIMEOUT), 0)?;
+
+        Ok(Self { client, dbconn })
+    }
+
+    // hands the delivery over, the receiver's status code comes back along
+    // with the start of it's reply
+    async fn post(
+        &self,
+        subscription: &webhooks::Model,
+        delivery: &webhook_deliveries::Model,
+    ) -> Result<(StatusCode, String)> {
+        let url: Url = subscription.url.parse()?;
+        let timestamp = Utc::now().timestamp();
+        // the address was checked when the webhook was made, but what it
+        // points to can have changed since
+        let mut resp = self
+            .client
+            .post(url)?
+            .header("Content-Type", "application/json")
+            .header("X-Backlog-Event", &delivery.event)
+            .header("X-Backlog-Delivery", &delivery.id)
+            .header("X-Backlog-Timestamp", timestamp.to_string())
+            .header(
+                "X-Backlog-Signature",
+                signature(&subscription.secret, timestamp, &delivery.payload),
+            )
+            .body(delivery.payload.clone())
+            .send()
+            .await?;
+
+        // only as much of the reply as is kept is read
+        let status = resp.status();
+        let mut reply = Vec::new();
+        while reply.len() < REPLY_LIMIT {
+            match resp.chunk().await {
+                Ok(Some(chunk)) => reply.extend_from_slice(&chunk),
+                _ => break,
+            }
+        }
+        reply.truncate(REPLY_LIMIT);
+        Ok((status, String::from_utf8_lossy(&reply).trim().to_string()))
+    }
+
+    // sends the delivery to the receiver, how it went is kept with it and the
+    // job tries again later when it didn't work out
+    pub async fn deliver(&self, id: &DeliveryId) -> Result<()> {
+        let delivery = match webhook_deliveries::Entity::find_by_id(id.to_string())
+            .one(self.dbconn.as_ref())
+            .await?
+        {
+            Some(delivery) if delivery.delivered_at.is_none() => delivery,
+            _ => return Ok(()),
+        };
+        // a subscription that was removed since doesn't get anything anymore
+        let subscription = match webhooks::Entity::find_by_id(delivery.webhook_id.clone())
+            .filter(webhooks::Column::DeletedAt.is_null())
+            .one(self.dbconn.as_ref())
+            .await?
+        {
+            Some(subscription) => subscription,
+            None => return Ok(()),
+        };
+
+        let result = match self.post(&subscription, &delivery).await {
+            Ok((status, _)) if status.is_success() => Ok(status),
+            Ok((status, reply)) => {
+                Err((Some(status), anyhow!("receiver replied {status}: {reply}")))
+            }
+            Err(e) => Err((None, e)),
+        };
+
+        let attempts = delivery.attempts + 1;
+        let mut der: webhook_deliveries::ActiveModel = delivery.into();
+        der.attempts = Set(attempts);
+        let result = match result {
+            Ok(status) => {
+                der.status_code = Set(Some(status.as_u16() as i16));
+                der.last_error = Set(None);
+                der.delivered_at = Set(Some(Utc::now().into()));
+                info!("delivered webhook {id} to {}", subscription.url);
+                Ok(())
+            }
+            Err((status, e)) => {
+                warn!(
+                    "delivering webhook {id} to {} failed: {e:#}",
+                    subscription.url
+                );
+                der.status_code = Set(status.map(|s| s.as_u16() as i16));
+                der.last_error = Set(Some(format!("{e:#}")));
+                Err(e)
+            }
+        };
+        der.update(self.dbconn.as_ref()).await?;
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &str = r#"{"event":"link.saved"}"#;
+
+    #[test]
+    fn signs_the_timestamp_and_body() {
+        // what a receiver computes with any hmac library
+        assert_eq!(
+            signature("whsec_test", 1_650_000_000, BODY),
+            "sha256=67641bae1faca796fcfec5d22fef67cdb4786e8beec21ac352393f4b143c71f8"
+        );
+    }
+
+    #[test]
+    fn signature_changes_with_everything_signed() {
+        let signed = signature("whsec_test", 1_650_000_000, BODY);
+        assert_ne!(signature("whsec_other", 1_650_000_000, BODY), signed);
+        assert_ne!(signature("whsec_test", 1_650_000_001, BODY), signed);
+        assert_ne!(
+            signature("whsec_test", 1_650_000_000, r#"{"event":"link.deleted"}"#),
+            signed
+        );
+    }
+}